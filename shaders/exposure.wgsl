@@ -0,0 +1,95 @@
+// Автоэкспозиция по гистограмме log2 яркости HDR кадра, два прохода:
+// build_histogram - по потоку на пиксель, average - одна группа из потока на корзину.
+// average считает среднюю яркость, сдвигает к ней экспозицию и очищает гистограмму
+
+//Раскладка как у hdr::ExposureParams
+struct Params {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    target_log_luminance: f32,
+    adaptation: f32,
+    pixel_count: u32,
+}
+
+//Раскладка как у hdr::ExposureState
+struct Exposure {
+    ev: f32,
+    average_log_luminance: f32,
+}
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(2)
+var<storage, read_write> exposure: Exposure;
+@group(0) @binding(3)
+var<uniform> params: Params;
+
+const BINS: u32 = 256u;
+
+var<workgroup> local_histogram: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<f32, 256>;
+
+//Нулевая корзина - почти чёрные пиксели, остальные равномерно по log2 яркости
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let log_luminance = log2(luminance);
+    if luminance <= 0.0 || log_luminance < params.min_log_luminance {
+        return 0u;
+    }
+
+    let position = clamp((log_luminance - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(position * f32(BINS - 2u)) + 1u;
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_histogram[index], 0u);
+    workgroupBarrier();
+
+    //Группы на краю кадра выходят за текстуру
+    let size = vec2<u32>(textureDimensions(t_hdr));
+    if all(id.xy < size) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_histogram[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&local_histogram[index]));
+}
+
+@compute @workgroup_size(256)
+fn average(@builtin(local_invocation_index) index: u32) {
+    let count = atomicLoad(&histogram[index]);
+    weighted[index] = f32(count) * f32(index);
+    atomicStore(&histogram[index], 0u);
+    workgroupBarrier();
+
+    //Сумма по корзинам сдваиванием
+    for (var stride = BINS / 2u; stride > 0u; stride = stride / 2u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        //count нулевого потока - число чёрных пикселей
+        let lit = max(f32(params.pixel_count) - f32(count), 1.0);
+        //Середина корзины: корзины 1..BINS-1 покрывают весь диапазон
+        let average_bin = weighted[0] / lit;
+        let position = (average_bin - 0.5) / f32(BINS - 2u);
+        let log_luminance = position * params.log_luminance_range + params.min_log_luminance;
+
+        //Совсем чёрный кадр экспозицию не трогает
+        if f32(params.pixel_count) > f32(count) {
+            let ev = params.target_log_luminance - log_luminance;
+            exposure.ev = mix(exposure.ev, ev, params.adaptation);
+            exposure.average_log_luminance = log_luminance;
+        }
+    }
+}
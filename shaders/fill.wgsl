@@ -0,0 +1,14 @@
+// Заливка всего viewport одним цветом: один треугольник, накрывающий экран.
+// Цвет приходит через blend constant, поэтому ни буферов, ни bind групп не нужно
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    //(-1, -1), (3, -1), (-1, 3)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
@@ -0,0 +1,354 @@
+struct CameraUniform {
+   view_proj: mat4x4<f32>,
+   view_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+//Раскладка как у lights::LightRaw
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    //Куда светит
+    direction: vec3<f32>,
+    //0 - без ограничения
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+    spot_scale: f32,
+    spot_offset: f32,
+    //Номер в shadows.casters, -1 - без тени
+    shadow_map: i32,
+    _padding: f32,
+}
+
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light>,
+}
+
+@group(0) @binding(1)
+var<storage, read> lights: Lights;
+
+//Раскладка как у shadows::ShadowUniform
+struct Shadows {
+    view_proj: array<mat4x4<f32>, 8>,
+    //x - первый слой источника, y - число его слоёв (каскадов)
+    casters: array<vec4<u32>, 4>,
+    //Дальние границы каскадов по глубине основной камеры
+    cascade_splits: vec4<f32>,
+    camera_position: vec3<f32>,
+    texel_size: f32,
+    camera_forward: vec3<f32>,
+    pcf_radius: u32,
+    count: u32,
+    layers: u32,
+    debug_cascades: u32,
+    _padding: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> shadows: Shadows;
+@group(0) @binding(3)
+var t_shadow: texture_depth_2d_array;
+@group(0) @binding(4)
+var s_shadow: sampler_comparison;
+
+//Раскладка как у model::MaterialUniform
+struct MaterialUniform {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    alpha_cutoff: f32,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(1) @binding(1)
+var t_base_color: texture_2d<f32>;
+@group(1) @binding(2)
+var s_base_color: sampler;
+//g - roughness, b - metallic
+@group(1) @binding(3)
+var t_metallic_roughness: texture_2d<f32>;
+@group(1) @binding(4)
+var s_metallic_roughness: sampler;
+@group(1) @binding(5)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(6)
+var s_normal: sampler;
+//r - затенение рассеянного света
+@group(1) @binding(7)
+var t_occlusion: texture_2d<f32>;
+@group(1) @binding(8)
+var s_occlusion: sampler;
+@group(1) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(1) @binding(10)
+var s_emissive: sampler;
+
+const PI: f32 = 3.14159265359;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    //w - знак битангенса
+    @location(3) tangent: vec4<f32>,
+}
+
+//Матрица модели экземпляра по столбцам
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
+};
+
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    //Без обратной транспонированной: при неравномерном масштабе нормали слегка искажаются
+    let normal_matrix = mat3x3<f32>(
+        model_matrix[0].xyz,
+        model_matrix[1].xyz,
+        model_matrix[2].xyz,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = vec4<f32>(normal_matrix * model.tangent.xyz, model.tangent.w);
+    out.clip_position = camera.view_proj * world_position;
+
+    return out;
+}
+
+
+//Нормаль из normal map в мировых координатах
+fn surface_normal(in: VertexOutput, sampled: vec3<f32>) -> vec3<f32> {
+    let n = normalize(in.world_normal);
+    //После интерполяции касательная уже не перпендикулярна нормали
+    let t = in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz);
+    if length(t) < 1e-5 {
+        return n;
+    }
+    let tangent = normalize(t);
+    let bitangent = cross(n, tangent) * in.world_tangent.w;
+
+    let mapped = sampled * 2.0 - 1.0;
+    let scaled = vec3<f32>(mapped.xy * material.normal_scale, mapped.z);
+
+    return normalize(mat3x3<f32>(tangent, bitangent, n) * scaled);
+}
+
+//GGX / Trowbridge-Reitz, alpha = roughness^2
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+//Smith со Schlick-GGX, k для прямого света
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+//Направление на источник и сколько света от него доходит до точки
+struct IncomingLight {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+fn incoming_light(light: Light, position: vec3<f32>) -> IncomingLight {
+    var out: IncomingLight;
+    if light.kind == LIGHT_DIRECTIONAL {
+        out.direction = -normalize(light.direction);
+        out.radiance = light.color * light.intensity;
+        return out;
+    }
+
+    let to_light = light.position - position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    out.direction = to_light * inverseSqrt(distance2);
+
+    //Обратный квадрат с плавным обнулением к range, как советует KHR_lights_punctual
+    var attenuation = 1.0 / distance2;
+    if light.range > 0.0 {
+        let ratio2 = distance2 / (light.range * light.range);
+        let window = clamp(1.0 - ratio2 * ratio2, 0.0, 1.0);
+        attenuation *= window * window;
+    }
+    if light.kind == LIGHT_SPOT {
+        let cone = clamp(dot(normalize(light.direction), -out.direction) * light.spot_scale + light.spot_offset, 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+
+    out.radiance = light.color * light.intensity * attenuation;
+    return out;
+}
+
+//Цвета каскадов для отладки: красный, зелёный, синий, жёлтый
+fn cascade_color(cascade: u32) -> vec3<f32> {
+    switch cascade {
+        case 0u: { return vec3<f32>(1.0, 0.3, 0.3); }
+        case 1u: { return vec3<f32>(0.3, 1.0, 0.3); }
+        case 2u: { return vec3<f32>(0.3, 0.3, 1.0); }
+        default: { return vec3<f32>(1.0, 1.0, 0.3); }
+    }
+}
+
+//Первый каскад, чья дальняя граница не ближе точки. У прожектора один слой - всегда 0
+fn select_cascade(cascades: u32, position: vec3<f32>) -> u32 {
+    let depth = dot(position - shadows.camera_position, shadows.camera_forward);
+    var cascade = 0u;
+    while cascade + 1u < cascades && depth > shadows.cascade_splits[cascade] {
+        cascade += 1u;
+    }
+    return cascade;
+}
+
+//Доля света, дошедшая до точки мимо теней: среднее сравнений глубины по ядру PCF
+fn shadow_factor(caster: i32, position: vec3<f32>) -> f32 {
+    if caster < 0 || u32(caster) >= shadows.count {
+        return 1.0;
+    }
+    let info = shadows.casters[caster];
+
+    //Выбранный по глубине каскад может не накрыть точку, если её видит не основная камера,
+    //тогда подходит следующий, более крупный
+    for (var cascade = select_cascade(info.y, position); cascade < info.y; cascade += 1u) {
+        let layer = i32(info.x + cascade);
+        let clip = shadows.view_proj[layer] * vec4<f32>(position, 1.0);
+        let ndc = clip.xyz / clip.w;
+        if clip.w > 0.0 && all(abs(ndc.xy) <= vec2<f32>(1.0)) && ndc.z <= 1.0 {
+            return pcf(layer, ndc);
+        }
+    }
+
+    //За пределами карты тени нет
+    return 1.0;
+}
+
+fn pcf(layer: i32, ndc: vec3<f32>) -> f32 {
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+    let radius = i32(shadows.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadows.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, layer, ndc.z);
+        }
+    }
+
+    let side = f32(2 * radius + 1);
+    return lit / (side * side);
+}
+
+//Cook-Torrance: отражённый свет от одного источника
+fn direct_light(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    incoming: IncomingLight,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let l = incoming.direction;
+    let h = normalize(v + l);
+
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let v_dot_h = max(dot(v, h), 0.0);
+
+    //Диэлектрики отражают около 4%, металлы - своим цветом и без рассеянной составляющей
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let fresnel = fresnel_schlick(v_dot_h, f0);
+    let specular = distribution_ggx(n_dot_h, roughness * roughness)
+        * geometry_smith(n_dot_v, n_dot_l, roughness)
+        * fresnel / (4.0 * n_dot_v * n_dot_l + 1e-4);
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color / PI;
+
+    return (diffuse + specular) * incoming.radiance * n_dot_l;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color = textureSample(t_base_color, s_base_color, in.tex_coords) * material.base_color_factor;
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    let sampled_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz;
+    let occlusion = textureSample(t_occlusion, s_occlusion, in.tex_coords).r;
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).rgb * material.emissive_factor;
+
+    if base_color.a < material.alpha_cutoff {
+        discard;
+    }
+
+    let metallic = clamp(metallic_roughness.b * material.metallic_factor, 0.0, 1.0);
+    //Совсем гладкая поверхность дала бы бесконечно узкий блик
+    let roughness = clamp(metallic_roughness.g * material.roughness_factor, 0.04, 1.0);
+
+    let n = surface_normal(in, sampled_normal);
+    let v = normalize(camera.view_position.xyz - in.world_position);
+
+    var direct = vec3<f32>(0.0);
+    //Каскады первого направленного источника с тенью, если включена отладка
+    var cascade_tint = vec3<f32>(1.0);
+    var tinted = shadows.debug_cascades == 0u;
+    let count = min(lights.count, arrayLength(&lights.lights));
+    for (var i = 0u; i < count; i += 1u) {
+        let light = lights.lights[i];
+        let incoming = incoming_light(light, in.world_position);
+        let shadow = shadow_factor(light.shadow_map, in.world_position);
+        direct += direct_light(n, v, incoming, base_color.rgb, metallic, roughness) * shadow;
+
+        let caster = light.shadow_map;
+        if !tinted && light.kind == LIGHT_DIRECTIONAL && caster >= 0 && u32(caster) < shadows.count {
+            cascade_tint = cascade_color(select_cascade(shadows.casters[caster].y, in.world_position));
+            tinted = true;
+        }
+    }
+
+    //Окклюзия глушит только рассеянный свет
+    let ambient_occlusion = mix(1.0, occlusion, material.occlusion_strength);
+    let ambient = lights.ambient * base_color.rgb * ambient_occlusion;
+
+    return vec4<f32>((direct + ambient + emissive) * cascade_tint, base_color.a);
+}
@@ -2,7 +2,7 @@ struct CameraUniform {
    view_proj: mat4x4<f32>,
 }
 
-@group(1) @binding(0)
+@group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
 struct VertexInput {
@@ -22,7 +22,7 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    out.color = model.color
+    out.color = model.color;
     out.clip_position =  camera.view_proj * vec4<f32>(model.position, 1.0);
 
     return out;
@@ -0,0 +1,72 @@
+// Глубина сцены с точки зрения источника света, в слой карты теней. Цвета нет,
+// фрагментный шейдер нужен только чтобы alpha MASK материалы давали тень по своей форме
+
+//Матрица источника для этого слоя, shadows::ShadowUniform::view_proj
+struct ShadowLayer {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> layer: ShadowLayer;
+
+//Раскладка как у model::MaterialUniform, нужен только alpha cutoff
+struct MaterialUniform {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    alpha_cutoff: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(1) @binding(1)
+var t_base_color: texture_2d<f32>;
+@group(1) @binding(2)
+var s_base_color: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+//Матрица модели экземпляра по столбцам
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = layer.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+
+@fragment
+fn fs_main(in: VertexOutput) {
+    //Без MASK alpha_cutoff = 0 и ничего не отбрасывается
+    let alpha = textureSample(t_base_color, s_base_color, in.tex_coords).a * material.base_color_factor.a;
+    if alpha < material.alpha_cutoff {
+        discard;
+    }
+}
@@ -0,0 +1,47 @@
+// Слой карты теней в оттенках серого поверх кадра: чёрный - у источника, белый - дальняя плоскость.
+// Один треугольник на весь viewport, номер слоя приходит через instance_index.
+// textureLoad из глубины не поддерживает GL, поэтому глубина собирается из сравнений с порогами
+
+@group(0) @binding(3)
+var t_shadow: texture_depth_2d_array;
+@group(0) @binding(4)
+var s_shadow: sampler_comparison;
+
+//Градаций серого
+const STEPS: i32 = 32;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) layer: u32,
+) -> VertexOutput {
+    //(0, 0), (2, 0), (0, 2)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    //v вниз, как строки текстуры
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.layer = layer;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    //Сравнение даёт 1, пока порог не дальше записанной глубины
+    var depth = 0.0;
+    for (var step = 0; step < STEPS; step += 1) {
+        let threshold = (f32(step) + 0.5) / f32(STEPS);
+        depth += textureSampleCompareLevel(t_shadow, s_shadow, in.uv, i32(in.layer), threshold);
+    }
+    depth /= f32(STEPS);
+
+    return vec4<f32>(depth, depth, depth, 1.0);
+}
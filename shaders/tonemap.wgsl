@@ -0,0 +1,93 @@
+// HDR кадр на поверхность: умножение на экспозицию и тональная компрессия в [0, 1].
+// Пишет линейный цвет, в sRGB его переводит формат поверхности. Один треугольник на весь экран
+
+//Раскладка как у hdr::ExposureState
+struct Exposure {
+    ev: f32,
+    average_log_luminance: f32,
+}
+
+//Раскладка как у hdr::TonemapUniform, tone_mapping - номер hdr::ToneMapping
+struct Settings {
+    tone_mapping: u32,
+}
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read> exposure: Exposure;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    //(-1, -1), (3, -1), (-1, 3)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+//Narkowicz, "ACES Filmic Tone Mapping Curve"
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp(color * (a * color + b) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+//Полином 6 степени, приближающий кривую контраста AgX по умолчанию
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+//AgX: сжатие гаммы, log2 кодирование, кривая контраста и обратно в линейный цвет
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let encoded = clamp((log2(max(inset * color, vec3<f32>(1e-10))) - min_ev) / (max_ev - min_ev), vec3<f32>(0.0), vec3<f32>(1.0));
+    let display = outset * agx_contrast(encoded);
+    //Кривая даёт цвет для дисплея с гаммой 2.2
+    return pow(clamp(display, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    //HDR цель того же размера, что и поверхность: пиксель в пиксель
+    let hdr = textureLoad(t_hdr, vec2<i32>(position.xy), 0).rgb;
+    let color = max(hdr, vec3<f32>(0.0)) * exp2(exposure.ev);
+
+    //Порядок как в hdr::ToneMapping, 0 - без компрессии
+    var mapped: vec3<f32>;
+    switch settings.tone_mapping {
+        case 1u: { mapped = reinhard(color); }
+        case 2u: { mapped = aces(color); }
+        case 3u: { mapped = agx(color); }
+        default: { mapped = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)); }
+    }
+
+    return vec4<f32>(mapped, 1.0);
+}
@@ -1,17 +1,43 @@
 use std::f32::consts::FRAC_PI_2;
-use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, Buffer}; use winit::{ event::*, };
-use crate::vmath::{Vector3, Matrix4x4};
+use wgpu::{util::DeviceExt, Buffer};
+use crate::vmath::{Vector3, Matrix4x4, Frustum, Aabb};
+use crate::player::{Level, Player, PlayerInput, PlayerSettings};
+use crate::input::Input;
+use crate::gamepad::GamepadOutput;
+use crate::projection::{Projection, ProjectionKind};
 
-#[derive(Debug, PartialEq)]
-enum CameraMode {
+const ORBIT_MIN_DISTANCE: f32 = 0.1;
+const ORBIT_MAX_DISTANCE: f32 = 500.0;
+//Во сколько раз меняется расстояние за одну строку колеса мыши
+const ORBIT_ZOOM_STEP: f32 = 0.9;
+//Сдвиг цели за пиксель мыши в долях расстояния до неё
+const ORBIT_PAN_SPEED: f32 = 0.002;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CameraMode {
     Player,
     Free,
+    //Вращение вокруг точки, для осмотра моделей
+    Orbit,
+}
+
+#[derive(Debug)]
+struct Orbit {
+    target: Vector3<f32>,
+    distance: f32,
+    //Левая кнопка зажата - вращаем, средняя - двигаем цель
+    rotating: bool,
+    panning: bool,
+    pan_x: f32,
+    pan_y: f32,
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    //w не используется, нужен для выравнивания
+    view_position: [f32; 4],
 }
 
 #[derive(Debug)]
@@ -20,6 +46,9 @@ pub struct CameraMovement {
     backward: f32,
     left: f32,
     right: f32,
+    jump: bool,
+    crouch: bool,
+    run: bool,
 }
 
 #[derive(Debug)]
@@ -27,9 +56,7 @@ pub struct Camera {
     pub uniform: CameraUniform,
     position: Vector3<f32>,
     target: Vector3<f32>,
-    fov: f32,
-    width: f32, 
-    height: f32,
+    pub projection: Projection,
     movement: CameraMovement,
     speed: f32,
     rotate_x: f32,
@@ -38,27 +65,39 @@ pub struct Camera {
     yaw: f32,
     pitch: f32,
     mode: CameraMode,
+    orbit: Orbit,
+    player: Player,
     //buffer: Option<wgpu::Buffer>,
     //bind_group: Option<wgpu::BindGroup>,
 }
 
 impl Camera {
-    pub fn new(position: Vector3<f32>, target: Vector3<f32>, fov: f32, width: f32, height: f32) -> Self {
+    pub fn new(position: Vector3<f32>, target: Vector3<f32>, projection: Projection) -> Self {
         Self {
             uniform: CameraUniform {
                 view_proj: Matrix4x4::new_indent().into(),
+                view_position: [position.x, position.y, position.z, 1.0],
             },
             position,
             target,
-            fov,
-            width,
-            height,
+            projection,
             movement: CameraMovement {
                 forward: 0.0,
                 backward: 0.0,
-                right: 0.0, left: 0.0, }, speed: 10.0, rotate_x: 0.0, rotate_y: 0.0, yaw: 0.0, pitch: 0.0, sensitivity: 0.4,
-            mode: CameraMode::Free
-
+                right: 0.0, left: 0.0, jump: false, crouch: false, run: false, }, speed: 10.0, rotate_x: 0.0, rotate_y: 0.0, yaw: 0.0, pitch: 0.0, sensitivity: 0.4,
+            mode: CameraMode::Free,
+            orbit: Orbit {
+                target: position + target.normalize() * 5.0,
+                distance: 5.0,
+                rotating: false,
+                panning: false,
+                pan_x: 0.0,
+                pan_y: 0.0,
+            },
+            player: Player::new(
+                position - Vector3::unit_y() * PlayerSettings::default().eye_height,
+                PlayerSettings::default(),
+            ),
         }
     }
 
@@ -66,24 +105,23 @@ impl Camera {
 
         //println!("yaw: {:?}, pitch: {:?}", self.yaw.to_degrees(), self.pitch.to_degrees());
 
-        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
-        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
-        self.target = Vector3::new(yaw_sin * pitch_cos, -pitch_sin, pitch_cos * yaw_cos).normalize(); 
+        self.target = Self::direction(self.yaw, self.pitch);
 
-        let right = Vector3::unit_y().cross(self.target) * (self.movement.right - self.movement.left);
-        let mut forward = self.target * (self.movement.forward - self.movement.backward);
-        if self.mode == CameraMode::Player {
-            forward.y = 0.0;
-        }
+        if self.mode == CameraMode::Orbit {
+            self.update_orbit();
+        } else if self.mode == CameraMode::Player {
+            //Игрок двигается в step_player
+            self.position = self.player.eye_position();
+        } else {
+            let right = Vector3::unit_y().cross(self.target) * (self.movement.right - self.movement.left);
+            let forward = self.target * (self.movement.forward - self.movement.backward);
 
-        self.position = self.position + (right + forward) * self.speed * delta_time.as_secs_f32();
+            self.position = self.position + (right + forward) * self.speed * delta_time.as_secs_f32();
+        }
 
         let view = Matrix4x4::new_look_at(self.position, self.target);
-        let proj = Matrix4x4::new_perspective(
-            self.width, self.height, 0.1, 1000.0, self.fov
-        );
-
-        self.uniform.view_proj = (proj * view).into();
+        self.uniform.view_proj = (self.projection.matrix() * view).into();
+        self.uniform.view_position = [self.position.x, self.position.y, self.position.z, 1.0];
 
         self.yaw += self.rotate_x * self.sensitivity * delta_time.as_secs_f32();
         self.pitch += self.rotate_y * self.sensitivity * delta_time.as_secs_f32();
@@ -102,6 +140,114 @@ impl Camera {
         
     }
 
+    fn direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = pitch.sin_cos();
+        Vector3::new(yaw_sin * pitch_cos, -pitch_sin, pitch_cos * yaw_cos).normalize()
+    }
+
+    //Позиция всегда на расстоянии distance от цели против направления взгляда
+    fn update_orbit(&mut self) {
+        let right = Vector3::unit_y().cross(self.target).normalize();
+        let up = self.target.cross(right);
+        let pan_scale = self.orbit.distance * ORBIT_PAN_SPEED;
+
+        //Цель едет против движения мыши, чтобы сцена двигалась вслед за курсором
+        self.orbit.target = self.orbit.target
+            - right * self.orbit.pan_x * pan_scale
+            + up * self.orbit.pan_y * pan_scale;
+        self.orbit.pan_x = 0.0;
+        self.orbit.pan_y = 0.0;
+
+        self.position = self.orbit.target - self.target * self.orbit.distance;
+    }
+
+    //Физика игрока в режиме Player, вызывается перед update
+    pub fn step_player(&mut self, delta_time: instant::Duration, level: &Level) {
+        if self.mode != CameraMode::Player {
+            return;
+        }
+
+        let input = PlayerInput {
+            forward: self.movement.forward - self.movement.backward,
+            right: self.movement.right - self.movement.left,
+            jump: self.movement.jump,
+            crouch: self.movement.crouch,
+            run: self.movement.run,
+        };
+        self.player.update(delta_time, self.yaw, &input, level);
+    }
+
+    pub fn player_settings_mut(&mut self) -> &mut PlayerSettings {
+        &mut self.player.settings
+    }
+
+    pub fn pose(&self) -> (Vector3<f32>, f32, f32) {
+        (self.position, self.yaw, self.pitch)
+    }
+
+    pub fn set_pose(&mut self, position: Vector3<f32>, yaw: f32, pitch: f32) {
+        self.position = position;
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    //Позиция и направление взгляда сохраняются, поэтому вид не прыгает:
+    //при входе в Orbit цель ставится перед камерой на текущем расстоянии орбиты
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.orbit.target = self.position + Self::direction(self.yaw, self.pitch) * self.orbit.distance;
+        }
+        if mode == CameraMode::Player && self.mode != CameraMode::Player {
+            self.player.crouched = false;
+            self.player.teleport(self.position - Vector3::unit_y() * self.player.settings.eye_height);
+        }
+        self.orbit.rotating = false;
+        self.orbit.panning = false;
+        self.mode = mode;
+    }
+
+    //Ставит цель орбиты в центр бокса и отъезжает так, чтобы описанная сфера влезла в кадр.
+    //Направление взгляда не меняется
+    pub fn frame(&mut self, bounds: Aabb<f32>) {
+        let radius = bounds.extents().length().max(ORBIT_MIN_DISTANCE);
+        self.orbit.target = bounds.center();
+
+        match &mut self.projection.kind {
+            ProjectionKind::Perspective | ProjectionKind::PerspectiveReversedZ => {
+                let half_fovy = (self.projection.fovy * 0.5).to_radians();
+                let half_fovx = (half_fovy.tan() * self.projection.aspect).atan();
+                self.orbit.distance = (radius / half_fovy.min(half_fovx).sin())
+                    .clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+            },
+            //Ортографической камере расстояние не важно, важен размер вида
+            ProjectionKind::Orthographic { height } => {
+                *height = 2.0 * radius / self.projection.aspect.min(1.0);
+                self.orbit.distance = (radius * 2.0).clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+            },
+        }
+
+        self.position = self.orbit.target - Self::direction(self.yaw, self.pitch) * self.orbit.distance;
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.projection.resize(width, height);
+    }
+
+    //Проекция на вид последнего update
+    pub fn view_proj(&self) -> Matrix4x4<f32> {
+        self.uniform.view_proj.into()
+    }
+
+    //Пирамида видимости по view_proj последнего update
+    pub fn frustum(&self) -> Frustum<f32> {
+        Frustum::from_view_proj(self.uniform.view_proj.into())
+    }
+
     pub fn TEST_get_view_proj_matrix_buffer(&mut self, device: &wgpu::Device) -> Buffer {
         device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -112,73 +258,252 @@ impl Camera {
         )
     }
 
-    pub fn get_camera_bind_groups(&mut self, device: &wgpu::Device) -> (
-        BindGroupLayout,
-        BindGroup,
-        Buffer,
-    ) {
-        let buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Camera Buffer"),
-                contents: bytemuck::cast_slice(&[self.uniform]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            }
-        );
-
-        let bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }
-                ],
-                label: Some("camera_bind_group_layout"),
-            }
-        );
+    //Движение и режимы из действий ввода за кадр, вызывается перед update
+    pub fn input(&mut self, input: &Input) {
+        let held = |action: &str| if input.pressed(action) { 1.0 } else { 0.0 };
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }
-            ],
-            label: Some("camera_bind_group"),
-        });
+        self.movement.forward = held("move_forward");
+        self.movement.backward = held("move_backward");
+        self.movement.left = held("move_left");
+        self.movement.right = held("move_right");
+        self.movement.jump = input.pressed("jump");
+        self.movement.crouch = input.pressed("crouch");
+        self.movement.run = input.pressed("run");
 
-        (bind_group_layout, bind_group, buffer)
+        if input.just_pressed("switch_mode") {
+            self.set_mode(match self.mode {
+                CameraMode::Free => CameraMode::Player,
+                CameraMode::Player => CameraMode::Orbit,
+                CameraMode::Orbit => CameraMode::Free,
+            });
+        }
+
+        self.orbit.rotating = input.pressed("orbit_rotate");
+        self.orbit.panning = input.pressed("orbit_pan");
+        self.mouse_events(input.axis("look_x"), input.axis("look_y"));
+        self.scroll_events(input.axis("zoom"));
     }
 
-    pub fn keyboard_events(&mut self, input: &KeyboardInput) {
-        let offset: f32 = if input.state == ElementState::Pressed { 1.0 } else { 0.0 };
+    //Геймпад дополняет клавиатуру и мышь, вызывается после input
+    pub fn gamepad(&mut self, gamepad: &GamepadOutput) {
+        let movement = &mut self.movement;
+        movement.forward = movement.forward.max(gamepad.forward);
+        movement.backward = movement.backward.max(-gamepad.forward);
+        movement.right = movement.right.max(gamepad.right);
+        movement.left = movement.left.max(-gamepad.right);
 
-        match input.virtual_keycode {
-            Some(VirtualKeyCode::A | VirtualKeyCode::Left) => {
-                self.movement.left = offset;      
-            },
-            Some(VirtualKeyCode::D | VirtualKeyCode::Right) => {
-                self.movement.right = offset;  
-            },
-            Some(VirtualKeyCode::W | VirtualKeyCode::Up) => {
-                self.movement.forward = offset;    
-            },
-            Some(VirtualKeyCode::S | VirtualKeyCode::Down) => {
-                self.movement.backward = offset;    
-            },
-            _ => {}
-        }
+        //Курки меняют скорость полёта, в режиме игрока правый курок - бег
+        movement.forward *= gamepad.speed_scale;
+        movement.backward *= gamepad.speed_scale;
+        movement.right *= gamepad.speed_scale;
+        movement.left *= gamepad.speed_scale;
+        movement.run |= gamepad.speed_scale > 1.0;
+
+        //update умножает поворот на sensitivity и время кадра, look_* уже в радианах в секунду
+        self.rotate_x += gamepad.look_x / self.sensitivity;
+        self.rotate_y += gamepad.look_y / self.sensitivity;
     }
 
     pub fn mouse_events(&mut self, delta_x: f32, delta_y: f32,) {
+        //В орбите мышь работает только с зажатой кнопкой
+        if self.mode == CameraMode::Orbit {
+            if self.orbit.panning {
+                self.orbit.pan_x += delta_x;
+                self.orbit.pan_y += delta_y;
+                return;
+            }
+            if !self.orbit.rotating {
+                return;
+            }
+        }
+
         self.rotate_x = delta_x;
         self.rotate_y = delta_y;
     }
+
+    //delta в строках колеса, положительная - приблизиться
+    pub fn scroll_events(&mut self, delta: f32) {
+        if self.mode == CameraMode::Orbit {
+            self.orbit.distance = (self.orbit.distance * ORBIT_ZOOM_STEP.powf(delta))
+                .clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{ElementState, MouseButton};
+    use crate::input::Binding;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn input() -> Input {
+        Input::from_config(crate::input::DEFAULT_CONFIG).unwrap()
+    }
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new(Vector3::new(1.0, 2.0, -3.0), Vector3::unit_z(), Projection::new_perspective(60.0, 160, 120, 0.1, 1000.0));
+        camera.yaw = 0.3;
+        camera.pitch = -0.2;
+        camera.update(instant::Duration::default());
+        camera
+    }
+
+    fn assert_close(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) {
+        for (a, b) in a.iter().flatten().zip(b.iter().flatten()) {
+            assert!((a - b).abs() < EPSILON, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn mode_switch_keeps_view() {
+        let mut camera = camera();
+        let view_proj = camera.uniform.view_proj;
+
+        for mode in [CameraMode::Orbit, CameraMode::Free, CameraMode::Orbit, CameraMode::Player] {
+            camera.set_mode(mode);
+            camera.update(instant::Duration::default());
+            assert_close(camera.uniform.view_proj, view_proj);
+        }
+    }
+
+    #[test]
+    fn orbit_rotates_around_target() {
+        let mut camera = camera();
+        camera.set_mode(CameraMode::Orbit);
+        let target = camera.orbit.target;
+
+        //Без зажатой кнопки мышь ничего не делает
+        let mut input = input();
+        input.mouse_motion(100.0, 0.0);
+        camera.input(&input);
+        camera.update(instant::Duration::from_secs(1));
+        camera.update(instant::Duration::from_secs(1));
+        assert!((camera.yaw - 0.3).abs() < EPSILON);
+        input.end_frame();
+
+        input.button(Binding::MouseButton(MouseButton::Left), ElementState::Pressed);
+        input.mouse_motion(1.0, 0.5);
+        camera.input(&input);
+        camera.update(instant::Duration::from_secs(1));
+        camera.update(instant::Duration::default());
+
+        assert!((camera.yaw - 0.3).abs() > EPSILON);
+        assert!(((camera.position - target).length() - camera.orbit.distance).abs() < EPSILON);
+        assert!((camera.orbit.target - target).length() < EPSILON);
+    }
+
+    #[test]
+    fn orbit_zoom_and_pan() {
+        let mut camera = camera();
+        camera.set_mode(CameraMode::Orbit);
+        let distance = camera.orbit.distance;
+
+        let mut input = input();
+        input.mouse_wheel(2.0);
+        camera.input(&input);
+        assert!((camera.orbit.distance - distance * ORBIT_ZOOM_STEP * ORBIT_ZOOM_STEP).abs() < EPSILON);
+        camera.scroll_events(1000.0);
+        assert_eq!(camera.orbit.distance, ORBIT_MIN_DISTANCE);
+        input.end_frame();
+
+        //Панорамирование двигает цель в плоскости экрана, не меняя направления взгляда
+        let target = camera.orbit.target;
+        input.button(Binding::MouseButton(MouseButton::Middle), ElementState::Pressed);
+        input.mouse_motion(50.0, -20.0);
+        camera.input(&input);
+        camera.update(instant::Duration::default());
+        let offset = camera.orbit.target - target;
+        assert!(offset.length() > 0.0);
+        assert!(offset.dot(camera.target).abs() < EPSILON);
+        assert!((camera.yaw - 0.3).abs() < EPSILON);
+    }
+
+    #[test]
+    fn player_mode_walks_on_level() {
+        let level = Level::new(Some(crate::vmath::Plane::new(Vector3::unit_y(), 0.0)));
+        let mut camera = Camera::new(Vector3::new(0.0, 1.7, 0.0), Vector3::unit_z(), Projection::new_perspective(60.0, 160, 120, 0.1, 1000.0));
+        camera.set_mode(CameraMode::Player);
+
+        let mut input = input();
+        input.key(Some(winit::event::VirtualKeyCode::W), 17, ElementState::Pressed);
+        camera.input(&input);
+        for _ in 0..60 {
+            camera.step_player(instant::Duration::from_millis(16), &level);
+            camera.update(instant::Duration::from_millis(16));
+        }
+
+        assert!((camera.position.y - 1.7).abs() < 1e-2, "{:?}", camera.position);
+        assert!(camera.position.z > 3.0);
+    }
+
+    #[test]
+    fn gamepad_moves_and_looks() {
+        use crate::gamepad::{GamepadSettings, GamepadState};
+        use crate::vmath::Vector2;
+
+        let settings = GamepadSettings::default();
+        let mut camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::unit_z(), Projection::new_perspective(60.0, 160, 120, 0.1, 1000.0));
+        camera.update(instant::Duration::default());
+
+        let state = GamepadState {
+            left_stick: Vector2::new(0.0, 1.0),
+            right_stick: Vector2::new(1.0, 0.0),
+            right_trigger: 1.0,
+            ..Default::default()
+        };
+        camera.input(&input());
+        camera.gamepad(&settings.output(&state));
+        camera.update(instant::Duration::from_millis(500));
+
+        //Скорость 10 с ускорением курком в 2 раза за полсекунды
+        assert!((camera.position.z - 10.0).abs() < 1e-3, "{:?}", camera.position);
+        //Поворот на look_speed * 0.5 радиан, без кривой на краю стика
+        assert!((camera.yaw - settings.look_speed * 0.5).abs() < 1e-3, "{}", camera.yaw);
+
+        //Отпущенный стик ничего не двигает
+        camera.input(&input());
+        camera.gamepad(&settings.output(&GamepadState::default()));
+        let position = camera.position;
+        camera.update(instant::Duration::from_millis(500));
+        assert_eq!(camera.position, position);
+    }
+
+    #[test]
+    fn resize_keeps_aspect() {
+        let mut camera = camera();
+        camera.resize(400, 100);
+        camera.update(instant::Duration::default());
+
+        //Точка на краю кадра по x при aspect 4 отстоит от центра в 4 раза дальше, чем по y
+        let view_proj: Matrix4x4<f32> = camera.uniform.view_proj.into();
+        let (position, yaw, pitch) = camera.pose();
+        let forward = Camera::direction(yaw, pitch);
+        let right = Vector3::unit_y().cross(forward).normalize();
+        let up = forward.cross(right);
+        let ndc = |point: Vector3<f32>| {
+            let clip = view_proj * point.extend(1.0);
+            (clip.x / clip.w, clip.y / clip.w)
+        };
+        let center = position + forward * 10.0;
+        let (x, _) = ndc(center + right);
+        let (_, y) = ndc(center + up);
+        assert!((y / x - 4.0).abs() < 1e-3, "{} {}", x, y);
+    }
+
+    #[test]
+    fn frame_fits_bounds() {
+        let mut camera = camera();
+        camera.set_mode(CameraMode::Orbit);
+        let bounds = Aabb::new(Vector3::new(10.0, -1.0, 4.0), Vector3::new(14.0, 3.0, 6.0));
+
+        camera.frame(bounds);
+        camera.update(instant::Duration::default());
+
+        assert!((camera.orbit.target - bounds.center()).length() < EPSILON);
+        let frustum = camera.frustum();
+        let corners = [bounds.min, bounds.max, Vector3::new(bounds.min.x, bounds.max.y, bounds.min.z)];
+        assert!(corners.iter().all(|&corner| frustum.contains(corner)));
+    }
 }
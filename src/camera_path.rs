@@ -0,0 +1,297 @@
+/*
+    Запись и воспроизведение пролёта камеры: позиция, yaw и pitch с отметкой времени.
+    Между ключами позиция интерполируется сплайном Catmull-Rom, ориентация - slerp.
+    Файл текстовый, одна строка на ключ: time x y z yaw pitch
+*/
+
+use std::{fs, path::Path};
+use anyhow::anyhow;
+
+use crate::vmath::{Quaternion, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    //Секунды от начала записи
+    pub time: f32,
+    pub position: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+// Пишет ключ не чаще раза в interval секунд
+#[derive(Debug)]
+pub struct PathRecorder {
+    pub path: CameraPath,
+    interval: instant::Duration,
+    //Время копится целыми наносекундами, чтобы шаг ключей не плыл
+    time: instant::Duration,
+    last: instant::Duration,
+}
+
+#[derive(Debug)]
+pub struct PathPlayer {
+    path: CameraPath,
+    time: instant::Duration,
+    pub looping: bool,
+}
+
+impl CameraPath {
+    #[cfg(test)]
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    //Время ключей должно расти, ключ из прошлого отбрасывается
+    pub fn push(&mut self, keyframe: Keyframe) {
+        if self.keyframes.last().is_none_or(|last| keyframe.time > last.time) {
+            self.keyframes.push(keyframe);
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |last| last.time)
+    }
+
+    //None для пустого пути, вне записи - крайние ключи
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+
+        //Первый ключ с временем больше time
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return Some(Keyframe { time, ..keyframes[0] });
+        }
+        if next > last {
+            return Some(Keyframe { time, ..keyframes[last] });
+        }
+
+        let (k1, k2) = (&keyframes[next - 1], &keyframes[next]);
+        //На концах соседний ключ повторяется
+        let k0 = &keyframes[next.saturating_sub(2)];
+        let k3 = &keyframes[(next + 1).min(last)];
+        let t = (time - k1.time) / (k2.time - k1.time);
+
+        let orientation = Quaternion::from_euler(k1.yaw, k1.pitch, 0.0)
+            .slerp(Quaternion::from_euler(k2.yaw, k2.pitch, 0.0), t);
+        let (yaw, pitch, _) = orientation.to_euler();
+
+        Some(Keyframe {
+            time,
+            position: catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            yaw,
+            pitch,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let mut text = String::from("# time x y z yaw pitch\n");
+        for keyframe in &self.keyframes {
+            let position = keyframe.position;
+            text += &format!(
+                "{} {} {} {} {} {}\n",
+                keyframe.time, position.x, position.y, position.z, keyframe.yaw, keyframe.pitch
+            );
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|err| anyhow!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut path = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line.split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| anyhow!("line {}: {}", number + 1, err))?;
+            let [time, x, y, z, yaw, pitch] = values[..] else {
+                return Err(anyhow!("line {}: expected 6 numbers, got {}", number + 1, values.len()));
+            };
+
+            if path.keyframes.last().is_some_and(|last| time <= last.time) {
+                return Err(anyhow!("line {}: time {} doesn't increase", number + 1, time));
+            }
+            path.push(Keyframe { time, position: Vector3::new(x, y, z), yaw, pitch });
+        }
+
+        Ok(path)
+    }
+}
+
+fn catmull_rom(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, t: f32) -> Vector3<f32> {
+    let (t2, t3) = (t * t, t * t * t);
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+impl PathRecorder {
+    pub fn new(interval: instant::Duration) -> Self {
+        Self {
+            path: CameraPath::default(),
+            interval,
+            time: instant::Duration::ZERO,
+            last: instant::Duration::ZERO,
+        }
+    }
+
+    //Первый кадр записи всегда становится ключом с временем 0
+    pub fn record(&mut self, delta_time: instant::Duration, position: Vector3<f32>, yaw: f32, pitch: f32) {
+        if !self.path.keyframes.is_empty() {
+            self.time += delta_time;
+            if self.time - self.last < self.interval {
+                return;
+            }
+        }
+
+        self.last = self.time;
+        self.path.push(Keyframe { time: self.time.as_secs_f32(), position, yaw, pitch });
+    }
+
+    pub fn finish(self) -> CameraPath {
+        self.path
+    }
+}
+
+impl PathPlayer {
+    pub fn new(path: CameraPath, looping: bool) -> Self {
+        Self { path, time: instant::Duration::ZERO, looping }
+    }
+
+    //Продвигает время и возвращает ключ для кадра, None - путь кончился
+    pub fn advance(&mut self, delta_time: instant::Duration) -> Option<Keyframe> {
+        let duration = self.path.duration();
+        if self.time.as_secs_f32() > duration {
+            if !self.looping || duration <= 0.0 {
+                return None;
+            }
+            self.time = instant::Duration::from_secs_f32(self.time.as_secs_f32() % duration);
+        }
+
+        let keyframe = self.path.sample(self.time.as_secs_f32());
+        self.time += delta_time;
+        keyframe
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn keyframe(time: f32, x: f32, yaw: f32) -> Keyframe {
+        Keyframe { time, position: Vector3::new(x, 0.0, 0.0), yaw, pitch: 0.0 }
+    }
+
+    fn path(keyframes: &[Keyframe]) -> CameraPath {
+        let mut path = CameraPath::default();
+        keyframes.iter().for_each(|&keyframe| path.push(keyframe));
+        path
+    }
+
+    #[test]
+    fn sample_passes_through_keyframes() {
+        let path = path(&[keyframe(0.0, 0.0, 0.0), keyframe(1.0, 1.0, 0.5), keyframe(2.0, 4.0, 1.0), keyframe(3.0, 9.0, 0.0)]);
+
+        for keyframe in path.keyframes() {
+            let sample = path.sample(keyframe.time).unwrap();
+            assert!((sample.position - keyframe.position).length() < EPSILON);
+            assert!((sample.yaw - keyframe.yaw).abs() < EPSILON);
+        }
+
+        //До начала и после конца - крайние ключи
+        assert_eq!(path.sample(-1.0).unwrap().position, path.keyframes()[0].position);
+        assert_eq!(path.sample(10.0).unwrap().position, path.keyframes()[3].position);
+        assert!(CameraPath::default().sample(0.0).is_none());
+    }
+
+    #[test]
+    fn catmull_rom_is_smooth() {
+        //Равномерное движение по прямой сплайн не искажает
+        let line = path(&[keyframe(0.0, 0.0, 0.0), keyframe(1.0, 1.0, 0.0), keyframe(2.0, 2.0, 0.0), keyframe(3.0, 3.0, 0.0)]);
+        assert!((line.sample(1.5).unwrap().position.x - 1.5).abs() < EPSILON);
+
+        //Скорость непрерывна на ключе
+        let curve = path(&[keyframe(0.0, 0.0, 0.0), keyframe(1.0, 1.0, 0.0), keyframe(2.0, 4.0, 0.0), keyframe(3.0, 9.0, 0.0)]);
+        let h = 1e-2;
+        let x = |t: f32| curve.sample(t).unwrap().position.x;
+        let before = (x(1.0) - x(1.0 - h)) / h;
+        let after = (x(1.0 + h) - x(1.0)) / h;
+        assert!((before - after).abs() < 0.1, "{} vs {}", before, after);
+    }
+
+    #[test]
+    fn orientation_slerps() {
+        let path = path(&[keyframe(0.0, 0.0, 0.0), keyframe(1.0, 0.0, 1.0)]);
+        assert!((path.sample(0.5).unwrap().yaw - 0.5).abs() < EPSILON);
+
+        //Через -pi/pi по короткой дуге
+        let path = self::path(&[keyframe(0.0, 0.0, 3.0), keyframe(1.0, 0.0, -3.0)]);
+        let yaw = path.sample(0.5).unwrap().yaw;
+        assert!((yaw.abs() - std::f32::consts::PI).abs() < 1e-3, "{}", yaw);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = path(&[keyframe(0.0, 1.5, 0.25), keyframe(0.5, -2.0, 1.0)]);
+        let file = std::env::temp_dir().join(format!("ebenya_camera_path_{}.txt", std::process::id()));
+
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(loaded, path);
+
+        assert!(CameraPath::parse("0 1 2 3 4").is_err());
+        assert!(CameraPath::parse("1 0 0 0 0 0\n0.5 0 0 0 0 0").is_err());
+        assert!(CameraPath::parse("0 0 0 0 0 x").is_err());
+    }
+
+    #[test]
+    fn record_and_play() {
+        let mut recorder = PathRecorder::new(instant::Duration::from_millis(100));
+        for frame in 0..30 {
+            recorder.record(instant::Duration::from_millis(50), Vector3::new(frame as f32, 0.0, 0.0), 0.0, 0.0);
+        }
+        let path = recorder.finish();
+        //Кадры по 50 мс, ключи раз в 100 мс
+        assert_eq!(path.keyframes().len(), 15);
+        assert_eq!(path.keyframes()[0].position.x, 0.0);
+
+        let mut player = PathPlayer::new(path.clone(), false);
+        let mut frames = 0;
+        while player.advance(instant::Duration::from_millis(100)).is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, (path.duration() / 0.1).round() as usize + 1);
+
+        let mut looping = PathPlayer::new(path.clone(), true);
+        for _ in 0..100 {
+            assert!(looping.advance(instant::Duration::from_millis(100)).is_some());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+/*
+    Геймпад для камеры: левый стик - движение, правый - обзор, курки - скорость.
+    Сырые значения стиков проходят через мёртвую зону и кривую отклика.
+    Железо читается через gilrs только с фичей `gamepad`, без неё (и в тестах)
+    состояние подставляется вручную через set_state, снаружи - через Renderer::gamepad_mut.
+*/
+
+use crate::vmath::Vector2;
+
+// Сырые значения: стики в [-1, 1] (y вверх), курки в [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamepadState {
+    pub left_stick: Vector2<f32>,
+    pub right_stick: Vector2<f32>,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    //value^exponent, больше 1 - точнее у центра стика
+    Power(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamepadSettings {
+    //Радиальные мёртвые зоны стика: внутри inner - ноль, после outer - максимум
+    pub inner_dead_zone: f32,
+    pub outer_dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    pub move_curve: ResponseCurve,
+    pub look_curve: ResponseCurve,
+    //Радианы в секунду при полностью отклонённом правом стике
+    pub look_speed: f32,
+    pub invert_y: bool,
+    //Множители скорости при полностью зажатых левом и правом курках
+    pub slow_factor: f32,
+    pub fast_factor: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            inner_dead_zone: 0.15,
+            outer_dead_zone: 0.95,
+            trigger_dead_zone: 0.05,
+            move_curve: ResponseCurve::Linear,
+            look_curve: ResponseCurve::Power(2.0),
+            look_speed: 2.5,
+            invert_y: false,
+            slow_factor: 0.25,
+            fast_factor: 2.0,
+        }
+    }
+}
+
+// Что камера получает от геймпада за кадр
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GamepadOutput {
+    //Движение вперёд/вправо в [-1, 1]
+    pub forward: f32,
+    pub right: f32,
+    //Скорость поворота в радианах в секунду
+    pub look_x: f32,
+    pub look_y: f32,
+    pub speed_scale: f32,
+}
+
+pub struct Gamepad {
+    pub settings: GamepadSettings,
+    state: GamepadState,
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        Self {
+            left_stick: Vector2::zero(),
+            right_stick: Vector2::zero(),
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+        }
+    }
+}
+
+impl ResponseCurve {
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Power(exponent) => value.signum() * value.abs().powf(exponent),
+        }
+    }
+}
+
+impl GamepadSettings {
+    //Длина результата в [0, 1], направление стика сохраняется
+    pub fn stick(&self, stick: Vector2<f32>, curve: ResponseCurve) -> Vector2<f32> {
+        let magnitude = stick.length();
+        if magnitude <= self.inner_dead_zone {
+            return Vector2::zero();
+        }
+
+        let scaled = ((magnitude - self.inner_dead_zone) / (self.outer_dead_zone - self.inner_dead_zone)).min(1.0);
+        stick / magnitude * curve.apply(scaled)
+    }
+
+    pub fn trigger(&self, value: f32) -> f32 {
+        if value <= self.trigger_dead_zone {
+            return 0.0;
+        }
+
+        ((value - self.trigger_dead_zone) / (1.0 - self.trigger_dead_zone)).min(1.0)
+    }
+
+    pub fn output(&self, state: &GamepadState) -> GamepadOutput {
+        let movement = self.stick(state.left_stick, self.move_curve);
+        let look = self.stick(state.right_stick, self.look_curve);
+        let invert = if self.invert_y { -1.0 } else { 1.0 };
+
+        //Курки тянут скорость в разные стороны от 1.0
+        let speed_scale = 1.0
+            + (self.fast_factor - 1.0) * self.trigger(state.right_trigger)
+            + (self.slow_factor - 1.0) * self.trigger(state.left_trigger);
+
+        GamepadOutput {
+            forward: movement.y,
+            right: movement.x,
+            look_x: look.x * self.look_speed,
+            //Стик вверх - смотреть вверх, а pitch растёт вниз
+            look_y: -look.y * self.look_speed * invert,
+            speed_scale: speed_scale.max(0.0),
+        }
+    }
+}
+
+impl Gamepad {
+    pub fn new(settings: GamepadSettings) -> Self {
+        Self {
+            settings,
+            state: GamepadState::default(),
+            #[cfg(feature = "gamepad")]
+            gilrs: match gilrs::Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(err) => {
+                    log::warn!("gamepads are unavailable: {}", err);
+                    None
+                }
+            },
+        }
+    }
+
+    pub fn state(&self) -> GamepadState {
+        self.state
+    }
+
+    //Виртуальный геймпад: состояние задаётся снаружи, например в тестах или CI без железа.
+    //С фичей `gamepad` подключённый геймпад перезапишет его в следующем poll
+    pub fn set_state(&mut self, state: GamepadState) {
+        self.state = state;
+    }
+
+    //Читает первый подключённый геймпад. Без фичи `gamepad` состояние не меняется
+    pub fn poll(&mut self) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &mut self.gilrs {
+            use gilrs::{Axis, Button};
+
+            //События нужно вычитывать, иначе gilrs не обновит состояние
+            while gilrs.next_event().is_some() {}
+
+            self.state = match gilrs.gamepads().next() {
+                Some((_, gamepad)) => {
+                    let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+                    GamepadState {
+                        left_stick: Vector2::new(gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY)),
+                        right_stick: Vector2::new(gamepad.value(Axis::RightStickX), gamepad.value(Axis::RightStickY)),
+                        left_trigger: trigger(Button::LeftTrigger2),
+                        right_trigger: trigger(Button::RightTrigger2),
+                    }
+                },
+                None => GamepadState::default(),
+            };
+        }
+    }
+
+    pub fn output(&self) -> GamepadOutput {
+        self.settings.output(&self.state)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    #[test]
+    fn dead_zones() {
+        let settings = GamepadSettings::default();
+
+        //Дрейф стика внутри мёртвой зоны, в том числе по диагонали
+        assert_eq!(settings.stick(Vector2::new(0.1, 0.1), ResponseCurve::Linear), Vector2::zero());
+        //Сразу за внутренней зоной - почти ноль, без скачка
+        let edge = settings.stick(Vector2::new(0.16, 0.0), ResponseCurve::Linear);
+        assert!(edge.x > 0.0 && edge.x < 0.02);
+        //После внешней - ровно 1, направление сохраняется
+        let full = settings.stick(Vector2::new(-0.7, 0.7), ResponseCurve::Linear);
+        assert!((full.length() - 1.0).abs() < EPSILON);
+        assert!((full.x + full.y).abs() < EPSILON && full.x < 0.0);
+
+        assert_eq!(settings.trigger(0.03), 0.0);
+        assert_eq!(settings.trigger(1.0), 1.0);
+    }
+
+    #[test]
+    fn response_curves() {
+        assert_eq!(ResponseCurve::Linear.apply(0.5), 0.5);
+        assert!((ResponseCurve::Power(2.0).apply(0.5) - 0.25).abs() < EPSILON);
+        assert!((ResponseCurve::Power(3.0).apply(-0.5) + 0.125).abs() < EPSILON);
+        assert_eq!(ResponseCurve::Power(2.0).apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn output_mapping() {
+        let mut settings = GamepadSettings::default();
+        let state = GamepadState {
+            left_stick: Vector2::new(0.0, 1.0),
+            right_stick: Vector2::new(1.0, 1.0),
+            left_trigger: 0.0,
+            right_trigger: 1.0,
+        };
+
+        let output = settings.output(&state);
+        assert!((output.forward - 1.0).abs() < EPSILON && output.right.abs() < EPSILON);
+        assert!(output.look_x > 0.0 && output.look_y < 0.0);
+        assert!((output.speed_scale - settings.fast_factor).abs() < EPSILON);
+
+        settings.invert_y = true;
+        let inverted = settings.output(&state);
+        assert_eq!(inverted.look_y, -output.look_y);
+
+        let slow = settings.output(&GamepadState { left_trigger: 1.0, ..Default::default() });
+        assert!((slow.speed_scale - settings.slow_factor).abs() < EPSILON);
+        assert_eq!(slow.forward, 0.0);
+    }
+
+    #[test]
+    fn virtual_gamepad() {
+        let mut gamepad = Gamepad::new(GamepadSettings::default());
+        assert_eq!(gamepad.output().speed_scale, 1.0);
+
+        let state = GamepadState { left_stick: Vector2::new(1.0, 0.0), ..Default::default() };
+        gamepad.set_state(state);
+        assert_eq!(gamepad.state(), state);
+        assert!((gamepad.output().right - 1.0).abs() < EPSILON);
+    }
+}
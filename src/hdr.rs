@@ -0,0 +1,268 @@
+/*
+    HDR: сцена рисуется в Texture::HDR_FORMAT без ограничения яркости, в конце кадра
+    TonemapPipeline умножает её на экспозицию и сжимает в [0, 1] поверхности.
+    Экспозиция задаётся вручную в EV или подбирается по гистограмме яркости кадра
+    (ExposurePipeline, shaders/exposure.wgsl) и плавно догоняет её.
+*/
+
+// Корзин гистограммы яркости, по потоку рабочей группы на корзину
+pub const HISTOGRAM_BINS: usize = 256;
+//Диапазон гистограммы в log2 яркости. Всё темнее попадает в нулевую корзину и не учитывается
+pub const MIN_LOG_LUMINANCE: f32 = -10.0;
+pub const MAX_LOG_LUMINANCE: f32 = 6.0;
+//Средняя яркость кадра приводится к 18% серого
+const MIDDLE_GRAY: f32 = 0.18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapping {
+    //Просто обрезка по единице, для отладки
+    None,
+    Reinhard,
+    //Аппроксимация ACES filmic от Narkowicz
+    #[default]
+    Aces,
+    //AgX с кривой контраста по умолчанию
+    AgX,
+}
+
+impl ToneMapping {
+    //Следующий по кругу, для переключения клавишей
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapping::None => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::AgX,
+            ToneMapping::AgX => ToneMapping::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    //Яркость умножается на 2^ev
+    Manual { ev: f32 },
+    //compensation сдвигает подобранную экспозицию в EV,
+    //speed - за сколько обратных секунд экспозиция догоняет кадр
+    Auto { compensation: f32, speed: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrSettings {
+    pub tone_mapping: ToneMapping,
+    pub exposure: Exposure,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            tone_mapping: ToneMapping::default(),
+            exposure: Exposure::Auto { compensation: 0.0, speed: 1.5 },
+        }
+    }
+}
+
+// Раскладка совпадает с Exposure в shaders/exposure.wgsl и shaders/tonemap.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ExposureState {
+    ev: f32,
+    //Средняя log2 яркость последнего кадра, по ней считается ev
+    average_log_luminance: f32,
+    _padding: [f32; 2],
+}
+
+impl ExposureState {
+    pub fn new(ev: f32) -> Self {
+        Self {
+            ev,
+            average_log_luminance: 0.0,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+// Раскладка совпадает с Params в shaders/exposure.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ExposureParams {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    //log2 яркости, к которой приводится средняя, с учётом compensation
+    target_log_luminance: f32,
+    //Какую часть пути к новой экспозиции пройти за кадр: 1 - сразу
+    adaptation: f32,
+    pixel_count: u32,
+    _padding: [u32; 3],
+}
+
+impl ExposureParams {
+    pub fn new(compensation: f32, speed: f32, delta_time: instant::Duration, pixel_count: u32) -> Self {
+        Self {
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE,
+            target_log_luminance: MIDDLE_GRAY.log2() + compensation,
+            adaptation: (1.0 - (-delta_time.as_secs_f32() * speed.max(0.0)).exp()).clamp(0.0, 1.0),
+            pixel_count,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// Раскладка совпадает с Settings в shaders/tonemap.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TonemapUniform {
+    tone_mapping: u32,
+    _padding: [u32; 3],
+}
+
+impl TonemapUniform {
+    pub fn new(tone_mapping: ToneMapping) -> Self {
+        Self {
+            tone_mapping: tone_mapping as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{ExposurePipeline, TonemapPipeline};
+    use crate::{texture, with_test_adapter, Renderer};
+
+    //f16 для заливки HDR текстуры: точные значения степеней двойки
+    fn half(value: f32) -> u16 {
+        match value {
+            v if v == 0.0 => 0x0000,
+            v => {
+                let exponent = v.log2().round() as i32;
+                assert_eq!(v, (exponent as f32).exp2(), "only powers of two");
+                ((exponent + 15) as u16) << 10
+            },
+        }
+    }
+
+    fn device(test: &str) -> Option<(wgpu::Device, wgpu::Queue)> {
+        with_test_adapter(test, Renderer::request_headless_device)
+    }
+
+    //HDR цель width x 1, пиксели серые с яркостью из values
+    fn hdr_target(device: &wgpu::Device, queue: &wgpu::Queue, values: &[f32]) -> texture::Texture {
+        let target = texture::Texture::create_hdr_target(device, values.len() as u32, 1, "test_hdr_target");
+        let texels: Vec<u16> = values.iter()
+            .flat_map(|&value| [half(value), half(value), half(value), half(1.0)])
+            .collect();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(values.len() as u32 * 8),
+                rows_per_image: std::num::NonZeroU32::new(1),
+            },
+            target.texture.size(),
+        );
+
+        target
+    }
+
+    #[test]
+    fn adaptation_follows_delta_time() {
+        let step = |milliseconds, speed| ExposureParams::new(0.0, speed, instant::Duration::from_millis(milliseconds), 1).adaptation;
+
+        assert_eq!(step(16, 0.0), 0.0);
+        assert!(step(16, 1.5) < step(100, 1.5));
+        assert!((step(10_000, 1.5) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn auto_exposure_brings_average_to_middle_gray() {
+        let Some((device, queue)) = device("auto_exposure_brings_average_to_middle_gray") else { return };
+
+        //Половина кадра яркостью 4, половина - 1: средняя log2 яркость 1
+        let target = hdr_target(&device, &queue, &[4.0, 1.0, 4.0, 1.0, 0.0, 0.0]);
+        let exposure = ExposurePipeline::new(&device, ExposureState::new(0.0));
+        let bind_group = exposure.bind_group(&device, &target.view);
+        exposure.update(&queue, &ExposureParams::new(0.0, 1.0, instant::Duration::from_secs(100), 6));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        exposure.dispatch(&mut encoder, &bind_group, target.texture.size());
+        queue.submit(std::iter::once(encoder.finish()));
+
+        //Чёрные пиксели не в счёт, точность - ширина корзины
+        let state = exposure.read_state(&device, &queue).unwrap();
+        assert!((state.average_log_luminance - 1.0).abs() < 0.1, "{:?}", state);
+        assert!((state.ev - (MIDDLE_GRAY.log2() - 1.0)).abs() < 0.1, "{:?}", state);
+
+        //Гистограмма очищена под следующий кадр: второй проход даёт то же самое
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        exposure.dispatch(&mut encoder, &bind_group, target.texture.size());
+        queue.submit(std::iter::once(encoder.finish()));
+        assert_eq!(exposure.read_state(&device, &queue).unwrap(), state);
+    }
+
+    #[test]
+    fn tone_mapping_operators() {
+        let Some((device, queue)) = device("tone_mapping_operators") else { return };
+
+        let values = [0.0, 0.25, 1.0, 4.0, 64.0];
+        let target = hdr_target(&device, &queue, &values);
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let exposure = ExposurePipeline::new(&device, ExposureState::new(0.0));
+        let tonemap = TonemapPipeline::new(&device, format);
+        let bind_group = tonemap.bind_group(&device, &target.view, &exposure.state_buffer);
+
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("test_tonemap_output"),
+            size: target.texture.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+
+        for tone_mapping in [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::AgX] {
+            tonemap.update(&queue, tone_mapping);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &output_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::RED), store: true },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                tonemap.draw(&mut render_pass, &bind_group);
+            }
+            queue.submit(std::iter::once(encoder.finish()));
+
+            let image = texture::Texture::read_to_image(&device, &queue, &output).unwrap();
+            let pixels: Vec<u8> = image.pixels().map(|pixel| pixel.0[0]).collect();
+
+            //Чёрный остаётся чёрным, серый - серым, ярче - светлее, но не выше белого
+            assert!(pixels[0] <= 2, "{:?}: {:?}", tone_mapping, pixels);
+            let gray = |pixel: &image::Rgba<u8>| pixel.0[0].abs_diff(pixel.0[1]) <= 2 && pixel.0[1].abs_diff(pixel.0[2]) <= 2;
+            assert!(image.pixels().all(gray), "{:?}: {:?}", tone_mapping, image);
+            assert!(pixels.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}: {:?}", tone_mapping, pixels);
+            match tone_mapping {
+                ToneMapping::None => assert_eq!(pixels[2..], [255, 255, 255]),
+                //1 / (1 + 1) = 0.5 линейных, в sRGB это 188
+                ToneMapping::Reinhard => assert!(pixels[2].abs_diff(188) <= 1, "{:?}", pixels),
+                //Плёночные кривые не доводят до белого даже очень яркое
+                _ => assert!(pixels[4] > 240 && pixels[3] < pixels[4], "{:?}: {:?}", tone_mapping, pixels),
+            }
+        }
+    }
+}
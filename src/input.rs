@@ -0,0 +1,362 @@
+/*
+    Действия вместо клавиш: физические клавиши, кнопки и оси мыши привязываются
+    к именованным действиям (move_forward, look_x, quit, ...) в конфиге res/input.cfg.
+    События окна копятся за кадр, состояние действий опрашивается в update,
+    в конце кадра end_frame сбрасывает "только что нажатые" и дельты осей.
+*/
+
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+use anyhow::{anyhow, bail};
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+pub const DEFAULT_CONFIG: &str = include_str!("../res/input.cfg");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    ScanCode(u32),
+    MouseButton(MouseButton),
+    MouseX,
+    MouseY,
+    MouseWheel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseSettings {
+    pub sensitivity: f32,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self { sensitivity: 1.0, invert_x: false, invert_y: false }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Input {
+    bindings: HashMap<String, Vec<Binding>>,
+    pub mouse: MouseSettings,
+    //Зажатые сейчас кнопки
+    down: HashSet<Binding>,
+    //Нажатые и отпущенные за текущий кадр, даже если успели отпустить в том же кадре
+    went_down: HashSet<Binding>,
+    went_up: HashSet<Binding>,
+    mouse_delta: (f32, f32),
+    wheel_delta: f32,
+}
+
+impl Input {
+    //Читает конфиг, при ошибке пишет предупреждение и берёт привязки по умолчанию
+    pub fn load(path: &Path) -> Self {
+        let config = match fs::read_to_string(path) {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("can't read input config {}: {}, using defaults", path.display(), err);
+                return Self::from_config(DEFAULT_CONFIG).unwrap();
+            }
+        };
+
+        match Self::from_config(&config) {
+            Ok(input) => input,
+            Err(err) => {
+                log::warn!("{}: {}, using defaults", path.display(), err);
+                Self::from_config(DEFAULT_CONFIG).unwrap()
+            }
+        }
+    }
+
+    pub fn from_config(config: &str) -> Result<Self, anyhow::Error> {
+        let mut input = Self::default();
+        let mut section = "";
+
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| anyhow!("line {}: {}", number + 1, message);
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+
+            let (name, value) = line.split_once('=')
+                .ok_or_else(|| error(format!("expected `name = value`, got `{}`", line)))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            match section {
+                "bindings" => {
+                    let bindings = value.split(',')
+                        .map(|binding| parse_binding(binding.trim()))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| error(format!("{}: {}", name, err)))?;
+                    input.bindings.entry(name.to_string()).or_default().extend(bindings);
+                },
+                "mouse" => {
+                    let parse_bool = |value: &str| value.parse::<bool>()
+                        .map_err(|_| error(format!("{}: expected true or false, got `{}`", name, value)));
+                    match name {
+                        "sensitivity" => input.mouse.sensitivity = value.parse()
+                            .map_err(|_| error(format!("sensitivity: expected number, got `{}`", value)))?,
+                        "invert_x" => input.mouse.invert_x = parse_bool(value)?,
+                        "invert_y" => input.mouse.invert_y = parse_bool(value)?,
+                        _ => return Err(error(format!("unknown mouse setting `{}`", name))),
+                    }
+                },
+                _ => return Err(error(format!("`{}` outside of [bindings] or [mouse]", name))),
+            }
+        }
+
+        Ok(input)
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.bindings.entry(action.to_string()).or_default().push(binding);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.bindings.get(action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    //true если событие относится к вводу
+    pub fn process_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode, scancode, state, .. },
+                ..
+            } => self.key(*virtual_keycode, *scancode, *state),
+            WindowEvent::MouseInput { button, state, .. } => self.button(Binding::MouseButton(*button), *state),
+            WindowEvent::MouseWheel { delta, .. } => self.mouse_wheel(match delta {
+                MouseScrollDelta::LineDelta(_, y) => *y,
+                //Пиксели тачпада примерно переводим в строки
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+            }),
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn key(&mut self, keycode: Option<VirtualKeyCode>, scancode: u32, state: ElementState) {
+        if let Some(keycode) = keycode {
+            self.button(Binding::Key(keycode), state);
+        }
+        self.button(Binding::ScanCode(scancode), state);
+    }
+
+    pub fn button(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                //Автоповтор клавиши не считается новым нажатием
+                if self.down.insert(binding) {
+                    self.went_down.insert(binding);
+                }
+            },
+            ElementState::Released => {
+                if self.down.remove(&binding) {
+                    self.went_up.insert(binding);
+                }
+            },
+        }
+    }
+
+    pub fn mouse_motion(&mut self, delta_x: f32, delta_y: f32) {
+        self.mouse_delta.0 += delta_x;
+        self.mouse_delta.1 += delta_y;
+    }
+
+    pub fn mouse_wheel(&mut self, delta: f32) {
+        self.wheel_delta += delta;
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.down.contains(binding))
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.went_down.contains(binding))
+    }
+
+    pub fn released(&self, action: &str) -> bool {
+        !self.pressed(action) && self.bindings(action).iter().any(|binding| self.went_up.contains(binding))
+    }
+
+    //Сумма осей мыши за кадр (с чувствительностью и инверсией) и зажатых кнопок (по 1.0)
+    pub fn axis(&self, action: &str) -> f32 {
+        let sign = |invert: bool| if invert { -1.0 } else { 1.0 };
+
+        self.bindings(action).iter()
+            .map(|binding| match binding {
+                Binding::MouseX => self.mouse_delta.0 * self.mouse.sensitivity * sign(self.mouse.invert_x),
+                Binding::MouseY => self.mouse_delta.1 * self.mouse.sensitivity * sign(self.mouse.invert_y),
+                Binding::MouseWheel => self.wheel_delta,
+                binding if self.down.contains(binding) => 1.0,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    pub fn end_frame(&mut self) {
+        self.went_down.clear();
+        self.went_up.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.wheel_delta = 0.0;
+    }
+}
+
+fn parse_binding(binding: &str) -> Result<Binding, anyhow::Error> {
+    let (kind, name) = binding.split_once(':')
+        .ok_or_else(|| anyhow!("expected `kind:name`, got `{}`", binding))?;
+
+    Ok(match (kind.trim(), name.trim()) {
+        ("key", name) => Binding::Key(
+            key_from_name(name).ok_or_else(|| anyhow!("unknown key `{}`", name))?
+        ),
+        ("scancode", code) => Binding::ScanCode(
+            code.parse().map_err(|_| anyhow!("bad scancode `{}`", code))?
+        ),
+        ("mouse", "x") => Binding::MouseX,
+        ("mouse", "y") => Binding::MouseY,
+        ("mouse", "wheel") => Binding::MouseWheel,
+        ("mouse", "left") => Binding::MouseButton(MouseButton::Left),
+        ("mouse", "right") => Binding::MouseButton(MouseButton::Right),
+        ("mouse", "middle") => Binding::MouseButton(MouseButton::Middle),
+        ("mouse", button) => Binding::MouseButton(MouseButton::Other(
+            button.parse().map_err(|_| anyhow!("unknown mouse binding `{}`", button))?
+        )),
+        (kind, _) => bail!("unknown binding kind `{}`", kind),
+    })
+}
+
+//Имена клавиш совпадают с вариантами VirtualKeyCode
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down, Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+    LAlt, LControl, LShift, LWin, RAlt, RControl, RShift, RWin,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(input: &mut Input, key: VirtualKeyCode) {
+        input.key(Some(key), 0, ElementState::Pressed);
+    }
+
+    fn release(input: &mut Input, key: VirtualKeyCode) {
+        input.key(Some(key), 0, ElementState::Released);
+    }
+
+    #[test]
+    fn default_config_parses() {
+        let input = Input::from_config(DEFAULT_CONFIG).unwrap();
+
+        assert_eq!(input.bindings("move_forward"), &[Binding::Key(VirtualKeyCode::W), Binding::Key(VirtualKeyCode::Up)]);
+        assert_eq!(input.bindings("look_x"), &[Binding::MouseX]);
+        assert_eq!(input.bindings("quit"), &[Binding::Key(VirtualKeyCode::Escape)]);
+        assert_eq!(input.mouse, MouseSettings::default());
+        assert!(input.bindings("no_such_action").is_empty());
+    }
+
+    #[test]
+    fn config_errors_have_line_numbers() {
+        let err = Input::from_config("[bindings]\nquit = key:Esc").unwrap_err();
+        assert!(err.to_string().contains("line 2") && err.to_string().contains("Esc"), "{}", err);
+
+        assert!(Input::from_config("[bindings]\nquit key:Escape").is_err());
+        assert!(Input::from_config("quit = key:Escape").is_err());
+        assert!(Input::from_config("[mouse]\ninvert_y = yes").is_err());
+        assert!(Input::from_config("[bindings]\nfire = joystick:a").is_err());
+    }
+
+    #[test]
+    fn azerty_rebinding() {
+        //ZQSD на AZERTY по символам и по физическим клавишам
+        let mut input = Input::from_config("
+            [bindings]
+            move_forward = key:Z, scancode:17
+            move_left = key:Q
+        ").unwrap();
+
+        press(&mut input, VirtualKeyCode::Z);
+        assert!(input.pressed("move_forward"));
+        release(&mut input, VirtualKeyCode::Z);
+
+        input.key(None, 17, ElementState::Pressed);
+        assert!(input.pressed("move_forward"));
+        assert!(!input.pressed("move_left"));
+    }
+
+    #[test]
+    fn pressed_just_pressed_released() {
+        let mut input = Input::from_config(DEFAULT_CONFIG).unwrap();
+
+        press(&mut input, VirtualKeyCode::W);
+        assert!(input.pressed("move_forward") && input.just_pressed("move_forward"));
+        input.end_frame();
+
+        //Автоповтор
+        press(&mut input, VirtualKeyCode::W);
+        assert!(input.pressed("move_forward") && !input.just_pressed("move_forward"));
+
+        //Вторая привязка того же действия держит его нажатым
+        press(&mut input, VirtualKeyCode::Up);
+        release(&mut input, VirtualKeyCode::W);
+        assert!(input.pressed("move_forward") && !input.released("move_forward"));
+        release(&mut input, VirtualKeyCode::Up);
+        assert!(!input.pressed("move_forward") && input.released("move_forward"));
+        input.end_frame();
+        assert!(!input.released("move_forward"));
+
+        //Нажали и отпустили за один кадр - оба события видны
+        press(&mut input, VirtualKeyCode::Escape);
+        release(&mut input, VirtualKeyCode::Escape);
+        assert!(input.just_pressed("quit") && input.released("quit") && !input.pressed("quit"));
+    }
+
+    #[test]
+    fn mouse_axes() {
+        let mut input = Input::from_config(DEFAULT_CONFIG).unwrap();
+        input.mouse = MouseSettings { sensitivity: 2.0, invert_x: false, invert_y: true };
+
+        input.mouse_motion(3.0, 1.0);
+        input.mouse_motion(1.0, 1.0);
+        input.mouse_wheel(-1.0);
+        assert_eq!(input.axis("look_x"), 8.0);
+        assert_eq!(input.axis("look_y"), -4.0);
+        assert_eq!(input.axis("zoom"), -1.0);
+
+        input.button(Binding::MouseButton(MouseButton::Middle), ElementState::Pressed);
+        assert!(input.pressed("orbit_pan"));
+
+        input.end_frame();
+        assert_eq!(input.axis("look_x"), 0.0);
+        assert_eq!(input.axis("zoom"), 0.0);
+
+        //Кнопка как ось
+        input.bind("look_x", Binding::Key(VirtualKeyCode::E));
+        press(&mut input, VirtualKeyCode::E);
+        assert_eq!(input.axis("look_x"), 1.0);
+    }
+}
@@ -1,6 +1,6 @@
 use model::DrawModel;
-use std::default;
-use wgpu::{BindGroupLayout, Dx12Compiler};
+use std::{fs, path::{Path, PathBuf}};
+use wgpu::Dx12Compiler;
 use wgpu::util::DeviceExt;
 use winit::{
     event::*,
@@ -8,7 +8,6 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::{WindowBuilder, Window},
 };
-use winit::window::CursorGrabMode;
 
 use crate::vmath::{Vector3};
 
@@ -17,9 +16,24 @@ mod log;
 
 mod texture;
 mod camera;
+mod camera_path;
+mod projection;
+mod scene;
+mod tangent_space;
+mod viewport;
 mod model;
+mod lights;
+mod shadows;
+mod hdr;
+mod input;
+mod gamepad;
+mod player;
 mod render;
 
+const SCREENSHOTS_PATH: &str = "screenshots";
+const INPUT_CONFIG_PATH: &str = "res/input.cfg";
+const CAMERA_PATH_FILE: &str = "paths/camera_path.txt";
+
 const WIDTH: f32 = 1280.0;
 const HEIGHT: f32 = 1240.0;
 
@@ -70,28 +84,126 @@ const VERTICES: &[render::Vertex] = &[
     render::Vertex { position: [0.5, 0.5, 1.0], color: [0.5, 0.0, 0.5] }, // B
 ];
 
-struct State {
-    surface: wgpu::Surface,
+//Куда рисуем кадр: в поверхность окна или в текстуру без окна
+enum RenderTarget {
+    Surface(wgpu::Surface),
+    Texture(wgpu::Texture),
+}
+
+//Параметры выбора адаптера
+#[derive(Debug, Clone, Copy)]
+pub struct AdapterOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    // true - максимальная поддержка всех платформ, софтварный рендер
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for AdapterOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+//Ни один адаптер не подошёл под AdapterOptions
+#[derive(Debug)]
+pub struct NoAdapterError;
+
+impl std::fmt::Display for NoAdapterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no suitable wgpu adapter found")
+    }
+}
+
+impl std::error::Error for NoAdapterError {}
+
+//При EBENYA_REQUIRE_GPU=1 тесты рендера без адаптера падают, а не пропускаются - для CI
+#[cfg(test)]
+const REQUIRE_GPU_ENV: &str = "EBENYA_REQUIRE_GPU";
+
+//Для тестов рендера: сначала адаптер по умолчанию, потом программный.
+//Без обоих тест помечается SKIPPED в выводе и возвращает None
+#[cfg(test)]
+pub(crate) fn with_test_adapter<T, F>(test: &str, request: impl Fn(AdapterOptions) -> F) -> Option<T>
+where
+    F: std::future::Future<Output = Result<T, anyhow::Error>>,
+{
+    let fallback = AdapterOptions { force_fallback_adapter: true, ..AdapterOptions::default() };
+    for options in [AdapterOptions::default(), fallback] {
+        match pollster::block_on(request(options)) {
+            Ok(value) => return Some(value),
+            Err(err) if err.is::<NoAdapterError>() => continue,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    if std::env::var(REQUIRE_GPU_ENV).is_ok_and(|value| value == "1") {
+        panic!("{}: no wgpu adapter, including the fallback one, and {}=1", test, REQUIRE_GPU_ENV);
+    }
+    eprintln!("SKIPPED {}: no wgpu adapter, including the fallback one", test);
+    None
+}
+
+pub struct Renderer {
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
+    render_pipeline: render::PrimitivePipeline,
     //diffuse_bind_group: wgpu::BindGroup,
     //diffuse_texture: texture::Texture,
     camera: camera::Camera,
-    common_bind_group: wgpu::BindGroup,
-    camera_buffer: wgpu::Buffer,
+    common: render::Common,
+    //Рисуются по порядку, первый - обычно основная камера на весь экран
+    views: Vec<viewport::View>,
+    view_layout: viewport::ViewLayout,
+    fill_pipeline: render::FillPipeline,
     depth_texture: texture::Texture,
-    texture_bind_group_layout: BindGroupLayout,
+    pbr_pipeline: render::PbrPipeline,
+    //Модель без освещения, только base color - для отладки материалов
+    textured_pipeline: render::TexturedPipeline,
+    lighting: bool,
+    shadow_settings: shadows::ShadowSettings,
+    shadow_pipeline: render::ShadowPipeline,
+    //Матрицы и число слоёв, посчитанные в последнем update
+    shadow_uniform: shadows::ShadowUniform,
+    shadow_debug_pipeline: render::ShadowDebugPipeline,
+    //Какой слой карты теней показать в углу кадра, каскады идут подряд
+    shadow_debug: Option<usize>,
+    hdr_settings: hdr::HdrSettings,
+    //Сюда рисуют виды на поверхности, размером с поверхность
+    hdr_texture: texture::Texture,
+    exposure_pipeline: render::ExposurePipeline,
+    exposure_bind_group: wgpu::BindGroup,
+    tonemap_pipeline: render::TonemapPipeline,
+    tonemap_bind_group: wgpu::BindGroup,
 
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
 
-    obj_model: model::Model
+    obj_model: model::Model,
+    //Источники света сцены, попадают на GPU в update
+    lights: lights::Lights,
+    //Отсечение мешей в последнем отрисованном кадре
+    cull_stats: model::CullStats,
+    //С чем сталкивается камера в режиме Player
+    level: player::Level,
+    input: input::Input,
+    gamepad: gamepad::Gamepad,
+    //Запись и воспроизведение пролёта камеры, не больше одного за раз
+    path_recorder: Option<camera_path::PathRecorder>,
+    path_player: Option<camera_path::PathPlayer>,
+    //Из поверхности читать нельзя: по запросу следующий кадр дублируется в captured
+    capture_requested: bool,
+    captured: Option<wgpu::Texture>,
 }
 
-impl State {
+impl Renderer {
     async fn new(window: &Window) -> Self {
         let size = window.inner_size();
 
@@ -110,14 +222,7 @@ impl State {
             },
         ).await.unwrap();
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None,
-        ).await.unwrap();
+        let (device, queue) = Self::request_device(&adapter).await.unwrap();
         
         //Конфигурируем surface
 
@@ -142,39 +247,80 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        //Описываем набор ресурсов и то, как к ним пожно получить доступ из шейдера
-        let texture_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            }
-        );
+        Self::with_target(device, queue, config, RenderTarget::Surface(surface)).unwrap()
+    }
 
-        let obj_model = model::Model::new(
-            "toy_car.gltf",
-            Vector3::new(0.0, 0.0, 0.0),
-            &device,
-            &queue,
-        ).unwrap();
+    //Рендер без окна: кадр рисуется в текстуру width x height формата Rgba8UnormSrgb
+    pub async fn new_offscreen(width: u32, height: u32, options: AdapterOptions) -> Result<Self, anyhow::Error> {
+        let (device, queue) = Self::request_headless_device(options).await?;
+
+        //Поверхности нет, но конфиг описывает размер и формат цели так же, как для окна
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+        let target = RenderTarget::Texture(Self::create_target_texture(&device, &config));
+
+        Self::with_target(device, queue, config, target)
+    }
+
+    //Устройство без поверхности: для offscreen рендера и тестов
+    pub(crate) async fn request_headless_device(options: AdapterOptions) -> Result<(wgpu::Device, wgpu::Queue), anyhow::Error> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: options.backends,
+            dx12_shader_compiler: Dx12Compiler::default(),
+        });
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference,
+                compatible_surface: None,
+                force_fallback_adapter: options.force_fallback_adapter,
+            },
+        ).await.ok_or(NoAdapterError)?;
+
+        Ok(Self::request_device(&adapter).await?)
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await
+    }
+
+    fn create_target_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        })
+    }
+
+    fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        target: RenderTarget,
+    ) -> Result<Self, anyhow::Error> {
+        let size = PhysicalSize::new(config.width, config.height);
 
         //Привязываем набор ресурсов
         /* let diffuse_bind_group = device.create_bind_group(
@@ -197,15 +343,10 @@ impl State {
         let mut camera = camera::Camera::new(
         vmath::Vector3::new(0.0, 0.0, 0.0),
             vmath::Vector3::new(0.0, 0.0, 1.0),
-            60.0,
-            WIDTH,
-            HEIGHT
+            projection::Projection::new_perspective(60.0, size.width, size.height, 0.1, 1000.0),
         );
         camera.update(instant::Duration::default());
 
-        let (camera_bind_group_layout, camera_bind_group, camera_buffer) =
-            camera.get_camera_bind_groups(&device);
-
 
         //Создаём шейдерный модуль
         /*let shader = device.create_shader_module(
@@ -213,7 +354,7 @@ impl State {
         );*/
 
         //Текстура глубины
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+        let depth_texture = texture::Texture::create_depth_texture(&device, config.width, config.height, "depth_texture");
 
         //Создаём графичсекий конвейер
         /*let render_pipeline_layout = device.create_pipeline_layout(
@@ -235,11 +376,88 @@ impl State {
             &camera.TEST_get_view_proj_matrix_buffer(&device)
         );*/
 
+        //Сцена рисуется в HDR цель, на поверхность её переносит TonemapPipeline
+        let hdr_config = wgpu::SurfaceConfiguration {
+            format: texture::Texture::HDR_FORMAT,
+            ..config.clone()
+        };
+
         let render_pipeline = render::PrimitivePipeline::new(
             &device,
             &common,
-            &config
-        ).pipeline;
+            &hdr_config
+        );
+
+        //Общая для всех пайплайнов с материалами и для самих материалов модели
+        let material_layout = model::Material::create_layout(&device);
+
+        let pbr_pipeline = render::PbrPipeline::new(
+            &device,
+            &common,
+            &material_layout,
+            &hdr_config
+        );
+
+        let fill_pipeline = render::FillPipeline::new(&device, &hdr_config);
+
+        let shadow_settings = shadows::ShadowSettings::default();
+        let shadow_pipeline = render::ShadowPipeline::new(
+            &device,
+            &common,
+            &material_layout,
+            shadow_settings.bias,
+        );
+        let shadow_debug_pipeline = render::ShadowDebugPipeline::new(&device, &common, &config);
+        //Настоящие матрицы посчитает update_shadows, когда будут источники и модель
+        let shadow_uniform = shadows::ShadowUniform::new(&[], &camera, &shadow_settings);
+
+        let mut main_view = viewport::View::new(
+            &device,
+            &common,
+            viewport::ViewCamera::Main,
+            viewport::ViewTarget::Surface(viewport::Viewport::FULL),
+        );
+        main_view.update(&queue, &camera);
+
+        let textured_pipeline = render::TexturedPipeline::new(
+            &device,
+            &common,
+            &material_layout,
+            &hdr_config
+        );
+
+        let hdr_texture = texture::Texture::create_hdr_target(&device, config.width, config.height, "hdr_texture");
+        let exposure_pipeline = render::ExposurePipeline::new(&device, hdr::ExposureState::new(0.0));
+        let exposure_bind_group = exposure_pipeline.bind_group(&device, &hdr_texture.view);
+        let tonemap_pipeline = render::TonemapPipeline::new(&device, config.format);
+        let tonemap_bind_group = tonemap_pipeline.bind_group(&device, &hdr_texture.view, &exposure_pipeline.state_buffer);
+
+        let obj_model = model::Model::new(
+            "toy_car.gltf",
+            Vector3::new(0.0, 0.0, 0.0),
+            &device,
+            &queue,
+            &material_layout,
+        )?;
+
+        //Солнце с тенью и рассеянный свет, плюс источники из файла модели
+        let mut lights = lights::Lights::new(Vector3::new(0.15, 0.15, 0.15));
+        lights.add(lights::Light {
+            shadows: true,
+            ..lights::Light::directional(Vector3::new(0.4, -1.0, 0.6), Vector3::new(1.0, 1.0, 1.0), 3.0)
+        });
+        for light in obj_model.lights() {
+            if lights.add(light).is_none() {
+                ::log::warn!("more than {} lights, the rest are skipped", lights::MAX_LIGHTS);
+                break;
+            }
+        }
+        lights.update(&queue, &common.light_buffer);
+
+        //Земля под моделью, плюс сами треугольники модели
+        let ground_height = obj_model.bounds().map_or(0.0, |bounds| bounds.min.y);
+        let mut level = player::Level::new(Some(vmath::Plane::new(Vector3::unit_y(), -ground_height)));
+        level.add_model(&obj_model);
 
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -321,8 +539,8 @@ impl State {
             }
         ); */
 
-        Self {
-            surface,
+        let mut renderer = Self {
+            target,
             device,
             queue,
             config,
@@ -330,56 +548,401 @@ impl State {
             render_pipeline,
             //diffuse_bind_group,
             camera,
-            common_bind_group: camera_bind_group,
-            //common_bind_group,
-            camera_buffer,
+            common,
+            views: vec![main_view],
+            view_layout: viewport::ViewLayout::Single,
+            fill_pipeline,
             depth_texture,
             obj_model,
+            lights,
             vertex_buffer,
             index_buffer,
-            texture_bind_group_layout,
-        }
+            pbr_pipeline,
+            textured_pipeline,
+            lighting: true,
+            shadow_settings,
+            shadow_pipeline,
+            shadow_uniform,
+            shadow_debug_pipeline,
+            shadow_debug: None,
+            hdr_settings: hdr::HdrSettings::default(),
+            hdr_texture,
+            exposure_pipeline,
+            exposure_bind_group,
+            tonemap_pipeline,
+            tonemap_bind_group,
+            cull_stats: model::CullStats::default(),
+            level,
+            input: input::Input::load(Path::new(INPUT_CONFIG_PATH)),
+            gamepad: gamepad::Gamepad::new(gamepad::GamepadSettings::default()),
+            path_recorder: None,
+            path_player: None,
+            capture_requested: false,
+            captured: None,
+        };
+        renderer.update_shadows();
+        renderer.update_hdr(instant::Duration::ZERO);
+
+        Ok(renderer)
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
-            self.config.width = new_size.width;
-            self.surface.configure(&self.device, &self.config);
+            self.config.height = new_size.height;
+            self.resize_views();
+            match &mut self.target {
+                RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Texture(texture) => *texture = Self::create_target_texture(&self.device, &self.config),
+            }
+            self.captured = None;
             //Обновление текстуры глубины
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, self.config.width, self.config.height, "depth_texture");
+            self.hdr_texture = texture::Texture::create_hdr_target(&self.device, self.config.width, self.config.height, "hdr_texture");
+            self.exposure_bind_group = self.exposure_pipeline.bind_group(&self.device, &self.hdr_texture.view);
+            self.tonemap_bind_group = self.tonemap_pipeline.bind_group(&self.device, &self.hdr_texture.view, &self.exposure_pipeline.state_buffer);
+        }
+    }
+
+    //Aspect камер под прямоугольники видов. Основная камера берёт размер первого вида с ней
+    fn resize_views(&mut self) {
+        let (width, height) = (self.config.width, self.config.height);
+        for view in &mut self.views {
+            view.resize(width, height);
+        }
+
+        let main_view = self.views.iter().find(|view| matches!(view.camera, viewport::ViewCamera::Main));
+        let (main_width, main_height) = main_view.map_or((width, height), |view| view.size(width, height));
+        self.camera.resize(main_width, main_height);
+    }
+
+    //Добавляет вид поверх остальных, возвращает его индекс
+    pub fn add_view(&mut self, camera: viewport::ViewCamera, target: viewport::ViewTarget, clear_color: wgpu::Color) -> usize {
+        let mut view = viewport::View::new(&self.device, &self.common, camera, target).with_clear_color(clear_color);
+        view.update(&self.queue, &self.camera);
+        self.views.push(view);
+        self.resize_views();
+
+        self.views.len() - 1
+    }
+
+    //Цель для ViewTarget::Texture в формате пайплайнов рендерера
+    pub fn create_view_texture(&self, width: u32, height: u32) -> viewport::ViewTarget {
+        viewport::ViewTarget::texture(&self.device, &self.config, width, height)
+    }
+
+    pub fn view_mut(&mut self, index: usize) -> Option<&mut viewport::View> {
+        self.views.get_mut(index)
+    }
+
+    pub fn remove_view(&mut self, index: usize) -> viewport::View {
+        let view = self.views.remove(index);
+        self.resize_views();
+        view
+    }
+
+    pub fn lights(&self) -> &lights::Lights {
+        &self.lights
+    }
+
+    //Добавленные, сдвинутые и удалённые источники попадут на GPU в update
+    pub fn lights_mut(&mut self) -> &mut lights::Lights {
+        &mut self.lights
+    }
+
+    //Состояние виртуального геймпада применяется к камере в update
+    pub fn gamepad_mut(&mut self) -> &mut gamepad::Gamepad {
+        &mut self.gamepad
+    }
+
+    pub fn shadow_settings(&self) -> shadows::ShadowSettings {
+        self.shadow_settings
+    }
+
+    //Новый сдвиг глубины пересоздаёт пайплайн теней, остальное вступит в силу в update
+    pub fn set_shadow_settings(&mut self, settings: shadows::ShadowSettings) {
+        if settings.bias != self.shadow_settings.bias {
+            self.shadow_pipeline.set_bias(&self.device, settings.bias);
+        }
+        self.shadow_settings = settings;
+    }
+
+    //Матрицы источников с тенями под текущую камеру
+    fn update_shadows(&mut self) {
+        let matrices = shadows::light_matrices(&self.lights, &self.camera, &self.shadow_settings, self.obj_model.bounds());
+        let uniform = shadows::ShadowUniform::new(&matrices, &self.camera, &self.shadow_settings);
+        //Предупреждаем один раз, когда источник перестал помещаться в слои
+        if uniform.count() < matrices.len() && uniform.count() != self.shadow_uniform.count() {
+            ::log::warn!(
+                "only {} of {} shadow casters fit into {} shadow map layers",
+                uniform.count(), matrices.len(), shadows::MAX_SHADOW_LAYERS
+            );
+        }
+        self.shadow_uniform = uniform;
+        self.queue.write_buffer(&self.common.shadow_buffer, 0, bytemuck::cast_slice(&[self.shadow_uniform]));
+        self.shadow_pipeline.update(&self.queue, &self.shadow_uniform);
+    }
+
+    pub fn hdr_settings(&self) -> hdr::HdrSettings {
+        self.hdr_settings
+    }
+
+    //Вступят в силу в update
+    pub fn set_hdr_settings(&mut self, settings: hdr::HdrSettings) {
+        self.hdr_settings = settings;
+    }
+
+    //Ручная экспозиция пишется сразу, автоматическая догоняет кадр за delta_time
+    fn update_hdr(&mut self, delta_time: instant::Duration) {
+        match self.hdr_settings.exposure {
+            hdr::Exposure::Manual { ev } => {
+                self.exposure_pipeline.set_state(&self.queue, hdr::ExposureState::new(ev));
+            },
+            hdr::Exposure::Auto { compensation, speed } => {
+                let pixel_count = self.config.width * self.config.height;
+                self.exposure_pipeline.update(&self.queue, &hdr::ExposureParams::new(compensation, speed, delta_time, pixel_count));
+            },
+        }
+        self.tonemap_pipeline.update(&self.queue, self.hdr_settings.tone_mapping);
+    }
+
+    //Картинка вида с собственной текстурой, None для вида на поверхности
+    pub fn capture_view(&self, index: usize) -> Option<Result<image::RgbaImage, anyhow::Error>> {
+        match &self.views.get(index)?.target {
+            viewport::ViewTarget::Texture(target) => {
+                Some(texture::Texture::read_to_image(&self.device, &self.queue, &target.texture))
+            },
+            viewport::ViewTarget::Surface(_) => None,
+        }
+    }
+
+    //Оставляет основной вид и строит вокруг него раскладку
+    pub fn set_view_layout(&mut self, layout: viewport::ViewLayout) {
+        use viewport::{Follow, ViewCamera, ViewLayout, ViewTarget, Viewport};
+
+        self.views.truncate(1);
+        self.views[0].target = ViewTarget::Surface(Viewport::FULL);
+        self.view_layout = layout;
+
+        //Чем больше модель, тем выше миникарта
+        let radius = self.obj_model.bounds().map_or(10.0, |bounds| bounds.extents().length());
+        let own_camera = |projection, follow| ViewCamera::Own {
+            camera: Box::new(camera::Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::unit_z(), projection)),
+            follow,
+        };
+        let background = wgpu::Color { r: 0.2, g: 0.2, b: 0.25, a: 1.0 };
+
+        match layout {
+            ViewLayout::Single => self.resize_views(),
+            ViewLayout::Minimap => {
+                let projection = projection::Projection::new_orthographic(radius * 4.0, 1, 1, 0.1, 1000.0);
+                self.add_view(
+                    own_camera(projection, Follow::TopDown { height: radius * 4.0 }),
+                    ViewTarget::Surface(Viewport::new(0.74, 0.02, 0.24, 0.24)),
+                    background,
+                );
+            },
+            ViewLayout::RearView => {
+                let projection = projection::Projection::new_perspective(60.0, 1, 1, 0.1, 1000.0);
+                self.add_view(
+                    own_camera(projection, Follow::Behind),
+                    ViewTarget::Surface(Viewport::new(0.35, 0.02, 0.3, 0.15)),
+                    background,
+                );
+            },
+            //Справа - вторая камера, осматривающая модель
+            ViewLayout::SplitScreen => {
+                self.views[0].target = ViewTarget::Surface(Viewport::new(0.0, 0.0, 0.5, 1.0));
+
+                let mut camera = camera::Camera::new(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::unit_z(),
+                    projection::Projection::new_perspective(60.0, 1, 1, 0.1, 1000.0),
+                );
+                camera.set_pose(Vector3::new(0.0, 0.0, 0.0), 0.5, 0.3);
+                camera.set_mode(camera::CameraMode::Orbit);
+                self.add_view(
+                    ViewCamera::Own { camera: Box::new(camera), follow: Follow::None },
+                    ViewTarget::Surface(Viewport::new(0.5, 0.0, 0.5, 1.0)),
+                    wgpu::Color::WHITE,
+                );
+                let bounds = self.obj_model.bounds();
+                if let (Some(bounds), ViewCamera::Own { camera, .. }) = (bounds, &mut self.views[1].camera) {
+                    camera.frame(bounds);
+                }
+            },
         }
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput { 
-                input,
-                .. 
-            } => self.camera.keyboard_events(&input),
-            _ => {}
+        self.input.process_window_event(event)
+    }
+
+    pub fn update(&mut self, delta_time: instant::Duration) {
+        //Навести орбиту на модель
+        if self.input.just_pressed("frame_model") {
+            if let Some(bounds) = self.obj_model.bounds() {
+                self.camera.set_mode(camera::CameraMode::Orbit);
+                self.camera.frame(bounds);
+            }
+        }
+
+        if self.input.just_pressed("record_path") {
+            self.toggle_path_recording();
+        }
+        if self.input.just_pressed("play_path") {
+            self.toggle_path_playback();
+        }
+        if self.input.just_pressed("cycle_views") {
+            self.set_view_layout(self.view_layout.next());
+        }
+        if self.input.just_pressed("toggle_lighting") {
+            self.lighting = !self.lighting;
+        }
+        //Выкл -> слой 0 -> ... -> последний занятый слой -> выкл
+        if self.input.just_pressed("shadow_debug") {
+            let next = self.shadow_debug.map_or(0, |layer| layer + 1);
+            self.shadow_debug = (next < self.shadow_uniform.layers()).then_some(next);
+        }
+        if self.input.just_pressed("cascade_debug") {
+            self.shadow_settings.debug_cascades = !self.shadow_settings.debug_cascades;
+        }
+        if self.input.just_pressed("cycle_tone_mapping") {
+            self.hdr_settings.tone_mapping = self.hdr_settings.tone_mapping.next();
+        }
+        //Ручная начинает с 0 EV, автоматическая - с текущей экспозиции и догоняет кадр
+        if self.input.just_pressed("toggle_auto_exposure") {
+            self.hdr_settings.exposure = match self.hdr_settings.exposure {
+                hdr::Exposure::Manual { .. } => hdr::HdrSettings::default().exposure,
+                hdr::Exposure::Auto { .. } => hdr::Exposure::Manual { ev: 0.0 },
+            };
+        }
+        //Шаг в пол-ступени: ручная экспозиция или поправка к автоматической
+        let exposure_step = 0.5 * (self.input.just_pressed("exposure_up") as i32 - self.input.just_pressed("exposure_down") as i32) as f32;
+        match &mut self.hdr_settings.exposure {
+            hdr::Exposure::Manual { ev } => *ev += exposure_step,
+            hdr::Exposure::Auto { compensation, .. } => *compensation += exposure_step,
+        }
+
+        self.camera.input(&self.input);
+        self.input.end_frame();
+        self.gamepad.poll();
+        self.camera.gamepad(&self.gamepad.output());
+
+        let keyframe = self.path_player.as_mut().map(|player| player.advance(delta_time));
+        match keyframe {
+            //Во время воспроизведения ввод не двигает камеру
+            Some(Some(keyframe)) => {
+                self.camera.set_pose(keyframe.position, keyframe.yaw, keyframe.pitch);
+                self.camera.update(instant::Duration::ZERO);
+            },
+            _ => {
+                if keyframe.is_some() {
+                    ::log::info!("camera path finished");
+                    self.path_player = None;
+                }
+                self.camera.step_player(delta_time, &self.level);
+                self.camera.update(delta_time);
+            },
+        }
+
+        if let Some(recorder) = &mut self.path_recorder {
+            let (position, yaw, pitch) = self.camera.pose();
+            recorder.record(delta_time, position, yaw, pitch);
+        }
+
+        self.obj_model.update(&self.queue);
+        self.lights.update(&self.queue, &self.common.light_buffer);
+        self.update_shadows();
+        self.update_hdr(delta_time);
+        for view in &mut self.views {
+            view.update(&self.queue, &self.camera);
         }
-    
-        
-        return false;
     }
 
-    fn update(&mut self, delta_time: instant::Duration) {
-        self.camera.update(delta_time);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera.uniform])
-        )
+    //Первое нажатие начинает запись, второе сохраняет её в CAMERA_PATH_FILE
+    fn toggle_path_recording(&mut self) {
+        match self.path_recorder.take() {
+            Some(recorder) => match recorder.finish().save(Path::new(CAMERA_PATH_FILE)) {
+                Ok(()) => ::log::info!("camera path saved to {}", CAMERA_PATH_FILE),
+                Err(e) => ::log::error!("can't save camera path: {:?}", e),
+            },
+            None => {
+                self.path_player = None;
+                self.path_recorder = Some(camera_path::PathRecorder::new(instant::Duration::from_millis(50)));
+                ::log::info!("recording camera path");
+            },
+        }
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        //Достаём текстуру из поверхности
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
+    fn toggle_path_playback(&mut self) {
+        if self.path_player.take().is_some() {
+            return;
+        }
+
+        match camera_path::CameraPath::load(Path::new(CAMERA_PATH_FILE)) {
+            Ok(path) => {
+                self.path_recorder = None;
+                self.camera.set_mode(camera::CameraMode::Free);
+                self.path_player = Some(camera_path::PathPlayer::new(path, false));
+            },
+            Err(e) => ::log::error!("can't load camera path: {:?}", e),
+        }
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        //Достаём текстуру из поверхности, без окна рисуем прямо в целевую текстуру
+        let (output, view) = match &self.target {
+            RenderTarget::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            },
+            RenderTarget::Texture(texture) => {
+                (None, texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            },
+        };
+
+        //Копия кадра для окна пишется тем же кодировщиком, что и сам кадр
+        let capture = match &self.target {
+            RenderTarget::Surface(_) if std::mem::take(&mut self.capture_requested) => {
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    ..self.config.clone()
+                };
+                Some(Self::create_target_texture(&self.device, &config))
+            },
+            _ => None,
+        };
+        let capture_view = capture.as_ref().map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let cull_stats = self.draw(&view, capture_view.as_ref());
+        if capture.is_some() {
+            self.captured = capture;
+        }
+        if cull_stats != self.cull_stats {
+            ::log::debug!("meshes drawn: {}, culled: {}", cull_stats.drawn, cull_stats.culled);
+        }
+        self.cull_stats = cull_stats;
+
+        if let Some(output) = output {
+            output.present();
+        }
+
+        Ok(())
+    }
+
+    pub fn cull_stats(&self) -> model::CullStats {
+        self.cull_stats
+    }
+
+    //Записывает и отправляет в очередь команды кадра: виды на поверхности рисуют в view,
+    //остальные - в свои текстуры. В capture попадает та же картинка, что и в view.
+    //Отсечение суммируется по всем видам
+    fn draw(&self, view: &wgpu::TextureView, capture: Option<&wgpu::TextureView>) -> model::CullStats {
+        let mut cull_stats = model::CullStats::default();
+
         //Кодировщик нужен для создания буфера команд которые потом пойдут в GPU
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
@@ -394,28 +957,71 @@ impl State {
                 usage: wgpu::BufferUsages::VERTEX,
             }
         );
-        //Создаём проход рендера
-        
-        {//так как созданный _render_pass владеет encoder 
+
+        //Сначала карты теней: по проходу глубины на каждый прожектор и каскад
+        for (layer, shadow_layer) in self.shadow_pipeline.layers.iter().enumerate().take(self.shadow_uniform.layers()) {
+            let mut shadow_pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("Shadow Pass"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &shadow_layer.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                }
+            );
+
+            shadow_pass.set_pipeline(&self.shadow_pipeline.pipeline);
+            shadow_pass.set_bind_group(0, &shadow_layer.bind_group, &[]);
+            let frustum = vmath::Frustum::from_view_proj(self.shadow_uniform.view_proj(layer));
+            shadow_pass.draw_model_culled(&self.obj_model, &frustum);
+        }
+
+        //Виды рисуют в HDR цели: на поверхности - в общую hdr_texture, остальные - в свою
+        let mut surface_cleared = false;
+        for scene_view in &self.views {
+            let (color_view, depth_view, viewport) = match &scene_view.target {
+                viewport::ViewTarget::Surface(viewport) => (&self.hdr_texture.view, &self.depth_texture.view, Some(viewport)),
+                viewport::ViewTarget::Texture(target) => (&target.hdr_texture.view, &target.depth_texture.view, None),
+            };
+
+            //Clear очищает всю цель, поэтому на поверхности так очищается только первый вид,
+            //прямоугольники остальных заливаются fill_pipeline
+            let fill = viewport.is_some() && surface_cleared;
+            let load = if fill {
+                wgpu::LoadOp::Load
+            } else {
+                wgpu::LoadOp::Clear(scene_view.clear_color)
+            };
+            surface_cleared |= viewport.is_some();
+
+            //Глубина сравнивается и очищается так, как требует проекция камеры вида
+            let view_camera = scene_view.camera(&self.camera);
+            let depth_mode = view_camera.projection.depth_mode();
+
+            //Создаём проход рендера
             let mut render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
                     label: Some("Render Pass"),
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        //Сохраняем цвета в созданную текстуру view
-                        view: &view,
+                        //Сохраняем цвета в текстуру вида
+                        view: color_view,
                         resolve_target: None,
                         ops: wgpu::Operations {
                             //Указываем как обрабатывать цвета которые остались в пердыдущем кадре
-                            //В нашем случае мы их очищаем, закрашивая всё цветом
-                            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
-                            //Сохранять результат в текстуре view
+                            load,
+                            //Сохранять результат в текстуре
                             store: true,
                         },
                     })],
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &self.depth_texture.view,
+                        view: depth_view,
                         depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(1.0),
+                            load: wgpu::LoadOp::Clear(depth_mode.clear_value()),
                             store: true,
                         }),
                         stencil_ops: None,
@@ -423,40 +1029,128 @@ impl State {
                 }
             );
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            //группа с текстурами и семплером
-            //render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-
-            render_pass.set_bind_group(0, &self.common_bind_group, &[]);
+            if let Some(viewport) = viewport {
+                let (x, y, width, height) = viewport.pixels(self.config.width, self.config.height);
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+            }
 
-            //параметры: номер слота, вершины
-            //   render_pass.set_vertex_buffer(0, self.obj_model.vertex_buffer.slice(..));
-            //render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            //Можно использовать только оидн индексный буфер
-            //   render_pass.set_index_buffer(self.obj_model.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            //нарисовать три вершины в одном экземляре
-            //render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+            if fill {
+                render_pass.set_pipeline(&self.fill_pipeline.pipeline);
+                render_pass.set_blend_constant(scene_view.clear_color);
+                render_pass.draw(0..3, 0..1);
+            }
 
+            render_pass.set_pipeline(self.render_pipeline.pipeline(depth_mode));
+            render_pass.set_bind_group(0, &scene_view.bind_group, &[]);
 
             render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
             render_pass.set_index_buffer(
                 self.index_buffer.slice(..),
                 wgpu::IndexFormat::Uint16
             );
-            render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1); // 2.
+            render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+
+            render_pass.set_pipeline(if self.lighting {
+                self.pbr_pipeline.pipeline(depth_mode)
+            } else {
+                self.textured_pipeline.pipeline(depth_mode)
+            });
+            render_pass.set_bind_group(0, &scene_view.bind_group, &[]);
+            let frustum = view_camera.frustum();
+            cull_stats += render_pass.draw_model_culled(&self.obj_model, &frustum);
+        }
 
+        //Экспозиция подбирается по видам на поверхности, виды со своей текстурой берут её же
+        if surface_cleared && matches!(self.hdr_settings.exposure, hdr::Exposure::Auto { .. }) {
+            self.exposure_pipeline.dispatch(&mut encoder, &self.exposure_bind_group, self.hdr_texture.texture.size());
+        }
 
-            //render_pass.draw_model(&self.obj_model)
+        for scene_view in &self.views {
+            if let viewport::ViewTarget::Texture(target) = &scene_view.target {
+                let bind_group = self.tonemap_pipeline.bind_group(&self.device, &target.hdr_texture.view, &self.exposure_pipeline.state_buffer);
+                let texture_view = target.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let mut tonemap_pass = Self::begin_tonemap_pass(&mut encoder, &texture_view);
+                self.tonemap_pipeline.draw(&mut tonemap_pass, &bind_group);
+            }
+        }
 
-            
-            //use model::DrawModel;
-            //render_pass.draw_mesh_instanced(mesh, material, 0..self.instances.len() as u32, &self.camera_bind_group);
+        //Без видов на поверхности hdr_texture не очищалась, поверхность тоже не трогаем
+        if surface_cleared {
+            for output in std::iter::once(view).chain(capture) {
+                self.draw_output(&mut encoder, output);
+            }
         }
         //Завершить буфер команд и отправить его в очередь
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
 
-        Ok(())
+        cull_stats
+    }
+
+    //Кадр из hdr_texture с экспозицией, уже посчитанной в этом кадре
+    fn draw_output(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut tonemap_pass = Self::begin_tonemap_pass(encoder, view);
+        self.tonemap_pipeline.draw(&mut tonemap_pass, &self.tonemap_bind_group);
+
+        //Отладочный слой карты теней - квадрат в левом нижнем углу, поверх готового кадра
+        if let (Some(layer), Some(scene_view)) = (self.shadow_debug, self.views.first()) {
+            let side = self.config.width.min(self.config.height) / 3;
+            tonemap_pass.set_viewport(0.0, (self.config.height - side) as f32, side as f32, side as f32, 0.0, 1.0);
+            tonemap_pass.set_scissor_rect(0, self.config.height - side, side, side);
+            tonemap_pass.set_pipeline(&self.shadow_debug_pipeline.pipeline);
+            tonemap_pass.set_bind_group(0, &scene_view.bind_group, &[]);
+            tonemap_pass.draw(0..3, layer as u32..layer as u32 + 1);
+        }
+    }
+
+    //Проход тональной компрессии: треугольник перекрывает всю цель, старое содержимое не нужно
+    fn begin_tonemap_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: Some("Tonemap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            }
+        )
+    }
+
+    //Следующий render для окна сохранит копию кадра для capture_frame.
+    //Offscreen цель и так читается напрямую
+    pub fn request_capture(&mut self) {
+        self.capture_requested = matches!(self.target, RenderTarget::Surface(_));
+    }
+
+    //Последний отрисованный кадр в виде картинки. Для окна - копия,
+    //запрошенная через request_capture до render
+    pub fn capture_frame(&self) -> Result<image::RgbaImage, anyhow::Error> {
+        let texture = match &self.target {
+            RenderTarget::Texture(texture) => texture,
+            RenderTarget::Surface(_) => self.captured.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("no captured frame, request_capture must precede render"))?,
+        };
+
+        texture::Texture::read_to_image(&self.device, &self.queue, texture)
+    }
+
+    //Сохраняет кадр в dir/screenshot_<время>.png, возвращает путь к файлу
+    pub fn save_screenshot(&self, dir: &Path) -> Result<PathBuf, anyhow::Error> {
+        let image = self.capture_frame()?;
+
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "screenshot_{}.png",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f")
+        ));
+        image.save(&path)?;
+
+        Ok(path)
     }
 
 }
@@ -479,7 +1173,7 @@ pub async fn run() {
     window.set_cursor_visible(false);
     window.set_cursor_position(PhysicalPosition::new(WIDTH * 0.5, HEIGHT * 0.5)).unwrap();
 
-    let mut state = State::new(&window).await;
+    let mut state = Renderer::new(&window).await;
     let mut last_render_time = instant::Instant::now();
 
     event_loop.run(move |event, _, control_flow| match event {
@@ -488,16 +1182,7 @@ pub async fn run() {
             window_id,
         } if window_id == window.id() => if !state.input(event) {
             match event {
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                 WindowEvent::Resized(physical_size) => {
                     state.resize(*physical_size);
                 },
@@ -513,9 +1198,24 @@ pub async fn run() {
             let delta_time = now - last_render_time;
             last_render_time = now;
 
+            if state.input.just_pressed("quit") {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            //Снимок - это кадр, который сейчас будет показан
+            let screenshot = state.input.just_pressed("screenshot");
+            if screenshot {
+                state.request_capture();
+            }
+
             state.update(delta_time);
             match state.render() {
-                Ok(_) => {}
+                Ok(_) => if screenshot {
+                    match state.save_screenshot(Path::new(SCREENSHOTS_PATH)) {
+                        Ok(path) => ::log::info!("screenshot saved to {}", path.display()),
+                        Err(e) => ::log::error!("can't save screenshot: {:?}", e),
+                    }
+                },
                 // Reconfigure the surface if lost
                 Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
                 // The system is out of memory, we should probably quit
@@ -529,7 +1229,7 @@ pub async fn run() {
                 delta
             },
             ..
-        } => state.camera.mouse_events(delta.0 as f32, delta.1 as f32),
+        } => state.input.mouse_motion(delta.0 as f32, delta.1 as f32),
         Event::MainEventsCleared => {
             //Получается этот ивент тригерится первый раз при создании окна??
             //И потом запрашивает перерисовку
@@ -552,4 +1252,186 @@ mod tests {
         log::write("test", "text", None).unwrap();
         //let obj = model::Model::new("res/keytruck.obj", Vector3::new(0.0, 0.0, 0.0));
     }
+
+    #[test]
+    fn offscreen_render() {
+        let Some(mut renderer) = with_test_adapter("offscreen_render", |options| Renderer::new_offscreen(64, 48, options)) else { return };
+
+        renderer.update(instant::Duration::from_millis(16));
+        renderer.render().unwrap();
+        let cull_stats = renderer.cull_stats();
+        assert_eq!(cull_stats.drawn + cull_stats.culled, renderer.obj_model.instances().len() as u32);
+
+        renderer.resize(PhysicalSize::new(32, 32));
+        renderer.render().unwrap();
+        assert!((renderer.camera.projection.aspect - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn multiple_views() {
+        use viewport::{Follow, ViewCamera, ViewTarget, Viewport};
+
+        let Some(mut renderer) = with_test_adapter("multiple_views", |options| Renderer::new_offscreen(64, 48, options)) else { return };
+
+        //Картинка в картинке: камера в пустоту, видна только заливка
+        let mut camera = camera::Camera::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+            projection::Projection::new_perspective(60.0, 1, 1, 0.1, 10.0),
+        );
+        camera.set_pose(Vector3::new(0.0, 0.0, -500.0), std::f32::consts::PI, 0.0);
+        let red = wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+        let inset = renderer.add_view(
+            ViewCamera::Own { camera: Box::new(camera), follow: Follow::None },
+            ViewTarget::Surface(Viewport::new(0.5, 0.0, 0.5, 0.5)),
+            red,
+        );
+        //Aspect камеры - по прямоугольнику вида, а не по всей цели
+        let ViewCamera::Own { camera, .. } = &renderer.view_mut(inset).unwrap().camera else { unreachable!() };
+        assert!((camera.projection.aspect - 32.0 / 24.0).abs() < 1e-6);
+
+        //Без тональной компрессии цвет заливки попадает на поверхность как есть
+        renderer.set_hdr_settings(hdr::HdrSettings {
+            tone_mapping: hdr::ToneMapping::None,
+            exposure: hdr::Exposure::Manual { ev: 0.0 },
+        });
+        let target = renderer.create_view_texture(16, 8);
+        let offscreen = renderer.add_view(ViewCamera::Main, target, wgpu::Color::BLACK);
+
+        renderer.update(instant::Duration::from_millis(16));
+        renderer.render().unwrap();
+
+        let image = renderer.capture_frame().unwrap();
+        assert_eq!(image.get_pixel(63, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(32, 23).0, [255, 0, 0, 255]);
+        assert_ne!(image.get_pixel(31, 24).0, [255, 0, 0, 255]);
+        assert_ne!(image.get_pixel(0, 47).0, [255, 0, 0, 255]);
+
+        let view_image = renderer.capture_view(offscreen).unwrap().unwrap();
+        assert_eq!(view_image.dimensions(), (16, 8));
+        assert!(renderer.capture_view(0).is_none());
+
+        //Раскладки перестраивают виды, основной остаётся первым
+        renderer.set_view_layout(viewport::ViewLayout::SplitScreen);
+        assert_eq!(renderer.views.len(), 2);
+        assert!((renderer.camera.projection.aspect - 32.0 / 48.0).abs() < 1e-6);
+        renderer.update(instant::Duration::from_millis(16));
+        renderer.render().unwrap();
+
+        renderer.set_view_layout(viewport::ViewLayout::Single);
+        assert_eq!(renderer.views.len(), 1);
+        assert!((renderer.camera.projection.aspect - 64.0 / 48.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shadow_map_debug_view() {
+        let Some(mut renderer) = with_test_adapter("shadow_map_debug_view", |options| Renderer::new_offscreen(96, 96, options)) else { return };
+
+        //Один каскад солнца только на окрестность модели, чтобы она заняла заметную часть слоя
+        let bounds = renderer.obj_model.bounds().unwrap();
+        renderer.camera.set_mode(camera::CameraMode::Orbit);
+        renderer.camera.frame(bounds);
+        renderer.set_shadow_settings(shadows::ShadowSettings {
+            bias: wgpu::DepthBiasState { constant: 4, slope_scale: 1.5, clamp: 0.0 },
+            distance: bounds.extents().length() * 4.0,
+            cascades: 1,
+            ..renderer.shadow_settings()
+        });
+        renderer.update(instant::Duration::from_millis(16));
+        assert_eq!(renderer.shadow_uniform.count(), 1);
+        assert_eq!(renderer.shadow_uniform.layers(), 1);
+
+        renderer.shadow_debug = Some(0);
+        renderer.render().unwrap();
+
+        //Слой в левом нижнем углу 32x32: пустая карта белая, модель ближе к солнцу - темнее
+        let image = renderer.capture_frame().unwrap();
+        let overlay: Vec<u8> = (64..96)
+            .flat_map(|y| (0..32).map(move |x| (x, y)))
+            .map(|(x, y)| image.get_pixel(x, y).0[0])
+            .collect();
+        assert!(overlay.iter().any(|&value| value == 255));
+        assert!(overlay.iter().any(|&value| value < 200));
+    }
+
+    #[test]
+    fn virtual_gamepad_moves_camera() {
+        let Some(mut renderer) = with_test_adapter("virtual_gamepad_moves_camera", |options| Renderer::new_offscreen(64, 48, options)) else { return };
+
+        let (start, _, _) = renderer.camera.pose();
+        renderer.gamepad_mut().set_state(gamepad::GamepadState {
+            left_stick: vmath::Vector2::new(0.0, 1.0),
+            ..Default::default()
+        });
+        renderer.update(instant::Duration::from_millis(100));
+
+        let (position, _, _) = renderer.camera.pose();
+        assert!((position - start).length() > 0.0);
+    }
+
+    #[test]
+    fn capture_matches_presented_frame() {
+        let Some(mut renderer) = with_test_adapter("capture_matches_presented_frame", |options| Renderer::new_offscreen(64, 48, options)) else { return };
+
+        //Автоэкспозиция и отладочный слой: копия должна совпасть и с тем, и с другим
+        renderer.shadow_debug = Some(0);
+        renderer.update(instant::Duration::from_millis(16));
+
+        let RenderTarget::Texture(target) = &renderer.target else { unreachable!() };
+        let capture = Renderer::create_target_texture(&renderer.device, &renderer.config);
+        renderer.draw(
+            &target.create_view(&wgpu::TextureViewDescriptor::default()),
+            Some(&capture.create_view(&wgpu::TextureViewDescriptor::default())),
+        );
+
+        let presented = texture::Texture::read_to_image(&renderer.device, &renderer.queue, target).unwrap();
+        let captured = texture::Texture::read_to_image(&renderer.device, &renderer.queue, &capture).unwrap();
+        assert!(presented == captured);
+    }
+
+    #[test]
+    fn reversed_z_camera() {
+        let Some(mut renderer) = with_test_adapter("reversed_z_camera", |options| Renderer::new_offscreen(64, 48, options)) else { return };
+
+        renderer.set_hdr_settings(hdr::HdrSettings {
+            tone_mapping: hdr::ToneMapping::None,
+            exposure: hdr::Exposure::Manual { ev: 0.0 },
+        });
+        let capture = |renderer: &mut Renderer| {
+            renderer.update(instant::Duration::from_millis(16));
+            renderer.render().unwrap();
+            renderer.capture_frame().unwrap()
+        };
+        let standard = capture(&mut renderer);
+
+        //Та же камера с обратным z: пайплайны и очистка глубины берут DepthMode из её проекции
+        let projection = renderer.camera.projection;
+        renderer.camera.projection = projection::Projection::new_perspective_reversed_z(projection.fovy, 64, 48, projection.near);
+        let reversed = capture(&mut renderer);
+
+        let drawn = reversed.pixels().filter(|pixel| pixel.0 != [255, 255, 255, 255]).count();
+        assert!(drawn > 0);
+        let different = standard.pixels().zip(reversed.pixels())
+            .filter(|(a, b)| a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > 8))
+            .count();
+        assert!(different * 100 <= drawn, "{} of {} pixels differ", different, drawn);
+    }
+
+    #[test]
+    fn offscreen_capture() {
+        let Some(mut renderer) = with_test_adapter("offscreen_capture", |options| Renderer::new_offscreen(100, 30, options)) else { return };
+
+        renderer.set_hdr_settings(hdr::HdrSettings {
+            tone_mapping: hdr::ToneMapping::None,
+            exposure: hdr::Exposure::Manual { ev: 0.0 },
+        });
+        renderer.update(instant::Duration::from_millis(16));
+        renderer.render().unwrap();
+
+        //Ширина 100 не кратна выравниванию строк, картинка всё равно должна совпасть по размеру
+        let image = renderer.capture_frame().unwrap();
+        assert_eq!(image.dimensions(), (100, 30));
+        //Фон очищается белым, угол кадра ничем не закрыт
+        assert_eq!(image.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,373 @@
+/*
+    Точечные источники света: направленный, точечный и прожектор, как в KHR_lights_punctual.
+    Lights хранит свет на CPU и заливает его в storage буфер группы Common
+    (см. Common::light_buffer): заголовок с рассеянным светом и числом источников,
+    за ним LightRaw подряд. Раскладка совпадает с Lights в shaders/pbr.wgsl.
+*/
+
+use std::mem;
+
+use crate::shadows::MAX_SHADOWS;
+use crate::vmath::{Matrix4x4, Vector3};
+
+// Больше источников буфер не вмещает
+pub const MAX_LIGHTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    //Бесконечно далёкий источник, светит вдоль direction, позиция не важна
+    Directional,
+    Point,
+    //Углы от оси конуса в радианах: внутри inner полная яркость, к outer спадает до нуля
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    //Линейный RGB
+    pub color: Vector3<f32>,
+    //Люксы для направленного, канделы для точечного и прожектора
+    pub intensity: f32,
+    //Дальше света нет. None - бесконечно, затухает только с квадратом расстояния
+    pub range: Option<f32>,
+    pub position: Vector3<f32>,
+    //Куда светит, единичный
+    pub direction: Vector3<f32>,
+    //Рисовать ли карту теней. Работает для направленных и прожекторов, не больше MAX_SHADOWS
+    pub shadows: bool,
+}
+
+// Номер источника в Lights, остаётся тем же, пока источник не удалён
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(usize);
+
+pub struct Lights {
+    //Удалённые источники оставляют дыру, её занимает следующий add
+    slots: Vec<Option<Light>>,
+    ambient: Vector3<f32>,
+    dirty: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsHeader {
+    ambient: [f32; 3],
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: [f32; 3],
+    kind: u32,
+    direction: [f32; 3],
+    //0 - без ограничения
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+    //Затухание прожектора: clamp(cos * scale + offset, 0, 1)^2, как советует KHR_lights_punctual
+    spot_scale: f32,
+    spot_offset: f32,
+    //Номер источника с тенью в ShadowUniform, -1 - без тени
+    shadow_map: i32,
+    _padding: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vector3<f32>, color: Vector3<f32>, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            range: None,
+            position: Vector3::zero(),
+            direction: direction.normalize(),
+            shadows: false,
+        }
+    }
+
+    pub fn point(position: Vector3<f32>, color: Vector3<f32>, intensity: f32, range: Option<f32>) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            position,
+            direction: -Vector3::unit_z(),
+            shadows: false,
+        }
+    }
+
+    pub fn spot(
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+        range: Option<f32>,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot { inner_cone_angle, outer_cone_angle },
+            color,
+            intensity,
+            range,
+            position,
+            direction: direction.normalize(),
+            shadows: false,
+        }
+    }
+
+    //Источник из KHR_lights_punctual в координатах своего узла: в начале координат, светит вдоль -z
+    pub fn from_gltf(light: &gltf::khr_lights_punctual::Light) -> Self {
+        let kind = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+                LightKind::Spot { inner_cone_angle, outer_cone_angle }
+            },
+        };
+
+        Self {
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+            position: Vector3::zero(),
+            direction: -Vector3::unit_z(),
+            shadows: false,
+        }
+    }
+
+    //Тот же источник, перенесённый матрицей узла. Масштаб на направление не влияет
+    pub fn transformed(&self, matrix: Matrix4x4<f32>) -> Self {
+        let position = matrix * self.position;
+        let direction = (matrix * (self.position + self.direction) - position)
+            .try_normalize()
+            .unwrap_or(self.direction);
+
+        Self { position, direction, ..*self }
+    }
+
+    //Может ли источник отбрасывать тень: точечному нужна кубическая карта, её нет
+    pub fn casts_shadows(&self) -> bool {
+        self.shadows && self.kind != LightKind::Point
+    }
+
+    //shadow_map - номер среди источников с тенью или None
+    pub fn raw(&self, shadow_map: Option<usize>) -> LightRaw {
+        let (kind, spot_scale, spot_offset) = match self.kind {
+            LightKind::Directional => (0, 0.0, 0.0),
+            LightKind::Point => (1, 0.0, 0.0),
+            LightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                let cos_outer = outer_cone_angle.cos();
+                let scale = 1.0 / (inner_cone_angle.cos() - cos_outer).max(1e-3);
+                (2, scale, -cos_outer * scale)
+            },
+        };
+
+        LightRaw {
+            position: self.position.into(),
+            kind,
+            direction: self.direction.into(),
+            range: self.range.unwrap_or(0.0),
+            color: self.color.into(),
+            intensity: self.intensity,
+            spot_scale,
+            spot_offset,
+            shadow_map: shadow_map.map_or(-1, |layer| layer as i32),
+            _padding: 0.0,
+        }
+    }
+}
+
+impl Lights {
+    //Размер storage буфера под MAX_LIGHTS источников
+    pub const BUFFER_SIZE: wgpu::BufferAddress =
+        (mem::size_of::<LightsHeader>() + mem::size_of::<LightRaw>() * MAX_LIGHTS) as wgpu::BufferAddress;
+
+    pub fn new(ambient: Vector3<f32>) -> Self {
+        Self {
+            slots: Vec::new(),
+            ambient,
+            dirty: true,
+        }
+    }
+
+    //None - все MAX_LIGHTS мест заняты
+    pub fn add(&mut self, light: Light) -> Option<LightId> {
+        let index = match self.slots.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if self.slots.len() < MAX_LIGHTS => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            },
+            None => return None,
+        };
+
+        self.slots[index] = Some(light);
+        self.dirty = true;
+
+        Some(LightId(index))
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.slots.get(id.0)?.as_ref()
+    }
+
+    //Изменения попадут на GPU при следующем update
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        let light = self.slots.get_mut(id.0)?.as_mut()?;
+        self.dirty = true;
+        Some(light)
+    }
+
+    pub fn set_position(&mut self, id: LightId, position: Vector3<f32>) -> bool {
+        self.get_mut(id).map(|light| light.position = position).is_some()
+    }
+
+    //После удаления id может достаться новому источнику
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        let light = self.slots.get_mut(id.0)?.take()?;
+        self.dirty = true;
+        Some(light)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.slots.iter()
+            .enumerate()
+            .filter_map(|(index, light)| light.as_ref().map(|light| (LightId(index), light)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn ambient(&self) -> Vector3<f32> {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: Vector3<f32>) {
+        self.ambient = ambient;
+        self.dirty = true;
+    }
+
+    //Источники с тенями по порядку их номеров в ShadowUniform, первые MAX_SHADOWS
+    pub fn shadow_casters(&self) -> impl Iterator<Item = &Light> {
+        self.iter()
+            .map(|(_, light)| light)
+            .filter(|light| light.casts_shadows())
+            .take(MAX_SHADOWS)
+    }
+
+    //Содержимое буфера: заголовок и источники без дыр
+    fn data(&self) -> Vec<u8> {
+        let mut casters = 0..MAX_SHADOWS;
+        let raw: Vec<LightRaw> = self.iter()
+            .map(|(_, light)| light.raw(if light.casts_shadows() { casters.next() } else { None }))
+            .collect();
+        let header = LightsHeader {
+            ambient: self.ambient.into(),
+            count: raw.len() as u32,
+        };
+
+        let mut data = bytemuck::bytes_of(&header).to_vec();
+        data.extend_from_slice(bytemuck::cast_slice(&raw));
+        data
+    }
+
+    //Заливает источники в буфер размера BUFFER_SIZE, false - ничего не менялось
+    pub fn update(&mut self, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        queue.write_buffer(buffer, 0, &self.data());
+        self.dirty = false;
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn white() -> Vector3<f32> {
+        Vector3::new(1.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn add_move_remove() {
+        let mut lights = Lights::new(Vector3::zero());
+        let sun = lights.add(Light::directional(-Vector3::unit_y(), white(), 3.0)).unwrap();
+        let lamp = lights.add(Light::point(Vector3::zero(), white(), 10.0, Some(5.0))).unwrap();
+        assert_eq!(lights.len(), 2);
+
+        assert!(lights.set_position(lamp, Vector3::new(1.0, 2.0, 3.0)));
+        assert_eq!(lights.get(lamp).unwrap().position, Vector3::new(1.0, 2.0, 3.0));
+
+        //Место удалённого занимает следующий, остальные id не меняются
+        assert!(lights.remove(sun).is_some());
+        assert!(lights.remove(sun).is_none());
+        assert!(!lights.set_position(sun, Vector3::zero()));
+        assert_eq!(lights.add(Light::point(Vector3::zero(), white(), 1.0, None)), Some(sun));
+        assert_eq!(lights.get(lamp).unwrap().intensity, 10.0);
+
+        while lights.len() < MAX_LIGHTS {
+            lights.add(Light::point(Vector3::zero(), white(), 1.0, None)).unwrap();
+        }
+        assert!(lights.add(Light::point(Vector3::zero(), white(), 1.0, None)).is_none());
+        assert!(lights.data().len() as wgpu::BufferAddress <= Lights::BUFFER_SIZE);
+    }
+
+    #[test]
+    fn raw_layout() {
+        //Раскладка должна совпадать с WGSL: vec3 + скаляр по 16 байт, структура кратна 16
+        assert_eq!(mem::size_of::<LightsHeader>(), 16);
+        assert_eq!(mem::size_of::<LightRaw>(), 64);
+
+        let spot = Light::spot(Vector3::zero(), Vector3::unit_z(), white(), 1.0, None, 0.2, 0.4).raw(Some(1));
+        assert_eq!(spot.kind, 2);
+        assert_eq!(spot.shadow_map, 1);
+        //На внешнем конусе затухание 0, на внутреннем 1
+        assert!((0.4f32.cos() * spot.spot_scale + spot.spot_offset).abs() < EPSILON);
+        assert!((0.2f32.cos() * spot.spot_scale + spot.spot_offset - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn from_gltf_node() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": { "KHR_lights_punctual": { "lights": [
+                { "type": "spot", "color": [1.0, 0.5, 0.0], "intensity": 20.0, "range": 8.0,
+                  "spot": { "innerConeAngle": 0.1, "outerConeAngle": 0.5 } }
+            ] } },
+            "nodes": [ { "extensions": { "KHR_lights_punctual": { "light": 0 } } } ]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let node = gltf.nodes().next().unwrap();
+        let light = Light::from_gltf(&node.light().unwrap());
+
+        assert_eq!(light.kind, LightKind::Spot { inner_cone_angle: 0.1, outer_cone_angle: 0.5 });
+        assert_eq!(light.color, Vector3::new(1.0, 0.5, 0.0));
+        assert_eq!(light.range, Some(8.0));
+
+        //Узел над началом координат, повёрнутый так, что -z смотрит вниз
+        let matrix = Matrix4x4::new_translation(Vector3::new(0.0, 4.0, 0.0))
+            * Matrix4x4::new_rotate(Vector3::unit_x(), FRAC_PI_2);
+        let placed = light.transformed(matrix);
+        assert!((placed.position - Vector3::new(0.0, 4.0, 0.0)).length() < EPSILON);
+        assert!((placed.direction - Vector3::new(0.0, -1.0, 0.0)).length() < EPSILON, "{:?}", placed.direction);
+    }
+}
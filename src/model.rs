@@ -1,43 +1,376 @@
-use std::{fs, io::Read, mem, path::Path, borrow::Borrow};
+use std::{fs, io::Read, mem, ops::Range, path::Path, rc::Rc};
 use anyhow::Ok;
 use wgpu::{util::DeviceExt, RenderPass, Buffer, BindGroupLayout};
-use crate::{vmath, texture};
+use crate::{lights, vmath, texture};
+use crate::scene::{SceneGraph, Transform};
+use crate::tangent_space::{self, NormalMode};
 
 const RESOURCES_PATH: &str = "res";
+const DEFAULT_TEXTURE: &str = "default.png";
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    //w - знак битангенса: bitangent = cross(normal, tangent.xyz) * w
+    pub tangent: [f32; 4],
+}
+
+// Как достраивать данные, которых нет в файле
+#[derive(Debug, Clone, Copy)]
+pub struct ModelOptions {
+    //Для примитивов без NORMAL
+    pub normals: NormalMode,
+}
+
+impl Default for ModelOptions {
+    fn default() -> Self {
+        Self {
+            //Так велит спецификация glTF
+            normals: NormalMode::Flat,
+        }
+    }
+}
+
+// Матрица модели экземпляра, вершинный буфер со step_mode Instance
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
 }
 
 pub struct Mesh {
     //pub vertices: Vec<Vertex>,
     pub indices:  Vec<u32>,
+    //Копия позиций вершин на CPU, для столкновений
+    pub positions: Vec<[f32; 3]>,
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
-    pub material: Material,
+    pub material: Rc<Material>,
+    //В координатах меша, без трансформации узла
+    pub bounds: vmath::Aabb<f32>,
+    //Экземпляры меша в Model::instances, идут подряд
+    pub instances: Range<u32>,
+}
+
+// Меш, нарисованный с матрицей узла. Один меш может висеть на нескольких узлах
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    pub mesh: usize,
+    pub node: usize,
+    //Мировые границы на момент последнего update
+    pub bounds: vmath::Aabb<f32>,
+}
+
+// Примитив glTF, собранный на CPU: вершины уже отражены по x
+struct PrimitiveData {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    //Индекс материала gltf, None - материал по умолчанию
+    material: Option<usize>,
+    //В координатах меша
+    bounds: vmath::Aabb<f32>,
 }
 
 pub struct Model {
-    pub position: vmath::Vector3<f32>,
     pub meshes: Vec<Mesh>,
+    pub materials: Vec<Rc<Material>>,
+    scene: SceneGraph,
+    instances: Vec<Instance>,
+    instance_buffer: Buffer,
+    //Источники KHR_lights_punctual в координатах своих узлов
+    lights: Vec<(usize, lights::Light)>,
+}
+
+// Множители материала glTF metallic-roughness, uniform буфер в binding 0 группы материала.
+// Раскладка совпадает с MaterialUniform в shaders/pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color_factor: [f32; 4],
+    pub emissive_factor: [f32; 3],
+    //Фрагменты с альфой меньше отбрасываются (alphaMode MASK), 0 - не отбрасываются
+    pub alpha_cutoff: f32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+}
+
+impl Default for MaterialUniform {
+    //Значения по умолчанию из спецификации glTF
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            emissive_factor: [0.0, 0.0, 0.0],
+            alpha_cutoff: 0.0,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+        }
+    }
+}
+
+pub struct MaterialTextures {
+    pub base_color: texture::Texture,
+    //g - roughness, b - metallic
+    pub metallic_roughness: texture::Texture,
+    //Нормаль в пространстве касательных
+    pub normal: texture::Texture,
+    //r - затенение рассеянного света
+    pub occlusion: texture::Texture,
+    pub emissive: texture::Texture,
 }
 
 pub struct Material {
-    pub texture: texture::Texture,
+    pub name: String,
+    pub uniform: MaterialUniform,
+    pub textures: MaterialTextures,
+    pub bind_group: wgpu::BindGroup,
+}
+
+const fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+const fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+impl MaterialTextures {
+    //Карты, которые ничего не меняют: белые умножаются на множители материала, нормаль плоская
+    pub fn neutral(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, anyhow::Error> {
+        let color = |color: [u8; 4], label: &str| {
+            texture::Texture::from_color(device, queue, color, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+        };
+        let data = |color: [u8; 4], label: &str| {
+            texture::Texture::from_color(device, queue, color, label, wgpu::TextureFormat::Rgba8Unorm)
+        };
+
+        Ok(Self {
+            base_color: color([255, 255, 255, 255], "neutral_base_color")?,
+            metallic_roughness: data([255, 255, 255, 255], "neutral_metallic_roughness")?,
+            normal: data([128, 128, 255, 255], "neutral_normal")?,
+            occlusion: data([255, 255, 255, 255], "neutral_occlusion")?,
+            emissive: color([255, 255, 255, 255], "neutral_emissive")?,
+        })
+    }
+
+    //В порядке binding'ов группы материала: текстура, за ней её сэмплер
+    fn iter(&self) -> [&texture::Texture; 5] {
+        [&self.base_color, &self.metallic_roughness, &self.normal, &self.occlusion, &self.emissive]
+    }
+}
+
+impl Material {
+    //Uniform с множителями, затем пары текстура-сэмплер: base color, metallic-roughness,
+    //normal, occlusion, emissive
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        texture_entry(1),
+        sampler_entry(2),
+        texture_entry(3),
+        sampler_entry(4),
+        texture_entry(5),
+        sampler_entry(6),
+        texture_entry(7),
+        sampler_entry(8),
+        texture_entry(9),
+        sampler_entry(10),
+    ];
+
+    pub fn create_layout(device: &wgpu::Device) -> BindGroupLayout {
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: Self::LAYOUT_ENTRIES,
+                label: Some("material_bind_group_layout"),
+            }
+        )
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        uniform: MaterialUniform,
+        textures: MaterialTextures,
+        layout: &BindGroupLayout,
+    ) -> Self {
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("material_buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            }
+        );
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+        ];
+        for (index, texture) in textures.iter().into_iter().enumerate() {
+            let binding = 1 + index as u32 * 2;
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout,
+                entries: &entries,
+                label: Some(name),
+            }
+        );
+
+        Self { name: String::from(name), uniform, textures, bind_group }
+    }
+
+    //Материал с текстурой res/default.png, для примитивов без материала или без UV
+    pub fn new_default(device: &wgpu::Device, queue: &wgpu::Queue, layout: &BindGroupLayout) -> Result<Self, anyhow::Error> {
+        let textures = MaterialTextures {
+            base_color: Self::default_texture(device, queue, "default_texture")?,
+            ..MaterialTextures::neutral(device, queue)?
+        };
+
+        Ok(Self::new(device, "default_material", MaterialUniform::default(), textures, layout))
+    }
+
+    fn default_texture(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<texture::Texture, anyhow::Error> {
+        let bytes = fs::read(Path::new(RESOURCES_PATH).join(DEFAULT_TEXTURE))?;
+        texture::Texture::from_bytes(device, queue, &bytes, label)
+    }
+
+    //images - прочитанные картинки gltf по индексам, None - картинку прочитать не удалось
+    fn from_gltf(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material: &gltf::Material,
+        images: &[Option<Vec<u8>>],
+        layout: &BindGroupLayout,
+    ) -> Result<Self, anyhow::Error> {
+        let name = material.name().unwrap_or("");
+        let pbr = material.pbr_metallic_roughness();
+
+        //Текстура gltf и номер набора UV для каждой карты
+        let base_color = pbr.base_color_texture().map(|info| (info.texture(), info.tex_coord()));
+        let metallic_roughness = pbr.metallic_roughness_texture().map(|info| (info.texture(), info.tex_coord()));
+        let normal = material.normal_texture().map(|info| (info.texture(), info.tex_coord()));
+        let occlusion = material.occlusion_texture().map(|info| (info.texture(), info.tex_coord()));
+        let emissive = material.emissive_texture().map(|info| (info.texture(), info.tex_coord()));
+
+        //None - карты нет или её картинка не прочиталась
+        let load = |map: Option<(gltf::Texture, u32)>, format: wgpu::TextureFormat| {
+            let Some((texture, tex_coord)) = map else { return Result::Ok(None) };
+            //Вершины хранят только TEXCOORD_0
+            if tex_coord != 0 {
+                log::warn!("material {}: TEXCOORD_{} isn't supported, using TEXCOORD_0", name, tex_coord);
+            }
+            match &images[texture.source().index()] {
+                Some(bytes) => texture::Texture::from_bytes_with_format(device, queue, bytes, name, format).map(Some),
+                None => Result::Ok(None),
+            }
+        };
+        let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+
+        let neutral = MaterialTextures::neutral(device, queue)?;
+        let textures = MaterialTextures {
+            //Картинка указана, но не прочиталась - текстура по умолчанию заметнее белого
+            base_color: match (base_color.is_some(), load(base_color, srgb)?) {
+                (_, Some(texture)) => texture,
+                (true, None) => Self::default_texture(device, queue, name)?,
+                (false, None) => neutral.base_color,
+            },
+            metallic_roughness: load(metallic_roughness, linear)?.unwrap_or(neutral.metallic_roughness),
+            normal: load(normal, linear)?.unwrap_or(neutral.normal),
+            occlusion: load(occlusion, linear)?.unwrap_or(neutral.occlusion),
+            emissive: load(emissive, srgb)?.unwrap_or(neutral.emissive),
+        };
+
+        let alpha_cutoff = match material.alpha_mode() {
+            gltf::material::AlphaMode::Mask => material.alpha_cutoff().unwrap_or(0.5),
+            gltf::material::AlphaMode::Opaque => 0.0,
+            //Прозрачных проходов пока нет, такой материал рисуется непрозрачным
+            gltf::material::AlphaMode::Blend => {
+                log::warn!("material {}: alphaMode BLEND is drawn as OPAQUE", name);
+                0.0
+            },
+        };
+
+        let uniform = MaterialUniform {
+            base_color_factor: pbr.base_color_factor(),
+            emissive_factor: material.emissive_factor(),
+            alpha_cutoff,
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            normal_scale: material.normal_texture().map_or(1.0, |info| info.scale()),
+            occlusion_strength: material.occlusion_texture().map_or(1.0, |info| info.strength()),
+        };
+
+        Ok(Self::new(device, name, uniform, textures, layout))
+    }
 }
 
 impl Model {
-    pub fn new(file_name: &str, position: vmath::Vector3<f32>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, anyhow::Error> {
+    pub fn new(
+        file_name: &str,
+        position: vmath::Vector3<f32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &BindGroupLayout,
+    ) -> Result<Self, anyhow::Error> {
+        Self::with_options(file_name, position, device, queue, material_layout, ModelOptions::default())
+    }
+
+    pub fn with_options(
+        file_name: &str,
+        position: vmath::Vector3<f32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: &BindGroupLayout,
+        options: ModelOptions,
+    ) -> Result<Self, anyhow::Error> {
         let path = Path::new(RESOURCES_PATH).join(file_name);
         let gltf = gltf::Gltf::open(path)?;
 
-        let mut images = Vec::new();
-        let mut materials = Vec::new();
+        //Индексы совпадают с индексами картинок в gltf, None - картинку не удалось прочитать
+        let mut images: Vec<Option<Vec<u8>>> = Vec::new();
+        let mut materials: Vec<Rc<Material>> = Vec::new();
+        let mut default_material: Option<Rc<Material>> = None;
 
-        let mut buffers = Vec::new();
         let mut meshes: Vec<Mesh> = Vec::new();
 
 
@@ -47,38 +380,123 @@ impl Model {
                     uri,
                     ..
                 } => {
-                    let mut file = fs::File::open(Path::new(RESOURCES_PATH).join(uri))?;
-                    let mut buffer = Vec::new();
-                    file.read_to_end(&mut buffer)?;
-                    images.push(buffer);
+                    match fs::read(Path::new(RESOURCES_PATH).join(uri)) {
+                        Result::Ok(buffer) => images.push(Some(buffer)),
+                        Err(err) => {
+                            log::warn!("{}: can't read image {}: {}", file_name, uri, err);
+                            images.push(None);
+                        }
+                    }
                 }
-                _ => {}
+                _ => images.push(None),
             }
         }
 
         for material in gltf.materials() {
-            match material.pbr_metallic_roughness().base_color_texture() {
-                Some(info) => {
-                    let texture = info.texture();
-                    
-                    materials.push(
-                        Material {
-                            texture: texture::Texture::from_bytes(
-                                &device,
-                                &queue,
-                                &images[texture.source().index()],
-                                match texture.name() {
-                                    Some(name) => name,
-                                    None => ""
-                                }
-                            )?
+            materials.push(Rc::new(Material::from_gltf(device, queue, &material, &images, material_layout)?));
+        }
+
+        let buffers = Self::read_buffers(&gltf)?;
+
+
+        //Меши из Model::meshes для каждого меша gltf, по одному на примитив
+        let mut mesh_primitives: Vec<Range<usize>> = Vec::new();
+
+        for mesh in gltf.meshes() {
+            let first = meshes.len();
+            for primitive in mesh.primitives() {
+                let PrimitiveData { vertices, indices, material, bounds } =
+                    Self::read_primitive(file_name, &mesh, &primitive, &buffers, options);
+
+                let vertex_buffer = device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Model vertex buffer"),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }
+                );
+        
+            
+                let index_buffer = device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Model index buffer"),
+                        contents: bytemuck::cast_slice(&indices),
+                        usage: wgpu::BufferUsages::INDEX,
+                    }
+                );
+
+                let material = match material {
+                    Some(index) => materials[index].clone(),
+                    None => match &default_material {
+                        Some(material) => material.clone(),
+                        None => {
+                            let material = Rc::new(Material::new_default(device, queue, material_layout)?);
+                            default_material = Some(material.clone());
+                            material
                         }
-                    )
-                },
-                None => {}
+                    },
+                };
+
+                let positions = vertices.iter().map(|vertex| vertex.position).collect();
+
+                meshes.push(Mesh { indices, positions, vertex_buffer, index_buffer, material, bounds, instances: 0..0 });
+            }
+            mesh_primitives.push(first..meshes.len());
+        }
+
+        if let Some(material) = default_material {
+            materials.push(material);
+        }
+
+        let mut scene = SceneGraph::new();
+        let mut lights = Vec::new();
+        scene.set_root_transform(Transform::from_translation(position));
+        match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+            Some(gltf_scene) => {
+                for node in gltf_scene.nodes() {
+                    Self::add_node(&mut scene, &mut lights, &node, None, &mesh_primitives);
+                }
+            },
+            //Файл без сцен - каждый меш в начале координат
+            None => {
+                for (index, primitives) in mesh_primitives.iter().enumerate() {
+                    let node = scene.add_node(&format!("mesh_{}", index), Transform::identity(), None);
+                    primitives.clone().for_each(|mesh| scene.add_mesh(node, mesh));
+                }
+            },
+        }
+        scene.update();
+
+        //Экземпляры сгруппированы по мешам, чтобы рисовать меш одним вызовом
+        let mut instances = Vec::new();
+        for (index, mesh) in meshes.iter_mut().enumerate() {
+            let first = instances.len() as u32;
+            for (node_index, node) in scene.nodes().iter().enumerate() {
+                for _ in node.meshes.iter().filter(|&&node_mesh| node_mesh == index) {
+                    instances.push(Instance {
+                        mesh: index,
+                        node: node_index,
+                        bounds: mesh.bounds.transform(node.world_matrix()),
+                    });
+                }
             }
+            mesh.instances = first..instances.len() as u32;
         }
 
+        let instance_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Model instance buffer"),
+                contents: bytemuck::cast_slice(&Self::instance_data(&scene, &instances)),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        Ok(Model { meshes, materials, scene, instances, instance_buffer, lights })
+    }
+
+    //Бинарные буферы gltf из файлов рядом с ним, по индексам буферов
+    fn read_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let mut buffers = Vec::new();
         for buffer in gltf.buffers() {
             match buffer.source() {
                 gltf::buffer::Source::Uri(uri) => {
@@ -91,77 +509,227 @@ impl Model {
             }
         }
 
+        Ok(buffers)
+    }
 
-        for mesh in gltf.meshes() {
-            for primitive in mesh.primitives() {
-                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    //Вершины, индексы и материал примитива без обращения к GPU
+    fn read_primitive(
+        file_name: &str,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        buffers: &[Vec<u8>],
+        options: ModelOptions,
+    ) -> PrimitiveData {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
-                let mut positions: Vec<[f32; 3]> = Vec::new();
-                let mut tex_coords: Vec<[f32; 2]> = Vec::new();
-                let mut indices: Vec<u32> = Vec::new();
-                let mut vertices: Vec<Vertex> = Vec::new();
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut tangents: Vec<[f32; 4]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut vertices: Vec<Vertex> = Vec::new();
 
-                if let Some(read_indices) = reader.read_indices() {
-                    indices = read_indices.into_u32().collect::<Vec<_>>();
-                }
+        if let Some(iter) = reader.read_positions() {
+            positions = iter.collect::<Vec<_>>();
+        }
 
-                if let Some(iter) = reader.read_positions() {
-                    positions = iter.collect::<Vec<_>>();
-                }
+        if let Some(read_indices) = reader.read_indices() {
+            indices = read_indices.into_u32().collect::<Vec<_>>();
+        } else {
+            indices.extend(0..positions.len() as u32);
+        }
 
-                if let Some(read_tex_coords) = reader.read_tex_coords(0) {
-                    tex_coords = read_tex_coords.into_f32().collect::<Vec<_>>();
-                }
+        if let Some(read_tex_coords) = reader.read_tex_coords(0) {
+            tex_coords = read_tex_coords.into_f32().collect::<Vec<_>>();
+        }
 
-                for i in 0..positions.len() {
-                    vertices.push(Vertex {
-                        position: [
-                            positions[i][0] * -1.0 + position.x, 
-                            positions[i][1] + position.y, 
-                            positions[i][2] + position.z,
-                        ],
-                        tex_coords: tex_coords[i],
-                    });
-                }
+        if let Some(read_normals) = reader.read_normals() {
+            normals = read_normals.collect::<Vec<_>>();
+        }
 
-                let vertex_buffer = device.create_buffer_init(
-                    &wgpu::util::BufferInitDescriptor {
-                        label: Some("Model vertex buffer"),
-                        contents: bytemuck::cast_slice(&vertices),
-                        usage: wgpu::BufferUsages::VERTEX,
+        if let Some(read_tangents) = reader.read_tangents() {
+            tangents = read_tangents.collect::<Vec<_>>();
+        }
+
+        //Нормали и касательные достраиваются в координатах glTF, до отражения x
+        if normals.is_empty() {
+            match options.normals {
+                NormalMode::Flat => {
+                    //У каждого треугольника свои вершины, иначе нормали усреднятся
+                    positions = tangent_space::unindex(&positions, &indices);
+                    if !tex_coords.is_empty() {
+                        tex_coords = tangent_space::unindex(&tex_coords, &indices);
                     }
-                );
-        
-            
-                let index_buffer = device.create_buffer_init(
-                    &wgpu::util::BufferInitDescriptor {
-                        label: Some("Model index buffer"),
-                        contents: bytemuck::cast_slice(&indices),
-                        usage: wgpu::BufferUsages::INDEX,
+                    if !tangents.is_empty() {
+                        tangents = tangent_space::unindex(&tangents, &indices);
                     }
-                );
+                    indices = (0..positions.len() as u32).collect();
+                    normals = tangent_space::flat_normals(&positions);
+                },
+                NormalMode::Smooth => normals = tangent_space::smooth_normals(&positions, &indices),
+            }
+        }
 
-                let material_index = primitive
-                    .material().index().unwrap();
+        //Касательные нужны только под normal map, и без UV их не построить
+        let has_normal_map = primitive.material().normal_texture().is_some();
+        if tangents.is_empty() && has_normal_map && !tex_coords.is_empty() {
+            //Вершины на швах UV при этом могут размножиться
+            match tangent_space::tangents(&positions, &normals, &tex_coords, &indices) {
+                Some(mesh) => {
+                    positions = mesh.positions;
+                    normals = mesh.normals;
+                    tex_coords = mesh.tex_coords;
+                    tangents = mesh.tangents;
+                    indices = mesh.indices;
+                },
+                None => log::warn!("{}: can't generate tangents for mesh {}", file_name, mesh.index()),
+            }
+        }
 
-                
+        for i in 0..positions.len() {
+            let normal = normals[i];
+            let tangent = tangents.get(i).copied().unwrap_or([1.0, 0.0, 0.0, 1.0]);
 
-                //meshes.push(Mesh { indices, vertex_buffer, index_buffer, material: materials[material_index] });
-            }
+            //При отражении x битангенс cross(normal, tangent) меняет знак, w это компенсирует
+            vertices.push(Vertex {
+                position: [
+                    positions[i][0] * -1.0,
+                    positions[i][1],
+                    positions[i][2],
+                ],
+                tex_coords: tex_coords.get(i).copied().unwrap_or([0.0, 0.0]),
+                normal: [-normal[0], normal[1], normal[2]],
+                tangent: [-tangent[0], tangent[1], tangent[2], -tangent[3]],
+            });
         }
 
-        /* let diffuse_texture = texture::Texture::from_bytes(
-            &device,
-            &queue,
-            &images[4],
-            &format!("{}_diffuse", file_name)
-        )?;
+        //Без материала или без UV текстуру не натянуть - берём материал по умолчанию
+        let material = primitive.material().index().filter(|_| !tex_coords.is_empty());
+
+        //min/max из аксессора необязательны, без них считаем по вершинам
+        let bounds = match Self::accessor_bounds(primitive) {
+            Some(bounds) => bounds,
+            None => {
+                let origin = vmath::Vector3::new(0.0, 0.0, 0.0);
+                vmath::Aabb::from_points(
+                    vertices.iter().map(|vertex| vertex.position.into())
+                ).unwrap_or(vmath::Aabb::new(origin, origin))
+            },
+        };
+
+        PrimitiveData { vertices, indices, material, bounds }
+    }
+
+    //Узел gltf с потомками. Как и вершины, трансформация отражается по x
+    fn add_node(
+        scene: &mut SceneGraph,
+        lights: &mut Vec<(usize, lights::Light)>,
+        node: &gltf::Node,
+        parent: Option<usize>,
+        mesh_primitives: &[Range<usize>],
+    ) {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let transform = Transform {
+            translation: vmath::Vector3::new(-translation[0], translation[1], translation[2]),
+            //Отражение меняет направление вращения вокруг y и z
+            rotation: vmath::Quaternion::new(rotation[0], -rotation[1], -rotation[2], rotation[3]),
+            scale: vmath::Vector3::new(scale[0], scale[1], scale[2]),
+        };
+
+        let index = scene.add_node(node.name().unwrap_or(""), transform, parent);
+        if let Some(mesh) = node.mesh() {
+            mesh_primitives[mesh.index()].clone().for_each(|primitive| scene.add_mesh(index, primitive));
+        }
+        //Направление -z узла при отражении x не меняется, отражать сам источник не нужно
+        if let Some(light) = node.light() {
+            lights.push((index, lights::Light::from_gltf(&light)));
+        }
+
+        for child in node.children() {
+            Self::add_node(scene, lights, &child, Some(index), mesh_primitives);
+        }
+    }
+
+    fn instance_data(scene: &SceneGraph, instances: &[Instance]) -> Vec<InstanceRaw> {
+        instances.iter()
+            .map(|instance| InstanceRaw { model: scene.node(instance.node).world_matrix().into() })
+            .collect()
+    }
+
+    pub fn scene(&self) -> &SceneGraph {
+        &self.scene
+    }
+
+    //Изменения узлов попадут на GPU после update
+    pub fn scene_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    //Источники света модели в мировых координатах на момент последнего update
+    pub fn lights(&self) -> Vec<lights::Light> {
+        self.lights.iter()
+            .map(|(node, light)| light.transformed(self.scene.node(*node).world_matrix()))
+            .collect()
+    }
+
+    pub fn position(&self) -> vmath::Vector3<f32> {
+        self.scene.root_transform().translation
+    }
+
+    pub fn set_position(&mut self, position: vmath::Vector3<f32>) {
+        self.scene.set_root_transform(Transform { translation: position, ..self.scene.root_transform() });
+    }
+
+    //Пересчитывает мировые матрицы и перезаливает буфер экземпляров. Вершинные буферы не трогаются
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        if !self.scene.update() {
+            return;
+        }
+
+        for instance in &mut self.instances {
+            instance.bounds = self.meshes[instance.mesh].bounds.transform(self.scene.node(instance.node).world_matrix());
+        }
+        if !self.instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&Self::instance_data(&self.scene, &self.instances)));
+        }
+    }
+
+    //Общие мировые границы всех экземпляров, None для модели без них
+    pub fn bounds(&self) -> Option<vmath::Aabb<f32>> {
+        self.instances.iter()
+            .map(|instance| instance.bounds)
+            .reduce(|bounds, other| bounds.union(other))
+    }
+
+    pub fn is_visible(&self, instance: usize, frustum: &vmath::Frustum<f32>) -> bool {
+        frustum.intersects_aabb(self.instances[instance].bounds)
+    }
 
-        let material = Material {
-            texture: diffuse_texture,
-        }; */
+    //Границы из min/max аксессора POSITION, с тем же отражением x, что и у вершин
+    fn accessor_bounds(primitive: &gltf::Primitive) -> Option<vmath::Aabb<f32>> {
+        let accessor = primitive.get(&gltf::Semantic::Positions)?;
+        let read = |value: gltf::json::Value| -> Option<[f32; 3]> {
+            let array = value.as_array()?;
+            if array.len() != 3 {
+                return None;
+            }
+            Some([
+                array[0].as_f64()? as f32,
+                array[1].as_f64()? as f32,
+                array[2].as_f64()? as f32,
+            ])
+        };
+        let min = read(accessor.min()?)?;
+        let max = read(accessor.max()?)?;
 
-        Ok(Model { position, meshes})
+        Some(vmath::Aabb::new(
+            vmath::Vector3::new(-max[0], min[1], min[2]),
+            vmath::Vector3::new(-min[0], max[1], max[2]),
+        ))
     }
 
     pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -179,21 +747,245 @@ impl Model {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+
+    //Матрица 4x4 передаётся четырьмя столбцами в location 5-8
+    pub fn instance_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const COLUMN: wgpu::BufferAddress = mem::size_of::<[f32; 4]>() as wgpu::BufferAddress;
+
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: COLUMN,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: COLUMN * 2,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: COLUMN * 3,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
+// Сколько мешей нарисовано и сколько отброшено отсечением по пирамиде видимости
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CullStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+impl std::ops::AddAssign for CullStats {
+    fn add_assign(&mut self, other: Self) {
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+    }
+}
+
 pub trait DrawModel<'a> {
-    fn draw_model(&mut self, model: &'a Model);
+    //Рисует только экземпляры, чьи границы пересекают frustum
+    fn draw_model_culled(&mut self, model: &'a Model, frustum: &vmath::Frustum<f32>) -> CullStats;
 }
 
 impl<'a> DrawModel<'a> for RenderPass<'a> {
-    fn draw_model(&mut self, model: &'a Model) {
+    fn draw_model_culled(&mut self, model: &'a Model, frustum: &vmath::Frustum<f32>) -> CullStats {
+        let mut stats = CullStats::default();
+
         for mesh in &model.meshes {
-            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-            self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            self.draw_indexed(0..mesh.indices.len() as _ , 0, 0..1);
+            //Видимые экземпляры подряд рисуются одним вызовом
+            let mut visible = mesh.instances.start..mesh.instances.start;
+            for instance in mesh.instances.clone() {
+                if model.is_visible(instance as usize, frustum) {
+                    stats.drawn += 1;
+                    visible.end = instance + 1;
+                    continue;
+                }
+
+                stats.culled += 1;
+                draw_mesh(self, model, mesh, visible);
+                visible = instance + 1..instance + 1;
+            }
+            draw_mesh(self, model, mesh, visible);
+        }
+
+        stats
+    }
+}
+
+fn draw_mesh<'a>(render_pass: &mut RenderPass<'a>, model: &'a Model, mesh: &'a Mesh, instances: Range<u32>) {
+    if instances.is_empty() {
+        return;
+    }
+
+    render_pass.set_bind_group(1, &mesh.material.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    render_pass.set_vertex_buffer(1, model.instance_buffer.slice(..));
+    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    render_pass.draw_indexed(0..mesh.indices.len() as _ , 0, instances);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    //Сборка примитивов без GPU, по мешам gltf
+    fn read(file_name: &str) -> (gltf::Gltf, Vec<Vec<PrimitiveData>>) {
+        let gltf = gltf::Gltf::open(Path::new(RESOURCES_PATH).join(file_name)).unwrap();
+        let buffers = Model::read_buffers(&gltf).unwrap();
+        let meshes = gltf.meshes()
+            .map(|mesh| mesh.primitives()
+                .map(|primitive| Model::read_primitive(file_name, &mesh, &primitive, &buffers, ModelOptions::default()))
+                .collect())
+            .collect();
+
+        (gltf, meshes)
+    }
+
+    fn load(test: &str, file_name: &str) -> Option<(Model, wgpu::Queue)> {
+        let (device, queue) = crate::with_test_adapter(test, crate::Renderer::request_headless_device)?;
+        let material_layout = Material::create_layout(&device);
+
+        let model = Model::new(
+            file_name,
+            vmath::Vector3::new(0.0, 0.0, 0.0),
+            &device,
+            &queue,
+            &material_layout,
+        ).unwrap();
+
+        Some((model, queue))
+    }
+
+    #[test]
+    fn box_meshes() {
+        let (gltf, meshes) = read("box_1x1.gltf");
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].len(), 1);
+        assert_eq!(meshes[0][0].indices.len(), 36);
+        assert_eq!(meshes[0][0].vertices.len(), 24);
+        assert_eq!(meshes[0][0].material, Some(0));
+        assert_eq!(gltf.materials().len(), 1);
+    }
+
+    #[test]
+    fn box_material() {
+        let Some((model, _)) = load("box_material", "box_1x1.gltf") else { return };
+
+        //Картинки текстуры нет в res, материал остаётся с текстурой по умолчанию
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.materials.len(), 1);
+        assert!(Rc::ptr_eq(&model.meshes[0].material, &model.materials[0]));
+    }
+
+    #[test]
+    fn toy_car_meshes() {
+        let (gltf, meshes) = read("toy_car.gltf");
+
+        let primitives: Vec<&PrimitiveData> = meshes.iter().flatten().collect();
+        assert_eq!(primitives.len(), 2);
+        assert_eq!(primitives[0].indices.len(), 266511);
+        assert_eq!(primitives[1].indices.len(), 7482);
+        let material_name = |primitive: &PrimitiveData| {
+            gltf.materials().nth(primitive.material.unwrap()).unwrap().name().map(str::to_owned)
+        };
+        assert_eq!(material_name(primitives[0]).as_deref(), Some("ToyCar"));
+        assert_eq!(material_name(primitives[1]).as_deref(), Some("Glass"));
+        //Индексы не выходят за вершины, у кузова с normal map касательные достроены
+        for primitive in &primitives {
+            assert!(primitive.indices.iter().all(|&index| (index as usize) < primitive.vertices.len()));
+        }
+        assert!(primitives[0].vertices.iter().any(|vertex| vertex.tangent != [-1.0, 0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn toy_car_materials() {
+        let Some((model, _)) = load("toy_car_materials", "toy_car.gltf") else { return };
+
+        //Все карты кузова взяты из картинок, а не подставлены 1x1
+        let car = &model.materials[0];
+        assert_eq!(car.uniform.emissive_factor, [1.0, 1.0, 1.0]);
+        assert_eq!(car.uniform.metallic_factor, 1.0);
+        for texture in car.textures.iter() {
+            assert!(texture.texture.width() > 1);
+        }
+        assert_eq!(car.textures.base_color.texture.format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(car.textures.normal.texture.format(), wgpu::TextureFormat::Rgba8Unorm);
+
+        //У стекла только множители
+        let glass = &model.materials[1];
+        assert_eq!(glass.uniform, MaterialUniform {
+            base_color_factor: [0.3, 0.8, 0.3, 1.0],
+            metallic_factor: 0.0,
+            roughness_factor: 0.0,
+            ..MaterialUniform::default()
+        });
+        assert_eq!(glass.textures.base_color.texture.width(), 1);
+    }
+
+    #[test]
+    fn box_bounds_and_culling() {
+        let Some((mut model, queue)) = load("box_bounds_and_culling", "box_1x1.gltf") else { return };
+
+        //Меш хранится в своих координатах, узел сдвигает его на 11 по x (с отражением x)
+        let half = vmath::Vector3::new(0.5, 0.5, 0.5);
+        let offset = vmath::Vector3::new(-11.0, 0.0, 0.0);
+        assert_eq!(model.meshes[0].bounds, vmath::Aabb::new(-half, half));
+        assert_eq!(model.bounds(), Some(vmath::Aabb::new(offset - half, offset + half)));
+
+        let frustum = |position: vmath::Vector3<f32>, direction: vmath::Vector3<f32>| {
+            vmath::Frustum::from_view_proj(
+                vmath::Matrix4x4::new_perspective(1.0, 1.0, 0.1, 100.0, 60.0)
+                    * vmath::Matrix4x4::new_look_at(position, direction)
+            )
+        };
+        let at_origin = frustum(vmath::Vector3::new(0.0, 0.0, -3.0), vmath::Vector3::unit_z());
+        assert!(model.is_visible(0, &frustum(offset + vmath::Vector3::new(0.0, 0.0, -3.0), vmath::Vector3::unit_z())));
+        assert!(!model.is_visible(0, &frustum(offset + vmath::Vector3::new(0.0, 0.0, -3.0), -vmath::Vector3::unit_z())));
+        assert!(!model.is_visible(0, &at_origin));
+
+        //Перемещение модели меняет только матрицы экземпляров
+        model.set_position(-offset);
+        model.update(&queue);
+        assert!(model.is_visible(0, &at_origin));
+        assert_eq!(model.bounds(), Some(vmath::Aabb::new(-half, half)));
+    }
+
+    #[test]
+    fn toy_car_scene() {
+        let Some((model, _)) = load("toy_car_scene", "toy_car.gltf") else { return };
+
+        let scene = model.scene();
+        assert_eq!(scene.roots().len(), 2);
+        assert_eq!(scene.node(scene.find("Glass").unwrap()).meshes, vec![1]);
+        assert_eq!(model.instances().len(), 2);
+        for (index, mesh) in model.meshes.iter().enumerate() {
+            assert_eq!(mesh.instances, index as u32..index as u32 + 1);
         }
     }
-}
\ No newline at end of file
+}
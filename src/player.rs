@@ -0,0 +1,515 @@
+/*
+    Контроллер игрока для CameraMode::Player: капсула с гравитацией, прыжком и приседанием,
+    сталкивается с плоскостью земли и треугольниками уровня.
+    Физика считается фиксированными шагами FIXED_STEP, поэтому результат не зависит
+    от того, какими кусками пришло время кадров.
+*/
+
+use instant::Duration;
+
+use crate::{model, vmath::{Aabb, Plane, Vector3}};
+
+pub const FIXED_STEP: Duration = Duration::from_nanos(1_000_000_000 / 120);
+
+//Поверхность с нормалью круче этого считается стеной, а не полом
+const GROUND_NORMAL_Y: f32 = 0.7;
+const MAX_COLLISION_ITERATIONS: usize = 4;
+const EPSILON: f32 = 1e-6;
+//Касания мельче этого не мешают встать: после выталкивания капсула остаётся вплотную к стенам
+const STAND_TOLERANCE: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerSettings {
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub crouch_speed: f32,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    pub radius: f32,
+    //Высота капсулы от ступней до макушки
+    pub height: f32,
+    pub crouch_height: f32,
+    pub eye_height: f32,
+    pub crouch_eye_height: f32,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            walk_speed: 4.0,
+            run_speed: 8.0,
+            crouch_speed: 2.0,
+            jump_speed: 5.0,
+            gravity: 9.81,
+            radius: 0.3,
+            height: 1.8,
+            crouch_height: 1.1,
+            eye_height: 1.7,
+            crouch_eye_height: 1.0,
+        }
+    }
+}
+
+// forward и right в [-1, 1], направление берётся от yaw камеры
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PlayerInput {
+    pub forward: f32,
+    pub right: f32,
+    pub jump: bool,
+    pub crouch: bool,
+    pub run: bool,
+}
+
+//Треугольники меша и их бокс
+type Chunk = (Aabb<f32>, Vec<[Vector3<f32>; 3]>);
+
+// Геометрия, с которой сталкивается игрок
+#[derive(Debug, Default)]
+pub struct Level {
+    pub ground: Option<Plane<f32>>,
+    //Треугольники сгруппированы по мешам, бокс меша отсекает их разом
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug)]
+pub struct Player {
+    pub settings: PlayerSettings,
+    pub feet: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub grounded: bool,
+    pub crouched: bool,
+    accumulator: Duration,
+}
+
+impl Level {
+    pub fn new(ground: Option<Plane<f32>>) -> Self {
+        Self { ground, chunks: Vec::new() }
+    }
+
+    pub fn add_triangles(&mut self, triangles: Vec<[Vector3<f32>; 3]>) {
+        let bounds = Aabb::from_points(triangles.iter().flatten().copied());
+        if let Some(bounds) = bounds {
+            self.chunks.push((bounds, triangles));
+        }
+    }
+
+    //Треугольники каждого экземпляра в мировых координатах на момент вызова
+    pub fn add_model(&mut self, model: &model::Model) {
+        for instance in model.instances() {
+            let mesh = &model.meshes[instance.mesh];
+            let world = model.scene().node(instance.node).world_matrix();
+            let triangles = mesh.indices.chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|index| world * Vector3::from(mesh.positions[index as usize])))
+                .collect();
+            self.add_triangles(triangles);
+        }
+    }
+
+    //Вызывает contact(нормаль, глубина) для каждого касания капсулы base-tip радиуса radius
+    fn contacts(&self, base: Vector3<f32>, tip: Vector3<f32>, radius: f32, mut contact: impl FnMut(Vector3<f32>, f32)) {
+        if let Some(ground) = self.ground {
+            let distance = ground.distance(base).min(ground.distance(tip));
+            if distance < radius {
+                contact(ground.normal, radius - distance);
+            }
+        }
+
+        let extents = Vector3::new(radius, radius, radius);
+        let capsule = Aabb::new(base.min(tip) - extents, base.max(tip) + extents);
+
+        for (bounds, triangles) in &self.chunks {
+            if !bounds.intersects(capsule) {
+                continue;
+            }
+            for &[a, b, c] in triangles {
+                if let Some((normal, depth)) = capsule_triangle(base, tip, radius, a, b, c) {
+                    contact(normal, depth);
+                }
+            }
+        }
+    }
+}
+
+impl Player {
+    pub fn new(feet: Vector3<f32>, settings: PlayerSettings) -> Self {
+        Self {
+            settings,
+            feet,
+            velocity: Vector3::zero(),
+            grounded: false,
+            crouched: false,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn eye_position(&self) -> Vector3<f32> {
+        let eye_height = if self.crouched { self.settings.crouch_eye_height } else { self.settings.eye_height };
+        self.feet + Vector3::unit_y() * eye_height
+    }
+
+    //Переносит игрока, сбрасывая скорость и накопленное время
+    pub fn teleport(&mut self, feet: Vector3<f32>) {
+        self.feet = feet;
+        self.velocity = Vector3::zero();
+        self.grounded = false;
+        self.accumulator = Duration::ZERO;
+    }
+
+    //Прогоняет столько фиксированных шагов, сколько влезает в накопленное время
+    pub fn update(&mut self, delta_time: Duration, yaw: f32, input: &PlayerInput, level: &Level) {
+        self.accumulator += delta_time;
+        while self.accumulator >= FIXED_STEP {
+            self.accumulator -= FIXED_STEP;
+            self.step(yaw, input, level);
+        }
+    }
+
+    pub fn step(&mut self, yaw: f32, input: &PlayerInput, level: &Level) {
+        let dt = FIXED_STEP.as_secs_f32();
+        let settings = self.settings;
+
+        if input.crouch {
+            self.crouched = true;
+        } else if self.crouched && self.can_stand(level) {
+            self.crouched = false;
+        }
+
+        //В воздухе горизонтальная скорость сохраняется
+        if self.grounded {
+            let speed = if self.crouched {
+                settings.crouch_speed
+            } else if input.run {
+                settings.run_speed
+            } else {
+                settings.walk_speed
+            };
+
+            let (yaw_sin, yaw_cos) = yaw.sin_cos();
+            let forward = Vector3::new(yaw_sin, 0.0, yaw_cos);
+            let right = Vector3::unit_y().cross(forward);
+            let wish = (forward * input.forward + right * input.right).try_normalize().unwrap_or(Vector3::zero());
+
+            self.velocity.x = wish.x * speed;
+            self.velocity.z = wish.z * speed;
+
+            if input.jump && !self.crouched {
+                self.velocity.y = settings.jump_speed;
+            }
+        }
+
+        self.velocity.y -= settings.gravity * dt;
+        self.feet += self.velocity * dt;
+        self.resolve_collisions(level);
+    }
+
+    fn height(&self) -> f32 {
+        if self.crouched { self.settings.crouch_height } else { self.settings.height }
+    }
+
+    //Центры нижней и верхней сфер капсулы
+    fn capsule(&self, height: f32) -> (Vector3<f32>, Vector3<f32>) {
+        let radius = self.settings.radius;
+        let base = self.feet + Vector3::unit_y() * radius;
+        let tip = self.feet + Vector3::unit_y() * (height - radius).max(radius);
+        (base, tip)
+    }
+
+    //Встать можно, если часть капсулы над присевшим игроком ничего не задевает
+    fn can_stand(&self, level: &Level) -> bool {
+        let (_, base) = self.capsule(self.settings.crouch_height);
+        let (_, tip) = self.capsule(self.settings.height);
+        let mut blocked = false;
+        level.contacts(base, tip, self.settings.radius, |_, depth| blocked |= depth > STAND_TOLERANCE);
+
+        !blocked
+    }
+
+    //Выталкивает капсулу из геометрии и гасит скорость вдоль нормалей касаний
+    fn resolve_collisions(&mut self, level: &Level) {
+        self.grounded = false;
+
+        for _ in 0..MAX_COLLISION_ITERATIONS {
+            let (base, tip) = self.capsule(self.height());
+            //Самое глубокое касание за проход, остальные разберутся на следующих итерациях
+            let mut deepest: Option<(Vector3<f32>, f32)> = None;
+            level.contacts(base, tip, self.settings.radius, |normal, depth| {
+                if deepest.is_none_or(|(_, deepest)| depth > deepest) {
+                    deepest = Some((normal, depth));
+                }
+            });
+
+            let Some((normal, depth)) = deepest else { break };
+            self.feet += normal * depth;
+
+            if normal.y >= GROUND_NORMAL_Y {
+                self.grounded = true;
+            }
+            let into = self.velocity.dot(normal);
+            if into < 0.0 {
+                self.velocity -= normal * into;
+            }
+        }
+    }
+}
+
+// Ближайшая к p точка треугольника (Ericson, Real-Time Collision Detection 5.1.5)
+fn closest_point_on_triangle(p: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+fn closest_point_on_segment(p: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+    let ab = b - a;
+    let length_squared = ab.dot(ab);
+    if length_squared <= EPSILON {
+        return a;
+    }
+
+    a + ab * ((p - a).dot(ab) / length_squared).clamp(0.0, 1.0)
+}
+
+// Нормаль выталкивания капсулы и глубина проникновения. Ось капсулы пересекается
+// с плоскостью треугольника, ближайшая к пересечению точка треугольника задаёт
+// сферу на оси, которая дальше проверяется как обычная сфера
+fn capsule_triangle(
+    base: Vector3<f32>,
+    tip: Vector3<f32>,
+    radius: f32,
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    c: Vector3<f32>,
+) -> Option<(Vector3<f32>, f32)> {
+    let normal = (b - a).cross(c - a).try_normalize()?;
+    let axis = tip - base;
+
+    let along = normal.dot(axis);
+    let reference = if along.abs() > EPSILON {
+        let t = normal.dot(a - base) / along;
+        closest_point_on_triangle(base + axis * t, a, b, c)
+    } else {
+        closest_point_on_triangle(base, a, b, c)
+    };
+
+    let center = closest_point_on_segment(reference, base, tip);
+    let offset = center - closest_point_on_triangle(center, a, b, c);
+    let distance = offset.length();
+    if distance >= radius {
+        return None;
+    }
+
+    //Центр сферы прямо на треугольнике - выталкиваем по нормали в сторону капсулы
+    let push = if distance > EPSILON {
+        offset / distance
+    } else if normal.dot(center - a) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+
+    Some((push, radius - distance))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3::new(x, y, z)
+    }
+
+    fn flat_level() -> Level {
+        Level::new(Some(Plane::new(Vector3::unit_y(), 0.0)))
+    }
+
+    //Квадрат из двух треугольников
+    fn quad(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>, d: Vector3<f32>) -> Vec<[Vector3<f32>; 3]> {
+        vec![[a, b, c], [a, c, d]]
+    }
+
+    fn run(player: &mut Player, seconds: u32, input: &PlayerInput, level: &Level) {
+        for _ in 0..seconds * 120 {
+            player.step(0.0, input, level);
+        }
+    }
+
+    #[test]
+    fn falls_and_lands_on_ground() {
+        let level = flat_level();
+        let mut player = Player::new(v(0.0, 5.0, 0.0), PlayerSettings::default());
+
+        run(&mut player, 2, &PlayerInput::default(), &level);
+
+        assert!(player.grounded);
+        assert!(player.feet.y.abs() < 1e-2, "{:?}", player.feet);
+        assert!((player.eye_position().y - player.settings.eye_height).abs() < 1e-2);
+    }
+
+    #[test]
+    fn jump_rises_and_lands() {
+        let level = flat_level();
+        let mut player = Player::new(v(0.0, 0.0, 0.0), PlayerSettings::default());
+        run(&mut player, 1, &PlayerInput::default(), &level);
+
+        let jump = PlayerInput { jump: true, ..Default::default() };
+        player.step(0.0, &jump, &level);
+        assert!(!player.grounded);
+
+        //Пик v²/2g
+        let mut peak: f32 = 0.0;
+        for _ in 0..240 {
+            player.step(0.0, &PlayerInput::default(), &level);
+            peak = peak.max(player.feet.y);
+        }
+        let expected = player.settings.jump_speed.powi(2) / (2.0 * player.settings.gravity);
+        assert!((peak - expected).abs() < 0.05, "{} vs {}", peak, expected);
+        assert!(player.grounded);
+    }
+
+    #[test]
+    fn walk_and_run_speeds() {
+        let level = flat_level();
+        let forward = PlayerInput { forward: 1.0, ..Default::default() };
+        let running = PlayerInput { run: true, ..forward };
+
+        let mut player = Player::new(v(0.0, 0.0, 0.0), PlayerSettings::default());
+        run(&mut player, 1, &PlayerInput::default(), &level);
+        let start = player.feet;
+        run(&mut player, 1, &forward, &level);
+        assert!((player.feet.z - start.z - player.settings.walk_speed).abs() < 0.05);
+        run(&mut player, 1, &running, &level);
+        assert!((player.feet.z - start.z - player.settings.walk_speed - player.settings.run_speed).abs() < 0.05);
+        assert!(player.feet.x.abs() < 1e-4);
+
+        //Направление берётся от yaw
+        let mut player = Player::new(v(0.0, 0.0, 0.0), PlayerSettings::default());
+        for _ in 0..120 {
+            player.step(std::f32::consts::FRAC_PI_2, &forward, &level);
+        }
+        assert!(player.feet.x > 3.0 && player.feet.z.abs() < 1e-3);
+    }
+
+    #[test]
+    fn wall_blocks_movement() {
+        let mut level = flat_level();
+        level.add_triangles(quad(v(-5.0, 0.0, 2.0), v(-5.0, 3.0, 2.0), v(5.0, 3.0, 2.0), v(5.0, 0.0, 2.0)));
+
+        let mut player = Player::new(v(0.0, 0.0, 0.0), PlayerSettings::default());
+        run(&mut player, 3, &PlayerInput { forward: 1.0, ..Default::default() }, &level);
+
+        assert!((player.feet.z - (2.0 - player.settings.radius)).abs() < 1e-2, "{:?}", player.feet);
+        assert!(player.grounded);
+    }
+
+    #[test]
+    fn stands_on_triangles_and_walks_off() {
+        //Платформа высотой 1 над землёй
+        let mut level = flat_level();
+        level.add_triangles(quad(v(-1.0, 1.0, -1.0), v(-1.0, 1.0, 1.0), v(1.0, 1.0, 1.0), v(1.0, 1.0, -1.0)));
+
+        let mut player = Player::new(v(0.0, 3.0, 0.0), PlayerSettings::default());
+        run(&mut player, 1, &PlayerInput::default(), &level);
+        assert!(player.grounded);
+        assert!((player.feet.y - 1.0).abs() < 1e-2);
+
+        run(&mut player, 2, &PlayerInput { forward: 1.0, ..Default::default() }, &level);
+        assert!(player.feet.y.abs() < 1e-2);
+    }
+
+    #[test]
+    fn crouch_under_ceiling() {
+        let mut level = flat_level();
+        level.add_triangles(quad(v(-5.0, 1.4, -5.0), v(5.0, 1.4, -5.0), v(5.0, 1.4, 5.0), v(-5.0, 1.4, 5.0)));
+
+        let mut player = Player::new(v(0.0, 0.0, -10.0), PlayerSettings::default());
+        let crouch = PlayerInput { crouch: true, forward: 1.0, ..Default::default() };
+        run(&mut player, 1, &PlayerInput::default(), &level);
+        run(&mut player, 3, &crouch, &level);
+        assert!(player.feet.z > -4.0 && player.feet.z < 4.0, "{:?}", player.feet);
+        assert!((player.eye_position().y - player.settings.crouch_eye_height).abs() < 1e-2);
+
+        //Под потолком встать нельзя, а снаружи можно
+        player.step(0.0, &PlayerInput::default(), &level);
+        assert!(player.crouched);
+        run(&mut player, 2, &PlayerInput { forward: 1.0, ..Default::default() }, &level);
+        assert!(player.crouched && player.feet.z < 4.0);
+        run(&mut player, 3, &PlayerInput { forward: 1.0, ..Default::default() }, &level);
+        assert!(player.feet.z > 5.0 && !player.crouched, "{:?}", player.feet);
+    }
+
+    #[test]
+    fn fixed_timestep_is_deterministic() {
+        let mut level = flat_level();
+        level.add_triangles(quad(v(-5.0, 0.0, 2.0), v(-5.0, 3.0, 2.0), v(5.0, 3.0, 2.0), v(5.0, 0.0, 2.0)));
+        let input = PlayerInput { forward: 1.0, right: 0.5, jump: true, ..Default::default() };
+
+        let mut a = Player::new(v(0.0, 2.0, 0.0), PlayerSettings::default());
+        let mut b = Player::new(v(0.0, 2.0, 0.0), PlayerSettings::default());
+        for _ in 0..50 {
+            a.update(Duration::from_millis(20), 0.3, &input, &level);
+        }
+        for frame in 0..40 {
+            //Неровные кадры
+            let delta = if frame % 2 == 0 { 10 } else { 40 };
+            b.update(Duration::from_millis(delta), 0.3, &input, &level);
+        }
+
+        assert_eq!(a.feet, b.feet);
+        assert_eq!(a.velocity, b.velocity);
+    }
+
+    #[test]
+    fn capsule_triangle_edge_cases() {
+        let (a, b, c) = (v(-1.0, 0.0, -1.0), v(0.0, 0.0, 1.0), v(1.0, 0.0, -1.0));
+
+        //Вертикальная капсула стоит на треугольнике
+        let (normal, depth) = capsule_triangle(v(0.0, 0.2, 0.0), v(0.0, 1.0, 0.0), 0.3, a, b, c).unwrap();
+        assert!((normal - Vector3::unit_y()).length() < 1e-5);
+        assert!((depth - 0.1).abs() < 1e-5);
+
+        //Рядом с ребром, но не касается
+        assert!(capsule_triangle(v(0.0, 0.2, 1.5), v(0.0, 1.0, 1.5), 0.3, a, b, c).is_none());
+        //Ось параллельна плоскости треугольника
+        assert!(capsule_triangle(v(-1.0, 0.2, 0.0), v(1.0, 0.2, 0.0), 0.3, a, b, c).is_some());
+        //Вырожденный треугольник
+        assert!(capsule_triangle(v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0), 0.3, a, a, c).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::texture::DepthMode;
+use crate::vmath::{Matrix4x4, Vector4};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionKind {
+    Perspective,
+    //Обратный z, дальняя плоскость в бесконечности (far = f32::INFINITY)
+    PerspectiveReversedZ,
+    //height - видимая высота в мировых единицах, ширина следует из aspect
+    Orthographic { height: f32 },
+}
+
+// Проекция камеры. Размер цели рендера меняется через resize, остальное - напрямую
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    pub kind: ProjectionKind,
+    //В градусах, для ортографической не используется
+    pub fovy: f32,
+    pub near: f32,
+    pub far: f32,
+    pub aspect: f32,
+}
+
+impl Projection {
+    pub fn new_perspective(fovy: f32, width: u32, height: u32, near: f32, far: f32) -> Self {
+        let mut projection = Self { kind: ProjectionKind::Perspective, fovy, near, far, aspect: 1.0 };
+        projection.resize(width, height);
+        projection
+    }
+
+    pub fn new_perspective_reversed_z(fovy: f32, width: u32, height: u32, near: f32) -> Self {
+        let mut projection = Self {
+            kind: ProjectionKind::PerspectiveReversedZ,
+            fovy,
+            near,
+            far: f32::INFINITY,
+            aspect: 1.0,
+        };
+        projection.resize(width, height);
+        projection
+    }
+
+    pub fn new_orthographic(view_height: f32, width: u32, height: u32, near: f32, far: f32) -> Self {
+        let mut projection = Self {
+            kind: ProjectionKind::Orthographic { height: view_height },
+            fovy: 0.0,
+            near,
+            far,
+            aspect: 1.0,
+        };
+        projection.resize(width, height);
+        projection
+    }
+
+    //Свёрнутое окно (нулевой размер) aspect не меняет
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4x4<f32> {
+        match self.kind {
+            ProjectionKind::Perspective => Matrix4x4::new_perspective(self.aspect, 1.0, self.near, self.far, self.fovy),
+            ProjectionKind::PerspectiveReversedZ => Matrix4x4::new_perspective_reversed_z(self.aspect, 1.0, self.near, self.fovy),
+            ProjectionKind::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect * 0.5, height * 0.5);
+                Matrix4x4::new_orthographic(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            },
+        }
+    }
+
+    //С чем сравнивать глубину в пайплайнах и чем очищать буфер глубины
+    pub fn depth_mode(&self) -> DepthMode {
+        match self.kind {
+            ProjectionKind::PerspectiveReversedZ => DepthMode::ReversedZ,
+            ProjectionKind::Perspective | ProjectionKind::Orthographic { .. } => DepthMode::Standard,
+        }
+    }
+
+    //Глубина в NDC точки на расстоянии distance вдоль взгляда
+    pub fn depth(&self, distance: f32) -> f32 {
+        let clip = self.matrix() * Vector4::new(0.0, 0.0, distance, 1.0);
+        clip.z / clip.w
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmath::{Vector3, Vector4};
+
+    const EPSILON: f32 = 1e-5;
+
+    //Отношение ширины к высоте, которое проекция даёт точкам на экране
+    fn projected_aspect(projection: &Projection) -> f32 {
+        let matrix = projection.matrix();
+        let clip = |point: Vector3<f32>| {
+            let clip = matrix * point.extend(1.0);
+            Vector4::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w, 1.0)
+        };
+
+        //Квадрат 1x1 на глубине 5 в NDC растягивается в 1/aspect раз по x
+        let (a, b) = (clip(Vector3::new(0.0, 0.0, 5.0)), clip(Vector3::new(1.0, 1.0, 5.0)));
+        (b.y - a.y) / (b.x - a.x)
+    }
+
+    #[test]
+    fn perspective_aspect_follows_resize() {
+        let mut projection = Projection::new_perspective(60.0, 1280, 720, 0.1, 1000.0);
+        assert!((projected_aspect(&projection) - 1280.0 / 720.0).abs() < EPSILON);
+
+        for (width, height) in [(720, 1280), (100, 100), (1, 1000), (3840, 1080)] {
+            projection.resize(width, height);
+            assert!((projection.aspect - width as f32 / height as f32).abs() < EPSILON);
+            assert!((projected_aspect(&projection) - width as f32 / height as f32).abs() < 1e-3);
+        }
+
+        //Свёрнутое окно
+        projection.resize(0, 0);
+        assert!((projection.aspect - 3840.0 / 1080.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn orthographic_aspect_follows_resize() {
+        let mut projection = Projection::new_orthographic(10.0, 200, 100, 0.1, 100.0);
+        assert!((projected_aspect(&projection) - 2.0).abs() < EPSILON);
+
+        projection.resize(100, 400);
+        assert!((projected_aspect(&projection) - 0.25).abs() < EPSILON);
+
+        //Видимая высота не зависит от размера окна
+        let top = projection.matrix() * Vector3::new(0.0, 5.0, 1.0).extend(1.0);
+        assert!((top.y - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn near_and_far_map_to_depth_range() {
+        let projection = Projection::new_perspective(90.0, 16, 9, 0.5, 50.0);
+        assert_eq!(projection.depth_mode(), DepthMode::Standard);
+        assert!(projection.depth(0.5).abs() < EPSILON);
+        assert!((projection.depth(50.0) - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one_and_decreases_with_distance() {
+        let mut projection = Projection::new_perspective_reversed_z(90.0, 16, 9, 0.5);
+        assert_eq!(projection.depth_mode(), DepthMode::ReversedZ);
+        assert!((projection.depth(0.5) - 1.0).abs() < EPSILON);
+
+        let mut previous = projection.depth(0.5);
+        for distance in [1.0, 10.0, 1000.0, 1e6] {
+            let depth = projection.depth(distance);
+            assert!(depth > 0.0 && depth < previous);
+            previous = depth;
+        }
+
+        //Тот же aspect, что и у обычной перспективы
+        projection.resize(1280, 720);
+        assert!((projected_aspect(&projection) - 1280.0 / 720.0).abs() < 1e-3);
+    }
+}
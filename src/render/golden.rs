@@ -0,0 +1,346 @@
+/*
+    Golden image tests: фиксированная сцена с фиксированной камерой рендерится без окна
+    и сравнивается с эталонными картинками из tests/golden.
+    Эталоны перезаписываются при EBENYA_UPDATE_GOLDEN=1,
+    при расхождении рядом в target/golden кладутся actual и diff картинки.
+    Без адаптера, даже программного, тесты пропускаются, а при EBENYA_REQUIRE_GPU=1 падают.
+*/
+
+use std::{env, path::{Path, PathBuf}};
+use wgpu::util::DeviceExt;
+
+use super::{Common, PbrPipeline, PrimitivePipeline, TexturedPipeline};
+use crate::{camera, lights, model, projection, scene, texture, vmath, with_test_adapter, Renderer};
+
+const GOLDEN_PATH: &str = "tests/golden";
+const OUTPUT_PATH: &str = "target/golden";
+const UPDATE_ENV: &str = "EBENYA_UPDATE_GOLDEN";
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+// Допустимая разница каждого канала, покрывает расхождения растеризации между адаптерами
+pub const DEFAULT_TOLERANCE: u8 = 8;
+
+pub struct Harness {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    pub common: Common,
+    pub camera_bind_group: wgpu::BindGroup,
+    //Из проекции камеры, для пайплайнов и begin_pass
+    pub depth_mode: texture::DepthMode,
+    //Пирамида камеры для draw_model_culled
+    pub frustum: vmath::Frustum<f32>,
+    target: wgpu::Texture,
+    depth_texture: texture::Texture,
+}
+
+impl Harness {
+    // None - нет даже программного адаптера, тест пропускается (см. with_test_adapter)
+    pub fn new(test: &str) -> Option<Self> {
+        let (device, queue) = with_test_adapter(test, Renderer::request_headless_device)?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: WIDTH,
+            height: HEIGHT,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("golden_target"),
+            size: wgpu::Extent3d {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        });
+        let depth_texture = texture::Texture::create_depth_texture(&device, WIDTH, HEIGHT, "golden_depth_texture");
+
+        //Камера в трёх единицах перед началом координат, смотрит вдоль +z
+        let mut camera = camera::Camera::new(
+            vmath::Vector3::new(0.0, 0.0, -3.0),
+            vmath::Vector3::new(0.0, 0.0, 1.0),
+            projection::Projection::new_perspective(60.0, WIDTH, HEIGHT, 0.1, 1000.0),
+        );
+        camera.update(instant::Duration::default());
+        let common = Common::new(&device);
+        let camera_bind_group = common.bind_group(&device, &camera.TEST_get_view_proj_matrix_buffer(&device));
+
+        let depth_mode = camera.projection.depth_mode();
+        let frustum = camera.frustum();
+
+        Some(Self { device, queue, config, common, camera_bind_group, depth_mode, frustum, target, depth_texture })
+    }
+
+    // draw сам открывает проход рендера (см. begin_pass) и записывает в него команды
+    pub fn render(
+        &self,
+        draw: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView, &wgpu::TextureView)
+    ) -> image::RgbaImage {
+        let view = self.target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Golden Encoder"),
+            }
+        );
+        draw(&mut encoder, &view, &self.depth_texture.view);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        texture::Texture::read_to_image(&self.device, &self.queue, &self.target).unwrap()
+    }
+}
+
+pub fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    depth_view: &'a wgpu::TextureView,
+    depth_mode: texture::DepthMode,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Golden Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(depth_mode.clear_value()),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+// Сравнивает картинку с эталоном tests/golden/<name>.png
+pub fn assert_golden(name: &str, actual: &image::RgbaImage, tolerance: u8) {
+    let golden_path = Path::new(GOLDEN_PATH).join(format!("{}.png", name));
+
+    if env::var(UPDATE_ENV).is_ok_and(|value| value == "1") {
+        std::fs::create_dir_all(GOLDEN_PATH).unwrap();
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&golden_path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => panic!(
+            "can't open golden image {}: {} (run with {}=1 to create it)",
+            golden_path.display(), err, UPDATE_ENV
+        ),
+    };
+
+    if let Some((diff, mismatched)) = diff_images(&expected, actual, tolerance) {
+        let actual_path = output_path(name, "actual");
+        let diff_path = output_path(name, "diff");
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "{}: {} pixels differ by more than {} (actual: {}, diff: {})",
+            name, mismatched, tolerance, actual_path.display(), diff_path.display()
+        );
+    }
+}
+
+fn output_path(name: &str, suffix: &str) -> PathBuf {
+    std::fs::create_dir_all(OUTPUT_PATH).unwrap();
+    Path::new(OUTPUT_PATH).join(format!("{}.{}.png", name, suffix))
+}
+
+// None если картинки совпадают, иначе diff картинка (красным - отличающиеся пиксели)
+// и число отличающихся пикселей
+fn diff_images(
+    expected: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    tolerance: u8
+) -> Option<(image::RgbaImage, usize)> {
+    if expected.dimensions() != actual.dimensions() {
+        return Some((actual.clone(), (actual.width() * actual.height()) as usize));
+    }
+
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+    let mut mismatched = 0;
+
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        let reference = expected.get_pixel(x, y);
+        let exceeds = pixel.0.iter()
+            .zip(reference.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > tolerance);
+
+        diff.put_pixel(x, y, if exceeds {
+            mismatched += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            //Совпавшие пиксели приглушены, чтобы отличия было видно
+            let luma = (pixel.0[0] as u32 + pixel.0[1] as u32 + pixel.0[2] as u32) / 3 / 4;
+            image::Rgba([luma as u8, luma as u8, luma as u8, 255])
+        });
+    }
+
+    if mismatched == 0 {
+        None
+    } else {
+        Some((diff, mismatched))
+    }
+}
+
+
+const CUBE_INDICES: &[u16] = &[
+    0, 2, 1,
+    1, 3, 0,
+
+    5, 6, 4,
+    4, 7, 5,
+
+    0, 4, 6,
+    6, 2, 0,
+
+    3, 1, 5,
+    5, 7, 3,
+
+    0, 3, 7,
+    7, 4, 0,
+
+    2, 6, 5,
+    5, 1, 2
+];
+
+const CUBE_VERTICES: &[super::Vertex] = &[
+    super::Vertex { position: [-0.5, 0.5, 0.0], color: [0.5, 0.0, 0.5] },
+    super::Vertex { position: [0.5, -0.5, 0.0], color: [0.0, 0.5, 0.5] },
+    super::Vertex { position: [-0.5, -0.5, 0.0], color: [0.5, 0.5, 0.0] },
+    super::Vertex { position: [0.5, 0.5, 0.0], color: [0.5, 0.0, 0.0] },
+
+    super::Vertex { position: [-0.5, 0.5, 1.0], color: [0.0, 0.5, 0.0] },
+    super::Vertex { position: [0.5, -0.5, 1.0], color: [0.0, 0.0, 0.5] },
+    super::Vertex { position: [-0.5, -0.5, 1.0], color: [0.5, 0.5, 0.5] },
+    super::Vertex { position: [0.5, 0.5, 1.0], color: [0.0, 0.0, 0.0] },
+];
+
+#[test]
+fn diff_respects_tolerance() {
+    let expected = image::RgbaImage::from_pixel(2, 1, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, image::Rgba([104, 100, 100, 255]));
+    assert!(diff_images(&expected, &actual, 4).is_none());
+
+    actual.put_pixel(1, 0, image::Rgba([100, 90, 100, 255]));
+    let (diff, mismatched) = diff_images(&expected, &actual, 4).unwrap();
+    assert_eq!(mismatched, 1);
+    assert_eq!(diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn golden_primitive_cube() {
+    let Some(harness) = Harness::new("golden_primitive_cube") else { return };
+    let pipeline = PrimitivePipeline::new(&harness.device, &harness.common, &harness.config);
+
+    let vertex_buffer = harness.device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Golden Vertex Buffer"),
+            contents: bytemuck::cast_slice(CUBE_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        }
+    );
+    let index_buffer = harness.device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Golden Index Buffer"),
+            contents: bytemuck::cast_slice(CUBE_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        }
+    );
+
+    let image = harness.render(|encoder, view, depth_view| {
+        let mut render_pass = begin_pass(encoder, view, depth_view, harness.depth_mode);
+        render_pass.set_pipeline(pipeline.pipeline(harness.depth_mode));
+        render_pass.set_bind_group(0, &harness.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..CUBE_INDICES.len() as u32, 0, 0..1);
+    });
+
+    assert_golden("primitive_cube", &image, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_textured_box() {
+    use model::DrawModel;
+
+    let Some(harness) = Harness::new("golden_textured_box") else { return };
+    let material_layout = model::Material::create_layout(&harness.device);
+    let pipeline = TexturedPipeline::new(&harness.device, &harness.common, &material_layout, &harness.config);
+    //Узел куба в файле сдвинут на 11 по x, модель возвращает его в начало координат
+    let model = model::Model::new(
+        "box_1x1.gltf",
+        vmath::Vector3::new(11.0, 0.0, 0.0),
+        &harness.device,
+        &harness.queue,
+        &material_layout,
+    ).unwrap();
+
+    let image = harness.render(|encoder, view, depth_view| {
+        let mut render_pass = begin_pass(encoder, view, depth_view, harness.depth_mode);
+        render_pass.set_pipeline(pipeline.pipeline(harness.depth_mode));
+        render_pass.set_bind_group(0, &harness.camera_bind_group, &[]);
+        render_pass.draw_model_culled(&model, &harness.frustum);
+    });
+
+    assert_golden("textured_box", &image, DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_pbr_toy_car() {
+    use model::DrawModel;
+
+    let Some(harness) = Harness::new("golden_pbr_toy_car") else { return };
+    let material_layout = model::Material::create_layout(&harness.device);
+    let pipeline = PbrPipeline::new(&harness.device, &harness.common, &material_layout, &harness.config);
+    let mut model = model::Model::new(
+        "toy_car.gltf",
+        vmath::Vector3::new(0.0, 0.0, 0.0),
+        &harness.device,
+        &harness.queue,
+        &material_layout,
+    ).unwrap();
+    //Машинка повёрнута к камере на три четверти и стоит чуть ниже её
+    model.scene_mut().set_root_transform(scene::Transform {
+        translation: vmath::Vector3::new(-0.1, -0.12, -2.0),
+        rotation: vmath::Quaternion::from_axis_angle(vmath::Vector3::unit_y(), 2.4),
+        scale: vmath::Vector3::new(1.0, 1.0, 1.0),
+    });
+    model.update(&harness.queue);
+
+    let mut lights = lights::Lights::new(vmath::Vector3::new(0.15, 0.15, 0.15));
+    lights.add(lights::Light::directional(
+        vmath::Vector3::new(0.4, -1.0, 0.6),
+        vmath::Vector3::new(1.0, 1.0, 1.0),
+        3.0,
+    ));
+    lights.update(&harness.queue, &harness.common.light_buffer);
+
+    let image = harness.render(|encoder, view, depth_view| {
+        let mut render_pass = begin_pass(encoder, view, depth_view, harness.depth_mode);
+        render_pass.set_pipeline(pipeline.pipeline(harness.depth_mode));
+        render_pass.set_bind_group(0, &harness.camera_bind_group, &[]);
+        render_pass.draw_model_culled(&model, &harness.frustum);
+    });
+
+    assert_golden("pbr_toy_car", &image, DEFAULT_TOLERANCE);
+}
@@ -1,3 +1,5 @@
 mod pipelines;
+#[cfg(test)]
+mod golden;
 
-pub use pipelines::{Common, PrimitivePipeline, Vertex};
\ No newline at end of file
+pub use pipelines::{Common, ExposurePipeline, FillPipeline, PbrPipeline, PrimitivePipeline, ShadowDebugPipeline, ShadowPipeline, TexturedPipeline, TonemapPipeline, Vertex};
\ No newline at end of file
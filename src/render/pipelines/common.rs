@@ -1,33 +1,108 @@
 use wgpu;
 
-// Base bind groups: camera, ...
+use crate::lights::Lights;
+use crate::shadows::{self, ShadowUniform};
+use crate::texture;
+
+// Base bind groups: camera, lights, shadow maps
 pub struct Common {
-    pub layout: wgpu::BindGroupLayout, 
+    pub layout: wgpu::BindGroupLayout,
     //cam_view_proj: [[f32; 4]; 4],
+    //Источники света, общие для всех видов. Заполняется Lights::update
+    pub light_buffer: wgpu::Buffer,
+    //Матрицы источников с тенями, ShadowUniform
+    pub shadow_buffer: wgpu::Buffer,
+    //Слои источников с тенью (у направленных - по каскаду), в них рисует ShadowPipeline
+    pub shadow_map: texture::Texture,
 }
 
 impl Common {
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            //Позиция камеры нужна фрагментному шейдеру для освещения
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 4,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+            count: None,
+        },
+    ];
+
     pub fn new(device: &wgpu::Device) -> Self {
         let bind_group_layout = device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }
-                ],
+                entries: Self::LAYOUT_ENTRIES,
                 label: Some("common_bind_group_layout"),
             }
         );
 
+        //Нули - ни одного источника и нет рассеянного света
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_buffer"),
+            size: Lights::BUFFER_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        //count = 0 - теней нет, пока рендерер не посчитает матрицы
+        let shadow_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow_buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shadow_map = texture::Texture::create_shadow_map(
+            device,
+            shadows::SHADOW_MAP_SIZE,
+            shadows::MAX_SHADOW_LAYERS as u32,
+            "shadow_map",
+        );
+
         Self {
-            layout: bind_group_layout
+            layout: bind_group_layout,
+            light_buffer,
+            shadow_buffer,
+            shadow_map,
         }
     }
 
@@ -38,9 +113,25 @@ impl Common {
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: view_proj_buffer.as_entire_binding(),
-                }
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.shadow_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&self.shadow_map.sampler),
+                },
             ],
             label: Some("common_bind_group"),
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,211 @@
+use wgpu::util::DeviceExt;
+
+use crate::hdr::{ExposureParams, ExposureState, HISTOGRAM_BINS};
+
+// Размер группы build_histogram в shaders/exposure.wgsl, 16 x 16 = HISTOGRAM_BINS потоков
+const TILE_SIZE: u32 = 16;
+
+// Auto exposure compute passes: luminance histogram of an HDR target, then its average
+// moves the exposure state read by TonemapPipeline (group 0 - HDR texture, histogram, state, params).
+pub struct ExposurePipeline {
+    pub histogram_pipeline: wgpu::ComputePipeline,
+    pub average_pipeline: wgpu::ComputePipeline,
+    pub layout: wgpu::BindGroupLayout,
+    //ExposureState, при ручной экспозиции пишется с CPU
+    pub state_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+}
+
+impl ExposurePipeline {
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(device: &wgpu::Device, state: ExposureState) -> Self {
+        let exposure_shader = device.create_shader_module(
+            wgpu::include_wgsl!("../../../shaders/exposure.wgsl")
+        );
+
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: Self::LAYOUT_ENTRIES,
+                label: Some("exposure_bind_group_layout"),
+            }
+        );
+
+        let compute_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("exposure_compute_pipeline_layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let compute_pipeline = |label, entry_point| device.create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&compute_pipeline_layout),
+                module: &exposure_shader,
+                entry_point,
+            }
+        );
+
+        //Гистограмма пустая, average очищает её после себя
+        let histogram_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("exposure_histogram_buffer"),
+                contents: bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS]),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+        let state_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("exposure_state_buffer"),
+                contents: bytemuck::cast_slice(&[state]),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            }
+        );
+        //Пока нет update, пустой кадр: pixel_count 0 экспозицию не трогает
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("exposure_params_buffer"),
+                contents: bytemuck::cast_slice(&[ExposureParams::new(0.0, 0.0, instant::Duration::ZERO, 0)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        Self {
+            histogram_pipeline: compute_pipeline("exposure_histogram_pipeline", "build_histogram"),
+            average_pipeline: compute_pipeline("exposure_average_pipeline", "average"),
+            layout,
+            state_buffer,
+            histogram_buffer,
+            params_buffer,
+        }
+    }
+
+    //hdr_view - цель, по которой подбирается экспозиция
+    pub fn bind_group(&self, device: &wgpu::Device, hdr_view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("exposure_bind_group"),
+        })
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, params: &ExposureParams) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[*params]));
+    }
+
+    //Ручная экспозиция: compute проходы не запускаются, состояние пишется напрямую
+    pub fn set_state(&self, queue: &wgpu::Queue, state: ExposureState) {
+        queue.write_buffer(&self.state_buffer, 0, bytemuck::cast_slice(&[state]));
+    }
+
+    //size - размер текстуры из bind_group
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, size: wgpu::Extent3d) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Exposure Pass"),
+        });
+        compute_pass.set_bind_group(0, bind_group, &[]);
+
+        compute_pass.set_pipeline(&self.histogram_pipeline);
+        compute_pass.dispatch_workgroups(
+            size.width.div_ceil(TILE_SIZE),
+            size.height.div_ceil(TILE_SIZE),
+            1,
+        );
+
+        compute_pass.set_pipeline(&self.average_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    #[cfg(test)]
+    pub fn read_state(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<ExposureState, anyhow::Error> {
+        let size = std::mem::size_of::<ExposureState>() as wgpu::BufferAddress;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("exposure_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Exposure Readback Encoder"),
+            }
+        );
+        encoder.copy_buffer_to_buffer(&self.state_buffer, 0, &buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let state = *bytemuck::from_bytes::<ExposureState>(&slice.get_mapped_range());
+        buffer.unmap();
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,81 @@
+use wgpu;
+
+use crate::texture;
+
+// Fills the current viewport with the blend constant color, ignoring depth.
+// Used to clear the rectangle of a picture-in-picture view: LoadOp::Clear always clears the whole target.
+pub struct FillPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl FillPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_config: &wgpu::SurfaceConfiguration
+    ) -> Self {
+        let fill_shader = device.create_shader_module(
+            wgpu::include_wgsl!("../../../shaders/fill.wgsl")
+        );
+
+        let render_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("fill_render_pipeline_layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            }
+        );
+
+        //Результат = blend constant * 1 + старый цвет * 0
+        let constant = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::Zero,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        Self {
+            pipeline: device.create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: Some("fill_render_pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &fill_shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &fill_shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: surface_config.format,
+                            blend: Some(wgpu::BlendState { color: constant, alpha: constant }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    //Проход с глубиной требует совместимый пайплайн, но глубину заливка не трогает
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: texture::Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                }
+            ),
+        }
+    }
+}
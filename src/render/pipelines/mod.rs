@@ -1,5 +1,21 @@
 mod primitive;
+mod textured;
+mod pbr;
 mod common;
+mod fill;
+mod shadow;
+mod shadow_debug;
+mod exposure;
+mod tonemap;
+#[cfg(test)]
+mod validation;
 
 pub use primitive::*;
-pub use common::*;
\ No newline at end of file
+pub use textured::*;
+pub use pbr::*;
+pub use common::*;
+pub use fill::*;
+pub use shadow::*;
+pub use shadow_debug::*;
+pub use exposure::*;
+pub use tonemap::*;
\ No newline at end of file
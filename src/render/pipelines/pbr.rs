@@ -0,0 +1,92 @@
+use wgpu;
+
+use super::common;
+use crate::{model, texture};
+
+// Pipeline for meshes with glTF metallic-roughness materials, Cook-Torrance lighting (group 1 - material).
+pub struct PbrPipeline {
+    standard: wgpu::RenderPipeline,
+    reversed_z: wgpu::RenderPipeline,
+}
+
+impl PbrPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        common: &common::Common,
+        material_layout: &wgpu::BindGroupLayout,
+        surface_config: &wgpu::SurfaceConfiguration
+    ) -> Self {
+        let pbr_shader = device.create_shader_module(
+            wgpu::include_wgsl!("../../../shaders/pbr.wgsl")
+        );
+
+        let render_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("pbr_render_pipeline_layout"),
+                bind_group_layouts: &[
+                    &common.layout,
+                    material_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let create_pipeline = |depth_mode: texture::DepthMode| device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("pbr_render_pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &pbr_shader,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        model::Model::vertex_buffer_layout(),
+                        model::Model::instance_buffer_layout(),
+                    ],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &pbr_shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: surface_config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: depth_mode.compare_function(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
+        Self {
+            standard: create_pipeline(texture::DepthMode::Standard),
+            reversed_z: create_pipeline(texture::DepthMode::ReversedZ),
+        }
+    }
+
+    pub fn pipeline(&self, depth_mode: texture::DepthMode) -> &wgpu::RenderPipeline {
+        match depth_mode {
+            texture::DepthMode::Standard => &self.standard,
+            texture::DepthMode::ReversedZ => &self.reversed_z,
+        }
+    }
+}
\ No newline at end of file
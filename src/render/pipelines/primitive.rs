@@ -12,7 +12,7 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    fn buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    pub fn buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -32,9 +32,10 @@ impl Vertex {
     }
 }
 
-// Base pipeline for primitive objects.
+// Base pipeline for primitive objects, one variant per DepthMode.
 pub struct PrimitivePipeline {
-    pub pipeline: wgpu::RenderPipeline,
+    standard: wgpu::RenderPipeline,
+    reversed_z: wgpu::RenderPipeline,
 }
 
 impl PrimitivePipeline {
@@ -49,7 +50,7 @@ impl PrimitivePipeline {
         });*/
 
         let primitive_shader = device.create_shader_module(
-            wgpu::include_wgsl!("primitive.wgsl")
+            wgpu::include_wgsl!("../../../shaders/primitive.wgsl")
         );
 
         let render_pipeline_layout = device.create_pipeline_layout(
@@ -62,69 +63,78 @@ impl PrimitivePipeline {
             }
         );
 
+        let create_pipeline = |depth_mode: texture::DepthMode| device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("primitive_render_pipeline"),
+                layout: Some(&render_pipeline_layout),
+                //@vertex
+                vertex: wgpu::VertexState {
+                    module: &primitive_shader,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        Vertex::buffer_layout()
+                        //model::Model::vertex_buffer_layout(),
+                    ],
+                },
+                //@fragment
+                fragment: Some(wgpu::FragmentState {
+                    module: &primitive_shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: surface_config.format,
+                        //REPLACE - новые цвета замещают старые
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        //Использовать все компоненты цвета, RGBA
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                //Как интерпретировать вершины при конвертации в треугольники
+                primitive: wgpu::PrimitiveState {
+                    //Каждые три вершины будут соответствовать 
+                    //одному треугольнику
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    //Треугольник обращён вперёд если 
+                    //построен проти часовой стрелки
+                    front_face: wgpu::FrontFace::Ccw,
+                    //Те которые не обращены вперёд, не рендерятся
+                    cull_mode: Some(wgpu::Face::Back),
+                    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    // Requires Features::DEPTH_CLIP_CONTROL
+                    unclipped_depth: false,
+                    // Requires Features::CONSERVATIVE_RASTERIZATION
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: depth_mode.compare_function(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    //Сколько сэмплов будет использовать конвейер
+                    count: 1,
+                    //Использовать все активные сэмплы
+                    mask: !0,
+                    // для сглаживания (пока отключено)
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        );
+
         Self {
-            pipeline: device.create_render_pipeline(
-                &wgpu::RenderPipelineDescriptor {
-                    label: Some("primitive_render_pipeline"),
-                    layout: Some(&render_pipeline_layout),
-                    //@vertex
-                    vertex: wgpu::VertexState {
-                        module: &primitive_shader,
-                        entry_point: "vs_main",
-                        buffers: &[
-                            Vertex::buffer_layout()
-                            //model::Model::vertex_buffer_layout(),
-                        ],
-                    },
-                    //@fragment
-                    fragment: Some(wgpu::FragmentState {
-                        module: &primitive_shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: surface_config.format,
-                            //REPLACE - новые цвета замещают старые
-                            blend: Some(wgpu::BlendState::REPLACE),
-                            //Использовать все компоненты цвета, RGBA
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    //Как интерпретировать вершины при конвертации в треугольники
-                    primitive: wgpu::PrimitiveState {
-                        //Каждые три вершины будут соответствовать 
-                        //одному треугольнику
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        //Треугольник обращён вперёд если 
-                        //построен проти часовой стрелки
-                        front_face: wgpu::FrontFace::Ccw,
-                        //Те которые не обращены вперёд, не рендерятся
-                        cull_mode: Some(wgpu::Face::Back),
-                        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        // Requires Features::DEPTH_CLIP_CONTROL
-                        unclipped_depth: false,
-                        // Requires Features::CONSERVATIVE_RASTERIZATION
-                        conservative: false,
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: texture::Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState {
-                        //Сколько сэмплов будет использовать конвейер
-                        count: 1,
-                        //Использовать все активные сэмплы
-                        mask: !0,
-                        // для сглаживания (пока отключено)
-                        alpha_to_coverage_enabled: false,
-                    },
-                    multiview: None,
-                }
-            )
+            standard: create_pipeline(texture::DepthMode::Standard),
+            reversed_z: create_pipeline(texture::DepthMode::ReversedZ),
         }
+    }
 
+    pub fn pipeline(&self, depth_mode: texture::DepthMode) -> &wgpu::RenderPipeline {
+        match depth_mode {
+            texture::DepthMode::Standard => &self.standard,
+            texture::DepthMode::ReversedZ => &self.reversed_z,
+        }
     }
 }
\ No newline at end of file
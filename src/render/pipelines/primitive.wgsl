@@ -1,37 +0,0 @@
-struct CameraUniform {
-   view_proj: mat4x4<f32>,
-}
-
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-};
-
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-
-    out.color = model.color;
-    out.clip_position =  camera.view_proj * vec4<f32>(model.position, 1.0);
-
-    return out;
-}
-
-
-@fragment
-// @location(0) - сохранить выходное значение в первом color target 
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    //Возвращаем цвет конкретного фрагмента
-    return vec4(in.color, 1.0);
-}
\ No newline at end of file
@@ -0,0 +1,168 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+
+use super::common;
+use crate::shadows::{self, ShadowUniform};
+use crate::{model, texture, vmath};
+
+// Depth-only pipeline filling the layers of Common::shadow_map, one per spot light or cascade
+// (group 0 - light matrix of the layer, group 1 - material for alpha cutoff).
+pub struct ShadowPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub layers: Vec<ShadowLayer>,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+}
+
+// Слой карты теней как цель прохода и матрица его источника
+pub struct ShadowLayer {
+    pub view: wgpu::TextureView,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ShadowPipeline {
+    pub const LAYOUT_ENTRIES: &'static [wgpu::BindGroupLayoutEntry] = &[
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+
+    pub fn new(
+        device: &wgpu::Device,
+        common: &common::Common,
+        material_layout: &wgpu::BindGroupLayout,
+        bias: wgpu::DepthBiasState,
+    ) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::include_wgsl!("../../../shaders/shadow.wgsl")
+        );
+
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: Self::LAYOUT_ENTRIES,
+                label: Some("shadow_bind_group_layout"),
+            }
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("shadow_render_pipeline_layout"),
+                bind_group_layouts: &[
+                    &layout,
+                    material_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let layers = (0..shadows::MAX_SHADOW_LAYERS as u32)
+            .map(|layer| {
+                let view = common.shadow_map.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("shadow_layer_view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                });
+                let buffer = device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("shadow_layer_buffer"),
+                        contents: bytemuck::cast_slice(&[<[[f32; 4]; 4]>::from(vmath::Matrix4x4::new_indent())]),
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    }
+                );
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("shadow_layer_bind_group"),
+                });
+
+                ShadowLayer { view, buffer, bind_group }
+            })
+            .collect();
+
+        Self {
+            pipeline: Self::create_pipeline(device, &pipeline_layout, &shader, bias),
+            layers,
+            shader,
+            pipeline_layout,
+        }
+    }
+
+    //Сдвиг глубины зашит в пайплайн, поэтому меняется только пересозданием
+    pub fn set_bias(&mut self, device: &wgpu::Device, bias: wgpu::DepthBiasState) {
+        self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, &self.shader, bias);
+    }
+
+    //Матрицы источников в буферы слоёв
+    pub fn update(&self, queue: &wgpu::Queue, uniform: &ShadowUniform) {
+        for (index, layer) in self.layers.iter().enumerate().take(uniform.layers()) {
+            let view_proj: [[f32; 4]; 4] = uniform.view_proj(index).into();
+            queue.write_buffer(&layer.buffer, 0, bytemuck::cast_slice(&[view_proj]));
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        bias: wgpu::DepthBiasState,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("shadow_render_pipeline"),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        model::Model::vertex_buffer_layout(),
+                        model::Model::instance_buffer_layout(),
+                    ],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: "fs_main",
+                    targets: &[],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    //Тонкие и незамкнутые меши тоже должны давать тень
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                //Проекции источников всегда с обычной глубиной, независимо от DepthMode камеры
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias,
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            }
+        )
+    }
+}
@@ -0,0 +1,71 @@
+use wgpu;
+
+use super::common;
+
+// Debug overlay showing one layer of Common::shadow_map in the current viewport of the output (group 0 - common).
+// The layer is the instance index: draw(0..3, layer..layer + 1).
+pub struct ShadowDebugPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowDebugPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        common: &common::Common,
+        surface_config: &wgpu::SurfaceConfiguration
+    ) -> Self {
+        let shadow_debug_shader = device.create_shader_module(
+            wgpu::include_wgsl!("../../../shaders/shadow_debug.wgsl")
+        );
+
+        let render_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("shadow_debug_render_pipeline_layout"),
+                bind_group_layouts: &[
+                    &common.layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        Self {
+            pipeline: device.create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: Some("shadow_debug_render_pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shadow_debug_shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shadow_debug_shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: surface_config.format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    //Рисуется на поверхность после тональной компрессии, без глубины
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                }
+            ),
+        }
+    }
+}
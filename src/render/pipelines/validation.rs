@@ -0,0 +1,297 @@
+/*
+    Проверка шейдеров из shaders/ через naga: каждый .wgsl должен парситься и валидироваться,
+    а его bind группы и входы вершинного шейдера - совпадать с layout'ами на стороне Rust.
+    Иначе ошибка всплывёт только паникой при создании пайплайна.
+*/
+
+use std::{fs, path::Path};
+
+use super::{Common, ExposurePipeline, ShadowPipeline, TonemapPipeline, Vertex};
+use crate::model;
+
+const SHADERS_PATH: &str = "shaders";
+
+// Что ожидает от шейдера Rust: вершинные буферы и layout каждой bind группы по порядку
+struct ShaderInterface {
+    file: &'static str,
+    vertex_buffers: Vec<wgpu::VertexBufferLayout<'static>>,
+    bind_groups: Vec<&'static [wgpu::BindGroupLayoutEntry]>,
+}
+
+fn interfaces() -> Vec<ShaderInterface> {
+    vec![
+        ShaderInterface {
+            file: "primitive.wgsl",
+            vertex_buffers: vec![Vertex::buffer_layout()],
+            bind_groups: vec![Common::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "textured.wgsl",
+            vertex_buffers: vec![model::Model::vertex_buffer_layout(), model::Model::instance_buffer_layout()],
+            bind_groups: vec![Common::LAYOUT_ENTRIES, model::Material::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "pbr.wgsl",
+            vertex_buffers: vec![model::Model::vertex_buffer_layout(), model::Model::instance_buffer_layout()],
+            bind_groups: vec![Common::LAYOUT_ENTRIES, model::Material::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "shadow.wgsl",
+            vertex_buffers: vec![model::Model::vertex_buffer_layout(), model::Model::instance_buffer_layout()],
+            bind_groups: vec![ShadowPipeline::LAYOUT_ENTRIES, model::Material::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "shadow_debug.wgsl",
+            vertex_buffers: vec![],
+            bind_groups: vec![Common::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "exposure.wgsl",
+            vertex_buffers: vec![],
+            bind_groups: vec![ExposurePipeline::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "tonemap.wgsl",
+            vertex_buffers: vec![],
+            bind_groups: vec![TonemapPipeline::LAYOUT_ENTRIES],
+        },
+        ShaderInterface {
+            file: "fill.wgsl",
+            vertex_buffers: vec![],
+            bind_groups: vec![],
+        },
+    ]
+}
+
+fn load(file: &str) -> Result<(naga::Module, naga::valid::ModuleInfo), String> {
+    let path = Path::new(SHADERS_PATH).join(file);
+    let source = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let module = naga::front::wgsl::parse_str(&source)
+        .map_err(|err| err.emit_to_string_with_path(&source, &path.display().to_string()))?;
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+        .validate(&module)
+        .map_err(|err| format!("{}: {:?}", path.display(), err))?;
+
+    Ok((module, info))
+}
+
+fn shader_files() -> Vec<String> {
+    let mut files: Vec<String> = fs::read_dir(SHADERS_PATH)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".wgsl"))
+        .collect();
+    files.sort();
+
+    files
+}
+
+// Число компонент и тип скаляра, которые шейдер получит из атрибута такого формата
+fn vertex_format_inner(format: wgpu::VertexFormat) -> (naga::ScalarKind, u8) {
+    use wgpu::VertexFormat::*;
+    use naga::ScalarKind::*;
+
+    match format {
+        Float32 | Float64 => (Float, 1),
+        Float32x2 | Float64x2 | Float16x2 | Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 => (Float, 2),
+        Float32x3 | Float64x3 => (Float, 3),
+        Float32x4 | Float64x4 | Float16x4 | Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 => (Float, 4),
+        Uint32 => (Uint, 1),
+        Uint32x2 | Uint8x2 | Uint16x2 => (Uint, 2),
+        Uint32x3 => (Uint, 3),
+        Uint32x4 | Uint8x4 | Uint16x4 => (Uint, 4),
+        Sint32 => (Sint, 1),
+        Sint32x2 | Sint8x2 | Sint16x2 => (Sint, 2),
+        Sint32x3 => (Sint, 3),
+        Sint32x4 | Sint8x4 | Sint16x4 => (Sint, 4),
+    }
+}
+
+fn shader_inner(inner: &naga::TypeInner) -> Option<(naga::ScalarKind, u8)> {
+    match *inner {
+        naga::TypeInner::Scalar { kind, .. } => Some((kind, 1)),
+        naga::TypeInner::Vector { size, kind, .. } => Some((kind, size as u8)),
+        _ => None,
+    }
+}
+
+fn check_vertex_inputs(module: &naga::Module, interface: &ShaderInterface, errors: &mut Vec<String>) {
+    for entry_point in module.entry_points.iter().filter(|ep| ep.stage == naga::ShaderStage::Vertex) {
+        //Входы - либо аргументы с @location, либо поля структуры-аргумента
+        let mut inputs = Vec::new();
+        for argument in &entry_point.function.arguments {
+            match (&argument.binding, &module.types[argument.ty].inner) {
+                (Some(naga::Binding::Location { location, .. }), inner) => inputs.push((*location, inner)),
+                (None, naga::TypeInner::Struct { members, .. }) => {
+                    for member in members {
+                        if let Some(naga::Binding::Location { location, .. }) = member.binding {
+                            inputs.push((location, &module.types[member.ty].inner));
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        for (location, inner) in inputs {
+            let attribute = interface.vertex_buffers.iter()
+                .flat_map(|layout| layout.attributes.iter())
+                .find(|attribute| attribute.shader_location == location);
+
+            match attribute {
+                None => errors.push(format!(
+                    "{}: {} reads @location({}) which no vertex buffer provides",
+                    interface.file, entry_point.name, location
+                )),
+                Some(attribute) if shader_inner(inner) != Some(vertex_format_inner(attribute.format)) => {
+                    errors.push(format!(
+                        "{}: {} @location({}) is {:?}, vertex buffer provides {:?}",
+                        interface.file, entry_point.name, location, inner, attribute.format
+                    ))
+                },
+                Some(_) => {},
+            }
+        }
+    }
+}
+
+fn binding_matches(module: &naga::Module, var: &naga::GlobalVariable, ty: &wgpu::BindingType) -> bool {
+    use naga::{AddressSpace, ImageClass, ImageDimension, ScalarKind, TypeInner};
+    use wgpu::{BindingType, BufferBindingType, SamplerBindingType, TextureSampleType, TextureViewDimension};
+
+    match (var.space, &module.types[var.ty].inner, ty) {
+        (AddressSpace::Uniform, _, BindingType::Buffer { ty: BufferBindingType::Uniform, .. }) => true,
+        (AddressSpace::Storage { access }, _, BindingType::Buffer { ty: BufferBindingType::Storage { read_only }, .. }) => {
+            *read_only == !access.contains(naga::StorageAccess::STORE)
+        },
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }, BindingType::Sampler(sampler)) => {
+            *comparison == (*sampler == SamplerBindingType::Comparison)
+        },
+        (AddressSpace::Handle, TypeInner::Image { dim, arrayed, class }, BindingType::Texture {
+            sample_type, view_dimension, multisampled
+        }) => {
+            let dimension_matches = match (dim, arrayed, view_dimension) {
+                (ImageDimension::D1, false, TextureViewDimension::D1)
+                | (ImageDimension::D2, false, TextureViewDimension::D2)
+                | (ImageDimension::D2, true, TextureViewDimension::D2Array)
+                | (ImageDimension::D3, false, TextureViewDimension::D3)
+                | (ImageDimension::Cube, false, TextureViewDimension::Cube)
+                | (ImageDimension::Cube, true, TextureViewDimension::CubeArray) => true,
+                _ => false,
+            };
+            let class_matches = match (class, sample_type) {
+                (ImageClass::Sampled { kind: ScalarKind::Float, multi }, TextureSampleType::Float { .. })
+                | (ImageClass::Sampled { kind: ScalarKind::Sint, multi }, TextureSampleType::Sint)
+                | (ImageClass::Sampled { kind: ScalarKind::Uint, multi }, TextureSampleType::Uint)
+                | (ImageClass::Depth { multi }, TextureSampleType::Depth) => multi == multisampled,
+                _ => false,
+            };
+
+            dimension_matches && class_matches
+        },
+        (AddressSpace::Handle, TypeInner::Image { class: ImageClass::Storage { .. }, .. }, BindingType::StorageTexture { .. }) => true,
+        _ => false,
+    }
+}
+
+fn check_bind_groups(
+    module: &naga::Module,
+    info: &naga::valid::ModuleInfo,
+    interface: &ShaderInterface,
+    errors: &mut Vec<String>
+) {
+    for (handle, var) in module.global_variables.iter() {
+        let Some(binding) = &var.binding else { continue };
+        let name = var.name.as_deref().unwrap_or("<unnamed>");
+
+        let entry = interface.bind_groups
+            .get(binding.group as usize)
+            .and_then(|entries| entries.iter().find(|entry| entry.binding == binding.binding));
+        let Some(entry) = entry else {
+            errors.push(format!(
+                "{}: `{}` is bound at @group({}) @binding({}) which the pipeline layout doesn't have",
+                interface.file, name, binding.group, binding.binding
+            ));
+            continue;
+        };
+
+        if !binding_matches(module, var, &entry.ty) {
+            errors.push(format!(
+                "{}: `{}` at @group({}) @binding({}) doesn't match layout entry {:?}",
+                interface.file, name, binding.group, binding.binding, entry.ty
+            ));
+        }
+
+        for (index, entry_point) in module.entry_points.iter().enumerate() {
+            let stage = match entry_point.stage {
+                naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+            };
+            if !info.get_entry_point(index)[handle].is_empty() && !entry.visibility.contains(stage) {
+                errors.push(format!(
+                    "{}: `{}` is used in {} but its layout entry is visible only in {:?}",
+                    interface.file, name, entry_point.name, entry.visibility
+                ));
+            }
+        }
+    }
+}
+
+#[test]
+fn shaders_are_valid() {
+    let errors: Vec<String> = shader_files().iter()
+        .filter_map(|file| load(file).err())
+        .collect();
+
+    assert!(errors.is_empty(), "\n{}", errors.join("\n"));
+}
+
+#[test]
+fn shader_interfaces_match_layouts() {
+    let interfaces = interfaces();
+    let mut errors = Vec::new();
+
+    for file in shader_files() {
+        if !interfaces.iter().any(|interface| interface.file == file) {
+            errors.push(format!("{}: no ShaderInterface describes this shader", file));
+        }
+    }
+
+    for interface in &interfaces {
+        let (module, info) = match load(interface.file) {
+            Ok(shader) => shader,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        check_vertex_inputs(&module, interface, &mut errors);
+        check_bind_groups(&module, &info, interface, &mut errors);
+    }
+
+    assert!(errors.is_empty(), "\n{}", errors.join("\n"));
+}
+
+#[test]
+fn mismatched_group_is_reported() {
+    //Та самая ошибка из старого shaders/primitive.wgsl: камера в @group(1) при одной bind группе
+    let source = fs::read_to_string(Path::new(SHADERS_PATH).join("primitive.wgsl"))
+        .unwrap()
+        .replace("@group(0)", "@group(1)");
+    let module = naga::front::wgsl::parse_str(&source).unwrap();
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    ).validate(&module).unwrap();
+
+    let mut errors = Vec::new();
+    check_bind_groups(&module, &info, &interfaces()[0], &mut errors);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+}
@@ -0,0 +1,207 @@
+/*
+    Граф сцены: узлы с локальными переносом, поворотом и масштабом, связанные родитель-потомок.
+    Мировые матрицы кэшируются. Изменение любого узла или корня помечает граф грязным,
+    и update пересчитывает все матрицы за один проход: родитель всегда добавлен раньше потомков.
+*/
+
+use crate::vmath::{Matrix4x4, Quaternion, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    //Индексы в Model::meshes, рисуемые с матрицей этого узла
+    pub meshes: Vec<usize>,
+    transform: Transform,
+    world: Matrix4x4<f32>,
+}
+
+#[derive(Debug)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+    //Общий родитель корневых узлов, например положение модели в мире
+    root: Transform,
+    dirty: bool,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self { translation, ..Self::identity() }
+    }
+
+    //Сначала масштаб, потом поворот, потом перенос
+    pub fn matrix(&self) -> Matrix4x4<f32> {
+        Matrix4x4::new_translation(self.translation)
+            * self.rotation.to_matrix()
+            * Matrix4x4::new_scale(&[self.scale.x, self.scale.y, self.scale.z, 1.0])
+    }
+}
+
+impl Node {
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    //Актуальна после SceneGraph::update
+    pub fn world_matrix(&self) -> Matrix4x4<f32> {
+        self.world
+    }
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            root: Transform::identity(),
+            dirty: false,
+        }
+    }
+
+    //Родитель должен уже быть в графе
+    pub fn add_node(&mut self, name: &str, transform: Transform, parent: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        match parent {
+            Some(parent) => {
+                assert!(parent < index, "parent node {} doesn't exist", parent);
+                self.nodes[parent].children.push(index);
+            },
+            None => self.roots.push(index),
+        }
+
+        self.nodes.push(Node {
+            name: String::from(name),
+            parent,
+            children: Vec::new(),
+            meshes: Vec::new(),
+            transform,
+            world: Matrix4x4::new_indent(),
+        });
+        self.dirty = true;
+
+        index
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    pub(crate) fn add_mesh(&mut self, node: usize, mesh: usize) {
+        self.nodes[node].meshes.push(mesh);
+    }
+
+    pub fn set_transform(&mut self, node: usize, transform: Transform) {
+        self.nodes[node].transform = transform;
+        self.dirty = true;
+    }
+
+    pub fn root_transform(&self) -> Transform {
+        self.root
+    }
+
+    pub fn set_root_transform(&mut self, transform: Transform) {
+        self.root = transform;
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    //Пересчитывает мировые матрицы, false - ничего не менялось
+    pub fn update(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        let root = self.root.matrix();
+        for index in 0..self.nodes.len() {
+            let parent = self.nodes[index].parent.map_or(root, |parent| self.nodes[parent].world);
+            self.nodes[index].world = parent * self.nodes[index].transform.matrix();
+        }
+        self.dirty = false;
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).length() < EPSILON, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn transform_order() {
+        let transform = Transform {
+            translation: Vector3::new(1.0, 0.0, 0.0),
+            rotation: Quaternion::from_axis_angle(Vector3::unit_y(), FRAC_PI_2),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+        };
+
+        //(0, 0, 1) -> масштаб (0, 0, 2) -> поворот вокруг y (2, 0, 0) -> перенос (3, 0, 0)
+        assert_near(transform.matrix() * Vector3::new(0.0, 0.0, 1.0), Vector3::new(3.0, 0.0, 0.0));
+        assert_near(Transform::identity().matrix() * Vector3::new(1.0, 2.0, 3.0), Vector3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn world_matrices_follow_hierarchy() {
+        let mut scene = SceneGraph::new();
+        let parent = scene.add_node("parent", Transform::from_translation(Vector3::new(0.0, 5.0, 0.0)), None);
+        let child = scene.add_node("child", Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)), Some(parent));
+        let other = scene.add_node("other", Transform::identity(), None);
+
+        assert!(scene.update());
+        assert!(!scene.update());
+        assert_eq!(scene.roots(), &[parent, other]);
+        assert_eq!(scene.node(parent).children, vec![child]);
+        assert_near(scene.node(child).world_matrix() * Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 5.0, 0.0));
+
+        //Поворот родителя уносит потомка, корень сдвигает все узлы
+        scene.set_transform(parent, Transform {
+            rotation: Quaternion::from_axis_angle(Vector3::unit_y(), FRAC_PI_2),
+            ..scene.node(parent).transform()
+        });
+        scene.set_root_transform(Transform::from_translation(Vector3::new(0.0, 0.0, 10.0)));
+        assert!(scene.update());
+
+        assert_near(scene.node(child).world_matrix() * Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 5.0, 9.0));
+        assert_near(scene.node(other).world_matrix() * Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 10.0));
+        assert_eq!(scene.find("child"), Some(child));
+    }
+}
@@ -0,0 +1,426 @@
+/*
+    Карты теней направленных источников и прожекторов. Каждый источник из Lights::shadow_casters
+    рисует глубину сцены в свои слои Common::shadow_map, pbr.wgsl сравнивает с ней глубину
+    фрагмента и сглаживает край PCF. Точечные источники теней не дают, им нужна кубическая карта.
+    Направленный источник получает несколько каскадов: пирамида видимости камеры режется по глубине,
+    каждому куску - своя ортографическая проекция и свой слой, фрагмент берёт каскад по своей глубине.
+    Прожектору хватает одного слоя с перспективой по его конусу.
+*/
+
+use crate::camera::Camera;
+use crate::lights::{Light, LightKind, Lights};
+use crate::vmath::{Aabb, Matrix4x4, Vector3, Vector4};
+
+// Источников с тенью
+pub const MAX_SHADOWS: usize = 4;
+// Каскадов у одного направленного источника
+pub const MAX_CASCADES: usize = 4;
+// Слоёв в карте теней на все источники вместе
+pub const MAX_SHADOW_LAYERS: usize = 8;
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+//Ближняя плоскость прожектора
+const SPOT_NEAR: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    //Сдвиг глубины при записи карты против "shadow acne": constant - в единицах глубины,
+    //slope_scale - по наклону треугольника к источнику. Меняется пересозданием пайплайна
+    pub bias: wgpu::DepthBiasState,
+    //Ядро PCF из (2 * pcf_radius + 1)^2 выборок, 0 - одна выборка
+    pub pcf_radius: u32,
+    //До какого расстояния от камеры направленные источники дают тени
+    pub distance: f32,
+    //Каскадов у направленного источника, от 1 до MAX_CASCADES
+    pub cascades: usize,
+    //Границы каскадов: 0 - равномерно по глубине, 1 - логарифмически, между ними - смесь
+    pub split_lambda: f32,
+    //Подкрасить фрагменты цветом их каскада
+    pub debug_cascades: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+            pcf_radius: 1,
+            distance: 100.0,
+            cascades: MAX_CASCADES,
+            split_lambda: 0.75,
+            debug_cascades: false,
+        }
+    }
+}
+
+// Раскладка совпадает с Shadows в shaders/pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    view_proj: [[[f32; 4]; 4]; MAX_SHADOW_LAYERS],
+    //По источнику с тенью: x - первый слой, y - число слоёв (каскадов)
+    casters: [[u32; 4]; MAX_SHADOWS],
+    //Дальние границы каскадов по глубине основной камеры
+    cascade_splits: [f32; MAX_CASCADES],
+    camera_position: [f32; 3],
+    texel_size: f32,
+    camera_forward: [f32; 3],
+    pcf_radius: u32,
+    count: u32,
+    layers: u32,
+    debug_cascades: u32,
+    _padding: f32,
+}
+
+impl ShadowUniform {
+    //casters - матрицы слоёв каждого источника. Источник, чьи слои уже не влезают, и все после него
+    //остаются без тени
+    pub fn new(casters: &[Vec<Matrix4x4<f32>>], camera: &Camera, settings: &ShadowSettings) -> Self {
+        let mut uniform = Self {
+            view_proj: [Matrix4x4::new_indent().into(); MAX_SHADOW_LAYERS],
+            casters: [[0; 4]; MAX_SHADOWS],
+            cascade_splits: cascade_splits(camera, settings),
+            camera_position: camera.pose().0.into(),
+            texel_size: 1.0 / SHADOW_MAP_SIZE as f32,
+            camera_forward: camera_forward(camera).into(),
+            pcf_radius: settings.pcf_radius,
+            count: 0,
+            layers: 0,
+            debug_cascades: settings.debug_cascades as u32,
+            _padding: 0.0,
+        };
+
+        for (caster, matrices) in casters.iter().take(MAX_SHADOWS).enumerate() {
+            let first = uniform.layers as usize;
+            if first + matrices.len() > MAX_SHADOW_LAYERS {
+                break;
+            }
+
+            for (layer, matrix) in matrices.iter().enumerate() {
+                uniform.view_proj[first + layer] = (*matrix).into();
+            }
+            uniform.casters[caster] = [first as u32, matrices.len() as u32, 0, 0];
+            uniform.layers += matrices.len() as u32;
+            uniform.count += 1;
+        }
+
+        uniform
+    }
+
+    //Источников, получивших слои
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    //Занятых слоёв карты теней
+    pub fn layers(&self) -> usize {
+        self.layers as usize
+    }
+
+    pub fn view_proj(&self, layer: usize) -> Matrix4x4<f32> {
+        self.view_proj[layer].into()
+    }
+}
+
+//Матрицы слоёв каждого источника с тенями по порядку Lights::shadow_casters.
+//casters - границы всего, что отбрасывает тень
+pub fn light_matrices(
+    lights: &Lights,
+    camera: &Camera,
+    settings: &ShadowSettings,
+    casters: Option<Aabb<f32>>,
+) -> Vec<Vec<Matrix4x4<f32>>> {
+    let cascades = settings.cascades.clamp(1, MAX_CASCADES);
+    let splits = cascade_splits(camera, settings);
+    //Каскад от предыдущей границы до своей
+    let slices: Vec<_> = (0..cascades)
+        .map(|cascade| {
+            let near = if cascade == 0 { camera.projection.near } else { splits[cascade - 1] };
+            frustum_corners(camera, near, splits[cascade])
+        })
+        .collect();
+
+    lights.shadow_casters()
+        .map(|light| match light.kind {
+            LightKind::Spot { outer_cone_angle, .. } => vec![spot_view_proj(light, outer_cone_angle, settings.distance)],
+            _ => slices.iter()
+                .map(|corners| match corners {
+                    Some(corners) => cascade_view_proj(light.direction, corners, casters, SHADOW_MAP_SIZE),
+                    None => Matrix4x4::new_indent(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+//Дальние границы каскадов по глубине камеры, последняя - settings.distance.
+//Лишние после settings.cascades тоже равны distance
+pub fn cascade_splits(camera: &Camera, settings: &ShadowSettings) -> [f32; MAX_CASCADES] {
+    let cascades = settings.cascades.clamp(1, MAX_CASCADES);
+    let near = camera.projection.near.max(1e-3);
+    let far = settings.distance.max(near);
+    let lambda = settings.split_lambda.clamp(0.0, 1.0);
+
+    let mut splits = [far; MAX_CASCADES];
+    for (index, split) in splits.iter_mut().enumerate().take(cascades - 1) {
+        let part = (index + 1) as f32 / cascades as f32;
+        let uniform = near + (far - near) * part;
+        let logarithmic = near * (far / near).powf(part);
+        *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+    }
+
+    splits
+}
+
+//Углы куска пирамиды видимости камеры между глубинами near и far: сначала 4 ближних, потом 4 дальних
+pub fn frustum_corners(camera: &Camera, near: f32, far: f32) -> Option<[Vector3<f32>; 8]> {
+    let inverse = camera.view_proj().try_inverse()?;
+    let projection = camera.projection;
+    //Вдоль ребра пирамиды расстояние меняется линейно. Рёбра задаются ближней плоскостью и
+    //второй на конечном расстоянии: дальняя может быть в бесконечности, а NDC глубина
+    //зависит от DepthMode
+    let reference = if projection.far.is_finite() { projection.far } else { projection.near * 2.0 };
+    let t = |distance: f32| (distance.clamp(projection.near, projection.far) - projection.near) / (reference - projection.near);
+    let (t_near, t_far) = (t(near), t(far));
+    let (start_depth, end_depth) = (projection.depth(projection.near), projection.depth(reference));
+
+    let mut corners = [Vector3::zero(); 8];
+    for (index, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].into_iter().enumerate() {
+        let start = (inverse * Vector4::new(x, y, start_depth, 1.0)).perspective_divide();
+        let end = (inverse * Vector4::new(x, y, end_depth, 1.0)).perspective_divide();
+        corners[index] = start.lerp(end, t_near);
+        corners[index + 4] = start.lerp(end, t_far);
+    }
+
+    Some(corners)
+}
+
+//Ортографическая проекция вдоль direction на сферу вокруг corners. Радиус сферы не зависит от
+//поворота камеры, а центр сдвигается целыми текселями карты размера resolution, поэтому
+//при движении камеры тени не мерцают. Ближняя плоскость отодвигается до casters,
+//чтобы тень давали и объекты позади камеры
+pub fn cascade_view_proj(
+    direction: Vector3<f32>,
+    corners: &[Vector3<f32>],
+    casters: Option<Aabb<f32>>,
+    resolution: u32,
+) -> Matrix4x4<f32> {
+    let center = corners.iter().fold(Vector3::zero(), |sum, &corner| sum + corner) / corners.len() as f32;
+    let radius = corners.iter().map(|&corner| (corner - center).length()).fold(0.0, f32::max);
+    //Округление гасит дрожание радиуса от погрешностей float
+    let radius = ((radius * 16.0).ceil() / 16.0).max(1.0 / 16.0);
+
+    //Базис источника без сдвига: сетка текселей привязана к миру, а не к камере
+    let view = light_view(Vector3::zero(), direction);
+    let texel = radius * 2.0 / resolution as f32;
+    let light_center = view * center;
+    let x = (light_center.x / texel).floor() * texel;
+    let y = (light_center.y / texel).floor() * texel;
+
+    let far = light_center.z + radius;
+    let near = casters.map_or(light_center.z - radius, |casters| {
+        casters.transform(view).min.z.min(light_center.z - radius)
+    });
+
+    Matrix4x4::new_orthographic(x - radius, x + radius, y - radius, y + radius, near, far) * view
+}
+
+//Перспектива из позиции прожектора на весь его внешний конус
+pub fn spot_view_proj(light: &Light, outer_cone_angle: f32, distance: f32) -> Matrix4x4<f32> {
+    let far = light.range.unwrap_or(distance).max(SPOT_NEAR * 2.0);
+    let fovy = (outer_cone_angle * 2.0).to_degrees().clamp(1.0, 170.0);
+
+    Matrix4x4::new_perspective(1.0, 1.0, SPOT_NEAR, far, fovy) * light_view(light.position, light.direction)
+}
+
+//Куда смотрит камера: от центра ближней грани пирамиды к центру дальней
+fn camera_forward(camera: &Camera) -> Vector3<f32> {
+    let Some(corners) = frustum_corners(camera, camera.projection.near, camera.projection.near * 2.0) else {
+        return Vector3::unit_z();
+    };
+    let near = corners[..4].iter().fold(Vector3::zero(), |sum, &corner| sum + corner);
+    let far = corners[4..].iter().fold(Vector3::zero(), |sum, &corner| sum + corner);
+
+    (far - near).try_normalize().unwrap_or(Vector3::unit_z())
+}
+
+//new_look_at берёт "вверх" из unit_y, для отвесного света вверх - unit_z
+fn light_view(position: Vector3<f32>, direction: Vector3<f32>) -> Matrix4x4<f32> {
+    let direction = direction.normalize();
+    if direction.y.abs() < 0.99 {
+        return Matrix4x4::new_look_at(position, direction);
+    }
+
+    let right = Vector3::unit_z().cross(direction).normalize();
+    let up = direction.cross(right);
+
+    Matrix4x4::from([
+        [right.x, up.x, direction.x, 0.0],
+        [right.y, up.y, direction.y, 0.0],
+        [right.z, up.z, direction.z, 0.0],
+        [-right.dot(position), -up.dot(position), -direction.dot(position), 1.0],
+    ])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::Projection;
+
+    const EPSILON: f32 = 1e-4;
+
+    //Точка внутри объёма карты: xy в [-1, 1], глубина в [0, 1]
+    fn inside(view_proj: Matrix4x4<f32>, point: Vector3<f32>) -> bool {
+        let clip = (view_proj * point.extend(1.0)).perspective_divide();
+        clip.x.abs() <= 1.0 + EPSILON && clip.y.abs() <= 1.0 + EPSILON
+            && clip.z >= -EPSILON && clip.z <= 1.0 + EPSILON
+    }
+
+    fn test_camera(position: Vector3<f32>) -> Camera {
+        let mut camera = Camera::new(
+            position,
+            Vector3::unit_z(),
+            Projection::new_perspective(60.0, 800, 600, 0.1, 1000.0),
+        );
+        camera.update(instant::Duration::ZERO);
+        camera
+    }
+
+    #[test]
+    fn cascade_fits_camera_slice() {
+        let camera = test_camera(Vector3::new(0.0, 2.0, -5.0));
+
+        //Кусок от 5 до 20, хотя камера видит до 1000
+        let corners = frustum_corners(&camera, 5.0, 20.0).unwrap();
+        let depth = |corner: Vector3<f32>| (camera.view_proj() * corner.extend(1.0)).w;
+        assert!((depth(corners[0]) - 5.0).abs() < 1e-2, "{}", depth(corners[0]));
+        assert!((depth(corners[4]) - 20.0).abs() < 1e-2, "{}", depth(corners[4]));
+
+        //Высоко над пирамидой, ближе к источнику: без casters такая точка в карту не попала бы
+        let center = corners.iter().fold(Vector3::zero(), |sum, &corner| sum + corner) / 8.0;
+        for direction in [Vector3::new(0.4, -1.0, 0.6), -Vector3::unit_y()] {
+            let above = center - direction.normalize() * 100.0;
+            let casters = Aabb::new(above, above);
+
+            assert!(!inside(cascade_view_proj(direction, &corners, None, SHADOW_MAP_SIZE), above));
+            let view_proj = cascade_view_proj(direction, &corners, Some(casters), SHADOW_MAP_SIZE);
+            assert!(corners.iter().all(|&corner| inside(view_proj, corner)));
+            assert!(inside(view_proj, above));
+        }
+    }
+
+    #[test]
+    fn reversed_z_camera_has_same_slices() {
+        let standard = test_camera(Vector3::new(0.0, 2.0, -5.0));
+        let mut reversed = test_camera(Vector3::new(0.0, 2.0, -5.0));
+        reversed.projection = Projection::new_perspective_reversed_z(60.0, 800, 600, 0.1);
+        reversed.update(instant::Duration::ZERO);
+
+        //Дальняя плоскость в бесконечности не мешает ни кускам каскадов, ни направлению взгляда
+        let (expected, corners) = (frustum_corners(&standard, 5.0, 20.0).unwrap(), frustum_corners(&reversed, 5.0, 20.0).unwrap());
+        //Обычная глубина на 20 из 1000 теряет точность float, отсюда допуск
+        for (expected, corner) in expected.into_iter().zip(corners) {
+            assert!((expected - corner).length() < 5e-2, "{:?} {:?}", expected, corner);
+        }
+        assert!((camera_forward(&reversed) - camera_forward(&standard)).length() < 1e-4);
+    }
+
+    #[test]
+    fn cascades_snap_to_texels() {
+        let direction = Vector3::new(0.4, -1.0, 0.6);
+        let resolution = 512;
+        let view_proj = |position: Vector3<f32>, yaw: f32| {
+            let mut camera = test_camera(position);
+            camera.set_pose(position, yaw, 0.0);
+            camera.update(instant::Duration::ZERO);
+            cascade_view_proj(direction, &frustum_corners(&camera, 1.0, 10.0).unwrap(), None, resolution)
+        };
+
+        let first = view_proj(Vector3::new(0.0, 1.0, 0.0), 0.0);
+        for (position, yaw) in [(Vector3::new(0.013, 1.0, 0.002), 0.0), (Vector3::new(-3.7, 1.2, 5.1), 1.3)] {
+            let moved = view_proj(position, yaw);
+
+            //Поворот камеры не меняет размер карты
+            let scale = |matrix: Matrix4x4<f32>| <[[f32; 4]; 4]>::from(matrix)[0][0];
+            assert!((scale(moved) - scale(first)).abs() < 1e-6);
+            //Точка мира сдвигается по карте на целое число текселей
+            let origin = |matrix: Matrix4x4<f32>| (matrix * Vector4::new(0.0, 0.0, 0.0, 1.0)).perspective_divide();
+            let shift = (origin(moved) - origin(first)) * (resolution as f32 / 2.0);
+            assert!((shift.x - shift.x.round()).abs() < 1e-2, "{}", shift.x);
+            assert!((shift.y - shift.y.round()).abs() < 1e-2, "{}", shift.y);
+        }
+    }
+
+    #[test]
+    fn split_blend() {
+        let camera = test_camera(Vector3::zero());
+        let settings = |split_lambda| ShadowSettings { distance: 100.0, cascades: 4, split_lambda, ..Default::default() };
+
+        let uniform = cascade_splits(&camera, &settings(0.0));
+        let logarithmic = cascade_splits(&camera, &settings(1.0));
+        let blended = cascade_splits(&camera, &settings(0.5));
+        for index in 0..3 {
+            assert!((uniform[index] - (0.1 + 99.9 * (index + 1) as f32 / 4.0)).abs() < 1e-3);
+            assert!((logarithmic[index] - 0.1 * 1000f32.powf((index + 1) as f32 / 4.0)).abs() < 1e-3);
+            assert!((blended[index] - (uniform[index] + logarithmic[index]) / 2.0).abs() < 1e-3);
+            assert!(blended[index] < blended[index + 1]);
+        }
+        assert_eq!(blended[3], 100.0);
+
+        //Два каскада: остальные границы упираются в distance
+        let two = cascade_splits(&camera, &ShadowSettings { cascades: 2, ..settings(0.5) });
+        assert!(two[0] < 100.0);
+        assert_eq!(&two[1..], &[100.0; 3]);
+    }
+
+    #[test]
+    fn spot_covers_cone() {
+        let light = Light::spot(
+            Vector3::new(0.0, 5.0, 0.0),
+            -Vector3::unit_y(),
+            Vector3::new(1.0, 1.0, 1.0),
+            10.0,
+            Some(10.0),
+            0.3,
+            0.5,
+        );
+        let view_proj = spot_view_proj(&light, 0.5, 30.0);
+
+        //Край внешнего конуса у самого range ещё в карте, точка за range - уже нет
+        let edge = Vector3::new(0.0, 5.0 - 9.0 * 0.45f32.cos(), 9.0 * 0.45f32.sin());
+        assert!(inside(view_proj, edge));
+        assert!(!inside(view_proj, Vector3::new(0.0, -6.0, 0.0)));
+    }
+
+    #[test]
+    fn shadow_layers_follow_casters() {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        let mut lights = Lights::new(Vector3::zero());
+        lights.add(Light { shadows: true, ..Light::point(Vector3::zero(), white, 1.0, None) });
+        lights.add(Light { shadows: true, ..Light::directional(-Vector3::unit_y(), white, 1.0) });
+        for _ in 0..MAX_SHADOWS {
+            lights.add(Light { shadows: true, ..Light::spot(Vector3::zero(), -Vector3::unit_y(), white, 1.0, None, 0.2, 0.4) });
+        }
+
+        //Точечный источник пропускается, лишний прожектор остаётся без тени
+        assert_eq!(lights.shadow_casters().count(), MAX_SHADOWS);
+        let camera = test_camera(Vector3::zero());
+        let settings = ShadowSettings::default();
+        let matrices = light_matrices(&lights, &camera, &settings, None);
+        assert_eq!(matrices.iter().map(Vec::len).collect::<Vec<_>>(), vec![MAX_CASCADES, 1, 1, 1]);
+
+        let uniform = ShadowUniform::new(&matrices, &camera, &settings);
+        assert_eq!(uniform.count(), MAX_SHADOWS);
+        assert_eq!(uniform.layers(), MAX_CASCADES + 3);
+        assert_eq!(uniform.casters[1], [MAX_CASCADES as u32, 1, 0, 0]);
+
+        //Каскады второго направленного источника уже не влезают в карту
+        let crowded = ShadowUniform::new(&vec![vec![Matrix4x4::new_indent(); MAX_CASCADES]; 3], &camera, &settings);
+        assert_eq!(crowded.count(), MAX_SHADOW_LAYERS / MAX_CASCADES);
+        assert_eq!(std::mem::size_of::<ShadowUniform>(), 64 * MAX_SHADOW_LAYERS + 16 * MAX_SHADOWS + 64);
+    }
+}
@@ -0,0 +1,268 @@
+/*
+    Нормали и касательные для примитивов, в которых их нет.
+    Всё считается в системе координат glTF (до отражения x в model.rs):
+    лицевая сторона треугольника - обход против часовой стрелки.
+    Касательные - MikkTSpace, как того требует спецификация glTF для normal map.
+*/
+
+use std::collections::HashMap;
+
+use crate::vmath::Vector3;
+
+//Нормаль для вырожденных треугольников, у которых нормали нет
+const FALLBACK_NORMAL: [f32; 3] = [0.0, 1.0, 0.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMode {
+    //Нормаль треугольника на каждой его вершине, вершины треугольников не делятся
+    Flat,
+    //Средняя по площади нормаль треугольников вокруг вершины
+    Smooth,
+}
+
+//Неиндексированные вершины, тройка подряд - треугольник
+struct Geometry {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    tangents: Vec<[f32; 4]>,
+}
+
+//Примитив с касательными. Вершина, которая досталась треугольникам с разными касательными
+//(например, на шве отражённой текстуры), разделена на несколько
+#[derive(Debug)]
+pub struct TangentMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+//Нормаль треугольника с длиной, равной удвоенной площади
+fn face_normal(positions: &[[f32; 3]], triangle: &[u32]) -> Vector3<f32> {
+    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| Vector3::from(positions[index as usize]));
+    (b - a).cross(c - a)
+}
+
+fn normalize_or_fallback(normal: Vector3<f32>) -> [f32; 3] {
+    if normal.length() > f32::EPSILON {
+        normal.normalize().into()
+    } else {
+        FALLBACK_NORMAL
+    }
+}
+
+//Дублирует вершины так, чтобы у каждого угла треугольника была своя, индексы становятся 0..n
+pub fn unindex<T: Copy>(attribute: &[T], indices: &[u32]) -> Vec<T> {
+    indices.iter().map(|&index| attribute[index as usize]).collect()
+}
+
+//Для неиндексированных вершин (после unindex): тройка подряд - треугольник
+pub fn flat_normals(positions: &[[f32; 3]]) -> Vec<[f32; 3]> {
+    let indices: Vec<u32> = (0..positions.len() as u32).collect();
+
+    indices.chunks_exact(3)
+        .flat_map(|triangle| [normalize_or_fallback(face_normal(positions, triangle)); 3])
+        .collect()
+}
+
+pub fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let normal = face_normal(positions, triangle);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+
+    normals.into_iter().map(normalize_or_fallback).collect()
+}
+
+//xyz - касательная, w - знак битангенса: bitangent = cross(normal, tangent.xyz) * w.
+//MikkTSpace считает касательную каждого угла треугольника, после чего одинаковые
+//по всем атрибутам углы снова сливаются в вершины.
+//None, если MikkTSpace не справился (например, нет треугольников)
+pub fn tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
+    indices: &[u32],
+) -> Option<TangentMesh> {
+    let mut geometry = Geometry {
+        positions: unindex(positions, indices),
+        normals: unindex(normals, indices),
+        tex_coords: unindex(tex_coords, indices),
+        tangents: vec![[1.0, 0.0, 0.0, 1.0]; indices.len()],
+    };
+
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return None;
+    }
+
+    Some(weld(geometry))
+}
+
+//Углы сравниваются побитово, поэтому сливаются только точные копии
+fn weld(geometry: Geometry) -> TangentMesh {
+    let mut mesh = TangentMesh {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        tex_coords: Vec::new(),
+        tangents: Vec::new(),
+        indices: Vec::with_capacity(geometry.positions.len()),
+    };
+    let mut welded: HashMap<[u32; 12], u32> = HashMap::new();
+
+    for corner in 0..geometry.positions.len() {
+        let (position, normal, tex_coord, tangent) = (
+            geometry.positions[corner],
+            geometry.normals[corner],
+            geometry.tex_coords[corner],
+            geometry.tangents[corner],
+        );
+
+        let mut key = [0; 12];
+        for (bits, value) in key.iter_mut().zip(position.iter().chain(&normal).chain(&tex_coord).chain(&tangent)) {
+            *bits = value.to_bits();
+        }
+
+        let index = *welded.entry(key).or_insert_with(|| {
+            mesh.positions.push(position);
+            mesh.normals.push(normal);
+            mesh.tex_coords.push(tex_coord);
+            mesh.tangents.push(tangent);
+            mesh.positions.len() as u32 - 1
+        });
+        mesh.indices.push(index);
+    }
+
+    mesh
+}
+
+impl Geometry {
+    fn index(&self, face: usize, vert: usize) -> usize {
+        face * 3 + vert
+    }
+}
+
+impl bevy_mikktspace::Geometry for Geometry {
+    fn num_faces(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.index(face, vert)]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.index(face, vert)]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.tex_coords[self.index(face, vert)]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let index = self.index(face, vert);
+        self.tangents[index] = tangent;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    //Квадрат в плоскости xy, смотрит на +z, u вдоль x, v вдоль -y
+    const QUAD_POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+    const QUAD_TEX_COORDS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn assert_near<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+        assert!(
+            actual.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < EPSILON),
+            "{:?} != {:?}", actual, expected
+        );
+    }
+
+    #[test]
+    fn flat_normals_per_triangle() {
+        let positions = unindex(&QUAD_POSITIONS, &QUAD_INDICES);
+        assert_eq!(positions.len(), 6);
+
+        let normals = flat_normals(&positions);
+        assert_eq!(normals.len(), 6);
+        normals.iter().for_each(|&normal| assert_near(normal, [0.0, 0.0, 1.0]));
+
+        //Вырожденный треугольник не даёт NaN
+        assert_eq!(flat_normals(&[[0.0; 3]; 3]), vec![FALLBACK_NORMAL; 3]);
+    }
+
+    #[test]
+    fn smooth_normals_average_faces() {
+        //Два треугольника под прямым углом с общим ребром 0-1: один смотрит на +z, другой на +y
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 0.0, -1.0]];
+        let indices = [0, 1, 2, 0, 1, 3];
+        let normals = smooth_normals(&positions, &indices);
+
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(normals[0], [0.0, diagonal, diagonal]);
+        assert_near(normals[1], [0.0, diagonal, diagonal]);
+        assert_near(normals[2], [0.0, 0.0, 1.0]);
+        assert_near(normals[3], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn tangents_follow_u() {
+        let normals = [[0.0, 0.0, 1.0]; 4];
+        let mesh = tangents(&QUAD_POSITIONS, &normals, &QUAD_TEX_COORDS, &QUAD_INDICES).unwrap();
+        //Углы с одинаковыми касательными снова объединены
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        let tangents = mesh.tangents;
+        //Битангенс cross(n, t) * w смотрит туда, куда растёт v, то есть на -y
+        tangents.iter().for_each(|&tangent| assert_near(tangent, [1.0, 0.0, 0.0, -1.0]));
+
+        //Отражённая по u текстура: касательная разворачивается, битангенс тот же - знак w меняется
+        let mirrored = QUAD_TEX_COORDS.map(|[u, v]| [1.0 - u, v]);
+        let tangents = self::tangents(&QUAD_POSITIONS, &normals, &mirrored, &QUAD_INDICES).unwrap().tangents;
+        tangents.iter().for_each(|&tangent| assert_near(tangent, [-1.0, 0.0, 0.0, 1.0]));
+
+        assert!(self::tangents(&[], &[], &[], &[]).is_none());
+    }
+
+    #[test]
+    fn mirror_seam_splits_shared_vertices() {
+        //Два квадрата с общим ребром 1-4 при x = 1, справа текстура отражена по u.
+        //Вершины ребра общие: у них одинаковые и позиция, и нормаль, и UV
+        let positions = [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [2.0, 1.0, 0.0],
+        ];
+        let tex_coords = [[0.0, 1.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [0.0, 0.0]];
+        let indices = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+        let normals = [[0.0, 0.0, 1.0]; 6];
+
+        let mesh = tangents(&positions, &normals, &tex_coords, &indices).unwrap();
+        //Каждой вершине шва нужны две касательные
+        assert_eq!(mesh.positions.len(), 8);
+        assert_eq!(mesh.indices.len(), indices.len());
+
+        for (corner, &index) in mesh.indices.iter().enumerate() {
+            let index = index as usize;
+            assert_eq!(mesh.positions[index], positions[indices[corner] as usize]);
+            assert_eq!(mesh.tex_coords[index], tex_coords[indices[corner] as usize]);
+
+            let expected = if corner < 6 { [1.0, 0.0, 0.0, -1.0] } else { [-1.0, 0.0, 0.0, 1.0] };
+            assert_near(mesh.tangents[index], expected);
+        }
+    }
+}
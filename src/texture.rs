@@ -1,5 +1,31 @@
+use std::sync::mpsc;
 use image::GenericImageView;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+
+// Как сравнивать и чем очищать глубину, берётся из Projection::depth_mode камеры вида.
+// ReversedZ нужен для ProjectionKind::PerspectiveReversedZ, где ближние точки имеют большую глубину
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DepthMode {
+    #[default]
+    Standard,
+    ReversedZ,
+}
+
+impl DepthMode {
+    pub fn compare_function(self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::ReversedZ => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    pub fn clear_value(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReversedZ => 0.0,
+        }
+    }
+}
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -9,20 +35,81 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // Цель сцены до тональной компрессии: яркость не ограничена единицей
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
     
 
+    //Текстура глубины любого размера, например под окно или вид
     pub fn create_depth_texture(
-        device: &wgpu::Device, 
-        config: &wgpu::SurfaceConfiguration,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
         label: &str
     ) -> Self {
-        //У текстуры должен быть такой же размер как и у поверхности окна.
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
+        Self::create_depth(device, size, wgpu::TextureViewDimension::D2, None, label)
+    }
+
+    //HDR цель того же размера, что и итоговая картинка: в неё рисуется сцена,
+    //тональная компрессия и гистограмма яркости читают её как текстуру
+    pub fn create_hdr_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: &str
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        //Читается textureLoad попиксельно, сэмплер только для полноты Texture
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    //Массив квадратных карт теней, по слою на источник. Сэмплер сравнивает глубину:
+    //1 - точка ближе к источнику, чем записанная в карте, то есть освещена
+    pub fn create_shadow_map(
+        device: &wgpu::Device,
+        size: u32,
+        layers: u32,
+        label: &str
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        };
+
+        //Массив даже из одного слоя, как ждёт texture_depth_2d_array в шейдере
+        Self::create_depth(device, size, wgpu::TextureViewDimension::D2Array, Some(wgpu::CompareFunction::LessEqual), label)
+    }
+
+    fn create_depth(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        view_dimension: wgpu::TextureViewDimension,
+        compare: Option<wgpu::CompareFunction>,
+        label: &str
+    ) -> Self {
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
@@ -36,7 +123,10 @@ impl Texture {
         };
 
         let texture = device.create_texture(&desc);
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
+            ..Default::default()
+        });
         
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor { // 4.
@@ -46,7 +136,7 @@ impl Texture {
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Linear,
                 mipmap_filter: wgpu::FilterMode::Nearest,
-                //compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+                compare, // 5.
                 //lod_min_clamp: -100.0,
                 //lod_max_clamp: 100.0,
                 ..Default::default()
@@ -61,9 +151,32 @@ impl Texture {
         queue: &wgpu::Queue,
         bytes: &[u8], 
         label: &str
+    ) -> Result<Self> {
+        Self::from_bytes_with_format(device, queue, bytes, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    //Srgb формат - для цвета (base color, emissive), Unorm - для данных (нормали, roughness, ...)
+    pub fn from_bytes_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        format: wgpu::TextureFormat,
     ) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;//.fliph();
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image_with_format(device, queue, &img, Some(label), format)
+    }
+
+    //Текстура 1x1 одного цвета, подставляется вместо отсутствующих карт материала
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        label: &str,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image_with_format(device, queue, &img, Some(label), format)
     }
 
     pub fn from_image(
@@ -71,6 +184,17 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>
+    ) -> Result<Self> {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    //format - один из Rgba8 форматов, данные пишутся по 4 байта на пиксель
+    pub fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -88,7 +212,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             }
@@ -125,4 +249,117 @@ impl Texture {
         
         Ok(Self { texture, view, sampler })
     }
-}
\ No newline at end of file
+
+    //Читает текстуру формата Rgba8/Bgra8 с GPU в картинку. У текстуры должен быть COPY_SRC
+    pub fn read_to_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<image::RgbaImage> {
+        let size = texture.size();
+        let bgra = match texture.format() {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(anyhow!("can't read texture of format {:?}", format)),
+        };
+
+        //Строки в буфере должны быть выровнены по COPY_BYTES_PER_ROW_ALIGNMENT
+        let padded_bytes_per_row = padded_bytes_per_row(size.width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            }
+        );
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(size.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let pixels = unpad_rows(&slice.get_mapped_range(), size.width, size.height, bgra);
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(size.width, size.height, pixels)
+            .ok_or_else(|| anyhow!("readback buffer doesn't match texture size"))
+    }
+}
+
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let bytes_per_row = width * 4;
+    bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+//Убирает выравнивание строк и переставляет BGRA в RGBA
+fn unpad_rows(data: &[u8], width: u32, height: u32, bgra: bool) -> Vec<u8> {
+    let bytes_per_row = (width * 4) as usize;
+    let padded_bytes_per_row = padded_bytes_per_row(width) as usize;
+
+    let mut pixels = Vec::with_capacity(bytes_per_row * height as usize);
+    for row in data.chunks(padded_bytes_per_row).take(height as usize) {
+        pixels.extend_from_slice(&row[..bytes_per_row]);
+    }
+
+    if bgra {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    pixels
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(1), 256);
+    }
+
+    #[test]
+    fn unpad_drops_padding_and_swizzles_bgra() {
+        //Две строки по одному пикселю, остальное - выравнивание
+        let mut data = vec![0u8; 512];
+        data[..4].copy_from_slice(&[1, 2, 3, 4]);
+        data[256..260].copy_from_slice(&[5, 6, 7, 8]);
+
+        assert_eq!(unpad_rows(&data, 1, 2, false), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(unpad_rows(&data, 1, 2, true), vec![3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+}
@@ -0,0 +1,218 @@
+/*
+    Виды: камера плюс место, куда она рисует. Вид занимает прямоугольник поверхности
+    (split-screen, картинка в картинке) или рисует в свою offscreen текстуру.
+    Рендерер рисует виды за кадр по порядку, каждый следующий поверх предыдущих.
+*/
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::camera::{Camera, CameraUniform};
+use crate::render::Common;
+use crate::{texture, vmath::Vector3};
+
+//Строго вниз смотреть нельзя: look_at берёт "вверх" из unit_y
+const TOP_DOWN_PITCH: f32 = FRAC_PI_2 - 1e-3;
+
+// Прямоугольник в долях цели рендера, (0, 0) - левый верхний угол
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+// Как собственная камера вида следует за основной
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Follow {
+    //Камерой управляют снаружи
+    None,
+    //Над основной камерой на height, повёрнута так же - миникарта
+    TopDown { height: f32 },
+    //Из той же точки назад, без отражения по горизонтали - камера заднего вида
+    Behind,
+}
+
+pub enum ViewCamera {
+    //Камера рендерера, которой управляет ввод
+    Main,
+    Own { camera: Box<Camera>, follow: Follow },
+}
+
+pub enum ViewTarget {
+    Surface(Viewport),
+    //Своя цель фиксированного размера, на поверхность не выводится
+    Texture(Box<TextureTarget>),
+}
+
+// Сцена рисуется в hdr_texture, в texture попадает уже после тональной компрессии
+pub struct TextureTarget {
+    pub texture: wgpu::Texture,
+    pub depth_texture: texture::Texture,
+    pub hdr_texture: texture::Texture,
+}
+
+pub struct View {
+    pub camera: ViewCamera,
+    pub target: ViewTarget,
+    //Чем заливается прямоугольник вида перед отрисовкой
+    pub clear_color: wgpu::Color,
+    buffer: wgpu::Buffer,
+    pub(crate) bind_group: wgpu::BindGroup,
+}
+
+// Готовые раскладки видов, переключаются по кругу
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewLayout {
+    Single,
+    Minimap,
+    RearView,
+    SplitScreen,
+}
+
+impl Viewport {
+    pub const FULL: Self = Self { x: 0.0, y: 0.0, width: 1.0, height: 1.0 };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    //x, y, ширина, высота в пикселях цели. Не выходит за цель и не бывает пустым
+    pub fn pixels(&self, target_width: u32, target_height: u32) -> (u32, u32, u32, u32) {
+        let to_pixels = |value: f32, size: u32| ((value.clamp(0.0, 1.0) * size as f32).round() as u32).min(size);
+
+        let x = to_pixels(self.x, target_width).min(target_width.saturating_sub(1));
+        let y = to_pixels(self.y, target_height).min(target_height.saturating_sub(1));
+        let width = to_pixels(self.x + self.width, target_width).saturating_sub(x).max(1);
+        let height = to_pixels(self.y + self.height, target_height).saturating_sub(y).max(1);
+
+        (x, y, width, height)
+    }
+}
+
+impl ViewTarget {
+    //Формат должен совпадать с форматом, под который собран TonemapPipeline рендерера
+    pub fn texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, width: u32, height: u32) -> Self {
+        let config = wgpu::SurfaceConfiguration {
+            width,
+            height,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            ..config.clone()
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("view_target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        });
+        let depth_texture = texture::Texture::create_depth_texture(device, width, height, "view_depth_texture");
+        let hdr_texture = texture::Texture::create_hdr_target(device, width, height, "view_hdr_texture");
+
+        ViewTarget::Texture(Box::new(TextureTarget { texture, depth_texture, hdr_texture }))
+    }
+}
+
+impl View {
+    pub fn new(device: &wgpu::Device, common: &Common, camera: ViewCamera, target: ViewTarget) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("view_camera_buffer"),
+            size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = common.bind_group(device, &buffer);
+
+        Self {
+            camera,
+            target,
+            clear_color: wgpu::Color::WHITE,
+            buffer,
+            bind_group,
+        }
+    }
+
+    pub fn with_clear_color(mut self, clear_color: wgpu::Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    //Камера, которой рисуется вид
+    pub fn camera<'a>(&'a self, main: &'a Camera) -> &'a Camera {
+        match &self.camera {
+            ViewCamera::Main => main,
+            ViewCamera::Own { camera, .. } => camera,
+        }
+    }
+
+    //Размер в пикселях, в который рисует вид
+    pub fn size(&self, target_width: u32, target_height: u32) -> (u32, u32) {
+        match &self.target {
+            ViewTarget::Surface(viewport) => {
+                let (_, _, width, height) = viewport.pixels(target_width, target_height);
+                (width, height)
+            },
+            ViewTarget::Texture(target) => (target.texture.width(), target.texture.height()),
+        }
+    }
+
+    //Подгоняет aspect своей камеры под прямоугольник вида. Основную камеру двигает рендерер
+    pub fn resize(&mut self, target_width: u32, target_height: u32) {
+        let (width, height) = self.size(target_width, target_height);
+        if let ViewCamera::Own { camera, .. } = &mut self.camera {
+            camera.resize(width, height);
+        }
+    }
+
+    //Ставит свою камеру за основной и пишет матрицу в буфер вида. Вызывается после update основной камеры
+    pub fn update(&mut self, queue: &wgpu::Queue, main: &Camera) {
+        if let ViewCamera::Own { camera, follow } = &mut self.camera {
+            let (position, yaw, pitch) = main.pose();
+            match *follow {
+                Follow::None => {},
+                Follow::TopDown { height } => camera.set_pose(position + Vector3::unit_y() * height, yaw, TOP_DOWN_PITCH),
+                Follow::Behind => camera.set_pose(position, yaw + PI, -pitch),
+            }
+            camera.update(instant::Duration::ZERO);
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.camera(main).uniform]));
+    }
+}
+
+impl ViewLayout {
+    pub fn next(self) -> Self {
+        match self {
+            ViewLayout::Single => ViewLayout::Minimap,
+            ViewLayout::Minimap => ViewLayout::RearView,
+            ViewLayout::RearView => ViewLayout::SplitScreen,
+            ViewLayout::SplitScreen => ViewLayout::Single,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_pixels() {
+        assert_eq!(Viewport::FULL.pixels(640, 480), (0, 0, 640, 480));
+        //Правая половина нечётной ширины не теряет столбец
+        assert_eq!(Viewport::new(0.5, 0.0, 0.5, 1.0).pixels(641, 480), (321, 0, 320, 480));
+        //Вылезающий за цель прямоугольник обрезается, пустой растягивается до пикселя
+        assert_eq!(Viewport::new(0.75, 0.75, 0.5, 0.5).pixels(100, 100), (75, 75, 25, 25));
+        assert_eq!(Viewport::new(1.0, 0.0, 0.0, 0.0).pixels(100, 100), (99, 0, 1, 1));
+    }
+}
@@ -0,0 +1,443 @@
+use num::{Float, cast};
+
+use crate::vmath::{Matrix4x4, Vector3};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb<T> {
+    pub min: Vector3<T>,
+    pub max: Vector3<T>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sphere<T> {
+    pub center: Vector3<T>,
+    pub radius: T,
+}
+
+// Точки p с normal.dot(p) + d >= 0 лежат с положительной стороны плоскости
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Plane<T> {
+    pub normal: Vector3<T>,
+    pub d: T,
+}
+
+// direction не обязан быть единичным, расстояния t считаются в его длинах
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Ray<T> {
+    pub origin: Vector3<T>,
+    pub direction: Vector3<T>,
+}
+
+// Шесть плоскостей нормалями внутрь: left, right, bottom, top, near, far
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Frustum<T> {
+    pub planes: [Plane<T>; 6],
+}
+
+impl<T: Float> Aabb<T> {
+    pub fn new(min: Vector3<T>, max: Vector3<T>) -> Self {
+        Self { min, max }
+    }
+
+    // None для пустого набора точек
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<T>>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Self::new(first, first), |aabb, point| aabb.include(point)))
+    }
+
+    pub fn include(self, point: Vector3<T>) -> Self {
+        Self::new(self.min.min(point), self.max.max(point))
+    }
+
+    pub fn union(self, other: Aabb<T>) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn center(self) -> Vector3<T> {
+        let half: T = cast(0.5).unwrap();
+        (self.min + self.max) * half
+    }
+
+    // Половина размера по каждой оси
+    pub fn extents(self) -> Vector3<T> {
+        let half: T = cast(0.5).unwrap();
+        (self.max - self.min) * half
+    }
+
+    pub fn contains(self, point: Vector3<T>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x
+            && point.y >= self.min.y && point.y <= self.max.y
+            && point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    pub fn intersects(self, other: Aabb<T>) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    // AABB, описанный вокруг преобразованного бокса (метод Арво):
+    // центр переносится матрицей, полуразмеры - модулем её 3x3 части
+    pub fn transform(self, matrix: Matrix4x4<T>) -> Self {
+        let center = matrix * self.center();
+        let e = self.extents();
+        let m = matrix.data;
+
+        let extents = Vector3::new(
+            m[0][0].abs() * e.x + m[1][0].abs() * e.y + m[2][0].abs() * e.z,
+            m[0][1].abs() * e.x + m[1][1].abs() * e.y + m[2][1].abs() * e.z,
+            m[0][2].abs() * e.x + m[1][2].abs() * e.y + m[2][2].abs() * e.z,
+        );
+
+        Self::new(center - extents, center + extents)
+    }
+}
+
+impl<T: Float> Sphere<T> {
+    pub fn new(center: Vector3<T>, radius: T) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains(self, point: Vector3<T>) -> bool {
+        (point - self.center).dot(point - self.center) <= self.radius * self.radius
+    }
+}
+
+impl<T: Float> Plane<T> {
+    pub fn new(normal: Vector3<T>, d: T) -> Self {
+        Self { normal, d }
+    }
+
+    pub fn from_point_normal(point: Vector3<T>, normal: Vector3<T>) -> Self {
+        let normal = normal.normalize();
+        Self::new(normal, -normal.dot(point))
+    }
+
+    // Со знаком, в длинах нормали
+    pub fn distance(self, point: Vector3<T>) -> T {
+        self.normal.dot(point) + self.d
+    }
+
+    // Вырожденную плоскость (нулевая нормаль) оставляет как есть
+    pub fn normalize(self) -> Self {
+        let length = self.normal.length();
+        if length <= T::epsilon() {
+            return self;
+        }
+
+        Self::new(self.normal / length, self.d / length)
+    }
+}
+
+impl<T: Float> Ray<T> {
+    pub fn new(origin: Vector3<T>, direction: Vector3<T>) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(self, t: T) -> Vector3<T> {
+        self.origin + self.direction * t
+    }
+
+    // Ближайшее t >= 0 пересечения с боксом; 0 если начало луча внутри
+    pub fn intersect_aabb(self, aabb: Aabb<T>) -> Option<T> {
+        let mut t_min = T::zero();
+        let mut t_max = T::infinity();
+
+        for axis in 0..3 {
+            let (origin, direction) = (self.origin[axis], self.direction[axis]);
+            let (min, max) = (aabb.min[axis], aabb.max[axis]);
+
+            //Луч параллелен слою: либо всегда внутри, либо промах
+            if direction == T::zero() {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv = T::one() / direction;
+            let (mut t0, mut t1) = ((min - origin) * inv, (max - origin) * inv);
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+
+    // Möller–Trumbore, треугольник двусторонний. Возвращает t > 0
+    pub fn intersect_triangle(self, a: Vector3<T>, b: Vector3<T>, c: Vector3<T>) -> Option<T> {
+        let epsilon: T = cast(1e-7).unwrap();
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = self.direction.cross(edge2);
+        let det = edge1.dot(p);
+        //Луч в плоскости треугольника или треугольник вырожден
+        if det.abs() < epsilon {
+            return None;
+        }
+
+        let inv_det = T::one() / det;
+        let s = self.origin - a;
+        let u = s.dot(p) * inv_det;
+        if u < T::zero() || u > T::one() {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inv_det;
+        if v < T::zero() || u + v > T::one() {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inv_det;
+        if t > epsilon { Some(t) } else { None }
+    }
+
+    // Ближайшее t >= 0; если начало внутри сферы - точка выхода
+    pub fn intersect_sphere(self, sphere: Sphere<T>) -> Option<T> {
+        let offset = self.origin - sphere.center;
+        let a = self.direction.dot(self.direction);
+        let half_b = offset.dot(self.direction);
+        let c = offset.dot(offset) - sphere.radius * sphere.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < T::zero() || a == T::zero() {
+            return None;
+        }
+
+        let root = discriminant.sqrt();
+        let near = (-half_b - root) / a;
+        let far = (-half_b + root) / a;
+
+        if near >= T::zero() {
+            Some(near)
+        } else if far >= T::zero() {
+            Some(far)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Float> Frustum<T> {
+    // Плоскости из строк view_proj (Gribb–Hartmann) для клипа x, y в [-w, w] и z в [0, w].
+    // Для бесконечной дальней плоскости она получается вырожденной и ничего не отсекает
+    pub fn from_view_proj(view_proj: Matrix4x4<T>) -> Self {
+        let m = view_proj.data;
+        let row = |i: usize| (Vector3::new(m[0][i], m[1][i], m[2][i]), m[3][i]);
+        let plane = |(n, d): (Vector3<T>, T)| Plane::new(n, d).normalize();
+        let add = |(a, ad): (Vector3<T>, T), (b, bd): (Vector3<T>, T)| (a + b, ad + bd);
+        let sub = |(a, ad): (Vector3<T>, T), (b, bd): (Vector3<T>, T)| (a - b, ad - bd);
+
+        Self {
+            planes: [
+                plane(add(row(3), row(0))),
+                plane(sub(row(3), row(0))),
+                plane(add(row(3), row(1))),
+                plane(sub(row(3), row(1))),
+                plane(row(2)),
+                plane(sub(row(3), row(2))),
+            ]
+        }
+    }
+
+    pub fn contains(&self, point: Vector3<T>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(point) >= T::zero())
+    }
+
+    // false только если бокс целиком снаружи одной из плоскостей.
+    // Консервативно: бокс у угла пирамиды может пройти проверку, не пересекая её
+    pub fn intersects_aabb(&self, aabb: Aabb<T>) -> bool {
+        self.planes.iter().all(|plane| {
+            //Вершина бокса, дальше всех продвинутая вдоль нормали
+            let positive = Vector3::new(
+                if plane.normal.x >= T::zero() { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= T::zero() { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= T::zero() { aabb.max.z } else { aabb.min.z },
+            );
+            plane.distance(positive) >= T::zero()
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: Sphere<T>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(sphere.center) >= -sphere.radius)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn v(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3::new(x, y, z)
+    }
+
+    fn unit_box() -> Aabb<f32> {
+        Aabb::new(v(-1.0, -1.0, -1.0), v(1.0, 1.0, 1.0))
+    }
+
+    // Камера в начале координат смотрит вдоль +z, как Camera с нулевыми yaw/pitch
+    fn camera_frustum() -> Frustum<f32> {
+        let view = Matrix4x4::new_look_at(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0));
+        let proj = Matrix4x4::new_perspective(1.0, 1.0, 0.1, 100.0, 90.0);
+        Frustum::from_view_proj(proj * view)
+    }
+
+    #[test]
+    fn aabb_from_points_and_queries() {
+        assert!(Aabb::<f32>::from_points([]).is_none());
+
+        let aabb = Aabb::from_points([v(1.0, -2.0, 3.0), v(-1.0, 4.0, 0.0), v(0.0, 0.0, 5.0)]).unwrap();
+        assert_eq!(aabb, Aabb::new(v(-1.0, -2.0, 0.0), v(1.0, 4.0, 5.0)));
+        assert_eq!(aabb.center(), v(0.0, 1.0, 2.5));
+        assert_eq!(aabb.extents(), v(1.0, 3.0, 2.5));
+        assert!(aabb.contains(v(1.0, 4.0, 5.0)));
+        assert!(!aabb.contains(v(1.1, 0.0, 1.0)));
+
+        //Касание гранью считается пересечением
+        assert!(unit_box().intersects(Aabb::new(v(1.0, 0.0, 0.0), v(2.0, 1.0, 1.0))));
+        assert!(!unit_box().intersects(Aabb::new(v(1.1, 0.0, 0.0), v(2.0, 1.0, 1.0))));
+    }
+
+    #[test]
+    fn aabb_transform() {
+        let moved = unit_box().transform(Matrix4x4::new_translation(v(5.0, 0.0, -2.0)));
+        assert_eq!(moved, Aabb::new(v(4.0, -1.0, -3.0), v(6.0, 1.0, -1.0)));
+
+        //Поворот на 45 градусов вокруг y: по x и z бокс расширяется до sqrt(2)
+        let rotation = crate::vmath::Quaternion::from_axis_angle(Vector3::unit_y(), std::f32::consts::FRAC_PI_4);
+        let rotated = Aabb::new(v(-1.0, -2.0, -1.0), v(1.0, 2.0, 1.0)).transform(rotation.to_matrix());
+        let sqrt2 = 2.0_f32.sqrt();
+        assert!((rotated.max - v(sqrt2, 2.0, sqrt2)).length() < EPSILON);
+        assert!((rotated.min + v(sqrt2, 2.0, sqrt2)).length() < EPSILON);
+    }
+
+    #[test]
+    fn plane_distance() {
+        let plane = Plane::from_point_normal(v(0.0, 2.0, 0.0), v(0.0, 3.0, 0.0));
+        assert_eq!(plane.distance(v(5.0, 5.0, 1.0)), 3.0);
+        assert_eq!(plane.distance(v(0.0, 0.0, 0.0)), -2.0);
+
+        let degenerate = Plane::new(v(0.0, 0.0, 0.0), 1.0).normalize();
+        assert_eq!(degenerate.distance(v(100.0, 100.0, 100.0)), 1.0);
+    }
+
+    #[test]
+    fn ray_aabb() {
+        let ray = Ray::new(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_aabb(unit_box()), Some(4.0));
+
+        //Начало внутри
+        assert_eq!(Ray::new(v(0.0, 0.0, 0.0), v(0.0, 1.0, 0.0)).intersect_aabb(unit_box()), Some(0.0));
+        //Бокс позади
+        assert_eq!(Ray::new(v(5.0, 0.0, 0.0), v(1.0, 0.0, 0.0)).intersect_aabb(unit_box()), None);
+        //Параллельно граням: внутри слоя и снаружи
+        assert_eq!(Ray::new(v(-5.0, 1.0, 1.0), v(1.0, 0.0, 0.0)).intersect_aabb(unit_box()), Some(4.0));
+        assert_eq!(Ray::new(v(-5.0, 1.5, 0.0), v(1.0, 0.0, 0.0)).intersect_aabb(unit_box()), None);
+        //Мимо по диагонали
+        assert_eq!(Ray::new(v(-5.0, 0.0, 0.0), v(1.0, 1.0, 0.0)).intersect_aabb(unit_box()), None);
+        //t в длинах direction
+        assert_eq!(Ray::new(v(-5.0, 0.0, 0.0), v(2.0, 0.0, 0.0)).intersect_aabb(unit_box()), Some(2.0));
+    }
+
+    #[test]
+    fn ray_triangle() {
+        let (a, b, c) = (v(-1.0, -1.0, 0.0), v(1.0, -1.0, 0.0), v(0.0, 1.0, 0.0));
+
+        let t = Ray::new(v(0.0, 0.0, -3.0), v(0.0, 0.0, 1.0)).intersect_triangle(a, b, c);
+        assert!((t.unwrap() - 3.0).abs() < EPSILON);
+
+        //Двусторонний: попадание с обратной стороны
+        let t = Ray::new(v(0.0, 0.0, 3.0), v(0.0, 0.0, -1.0)).intersect_triangle(a, b, c);
+        assert!((t.unwrap() - 3.0).abs() < EPSILON);
+
+        //Мимо, в плоскости треугольника, позади, вырожденный треугольник
+        assert!(Ray::new(v(2.0, 0.0, -3.0), v(0.0, 0.0, 1.0)).intersect_triangle(a, b, c).is_none());
+        assert!(Ray::new(v(-5.0, 0.0, 0.0), v(1.0, 0.0, 0.0)).intersect_triangle(a, b, c).is_none());
+        assert!(Ray::new(v(0.0, 0.0, 3.0), v(0.0, 0.0, 1.0)).intersect_triangle(a, b, c).is_none());
+        assert!(Ray::new(v(0.0, 0.0, -3.0), v(0.0, 0.0, 1.0)).intersect_triangle(a, a, c).is_none());
+
+        //Попадание точно в вершину
+        assert!(Ray::new(v(-1.0, -1.0, -3.0), v(0.0, 0.0, 1.0)).intersect_triangle(a, b, c).is_some());
+    }
+
+    #[test]
+    fn ray_sphere() {
+        let sphere = Sphere::new(v(0.0, 0.0, 5.0), 1.0);
+
+        assert_eq!(Ray::new(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0)).intersect_sphere(sphere), Some(4.0));
+        //Изнутри - точка выхода
+        assert_eq!(Ray::new(v(0.0, 0.0, 5.0), v(0.0, 0.0, 1.0)).intersect_sphere(sphere), Some(1.0));
+        //Касательная
+        assert_eq!(Ray::new(v(1.0, 0.0, 0.0), v(0.0, 0.0, 1.0)).intersect_sphere(sphere), Some(5.0));
+        //Мимо и позади
+        assert_eq!(Ray::new(v(1.5, 0.0, 0.0), v(0.0, 0.0, 1.0)).intersect_sphere(sphere), None);
+        assert_eq!(Ray::new(v(0.0, 0.0, 0.0), v(0.0, 0.0, -1.0)).intersect_sphere(sphere), None);
+        //Нулевое направление
+        assert_eq!(Ray::new(v(0.0, 0.0, 0.0), v(0.0, 0.0, 0.0)).intersect_sphere(sphere), None);
+    }
+
+    #[test]
+    fn frustum_planes() {
+        let frustum = camera_frustum();
+
+        assert!(frustum.contains(v(0.0, 0.0, 1.0)));
+        assert!(frustum.contains(v(0.0, 0.0, 100.0)));
+        assert!(!frustum.contains(v(0.0, 0.0, -1.0)));
+        assert!(!frustum.contains(v(0.0, 0.0, 0.05)));
+        assert!(!frustum.contains(v(0.0, 0.0, 101.0)));
+        //fovy 90: граница по x = z
+        assert!(frustum.contains(v(9.9, 0.0, 10.0)));
+        assert!(!frustum.contains(v(10.1, 0.0, 10.0)));
+        assert!(!frustum.contains(v(0.0, -10.1, 10.0)));
+    }
+
+    #[test]
+    fn frustum_aabb() {
+        let frustum = camera_frustum();
+
+        assert!(frustum.intersects_aabb(Aabb::new(v(-1.0, -1.0, 5.0), v(1.0, 1.0, 6.0))));
+        //Частично внутри
+        assert!(frustum.intersects_aabb(Aabb::new(v(9.0, 0.0, 9.0), v(12.0, 1.0, 10.0))));
+        //Камера внутри бокса
+        assert!(frustum.intersects_aabb(unit_box()));
+        //Позади, сбоку, за дальней плоскостью
+        assert!(!frustum.intersects_aabb(Aabb::new(v(-1.0, -1.0, -6.0), v(1.0, 1.0, -5.0))));
+        assert!(!frustum.intersects_aabb(Aabb::new(v(20.0, -1.0, 5.0), v(21.0, 1.0, 6.0))));
+        assert!(!frustum.intersects_aabb(Aabb::new(v(-1.0, -1.0, 200.0), v(1.0, 1.0, 201.0))));
+    }
+
+    #[test]
+    fn frustum_sphere() {
+        let frustum = camera_frustum();
+
+        assert!(frustum.intersects_sphere(Sphere::new(v(0.0, 0.0, 10.0), 1.0)));
+        //Центр снаружи, но сфера задевает плоскость
+        assert!(frustum.intersects_sphere(Sphere::new(v(11.0, 0.0, 10.0), 1.0)));
+        assert!(!frustum.intersects_sphere(Sphere::new(v(13.0, 0.0, 10.0), 1.0)));
+        assert!(!frustum.intersects_sphere(Sphere::new(v(0.0, 0.0, -5.0), 1.0)));
+    }
+
+    #[test]
+    fn infinite_far_plane_does_not_cull() {
+        let view = Matrix4x4::new_look_at(v(0.0, 0.0, 0.0), v(0.0, 0.0, 1.0));
+        let proj = Matrix4x4::new_perspective_reversed_z(1.0, 1.0, 0.1, 90.0);
+        let frustum = Frustum::from_view_proj(proj * view);
+
+        assert!(frustum.contains(v(0.0, 0.0, 1e6)));
+        assert!(!frustum.contains(v(0.0, 0.0, 0.05)));
+    }
+}
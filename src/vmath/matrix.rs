@@ -2,9 +2,11 @@
     Col-major square matrix
 */
 
-use std::{fmt::Debug, ops::Mul};
+use std::{cmp::Ordering, fmt::Debug, ops::Mul};
 
-use num::{Float};
+use num::{Float, cast};
+
+use crate::vmath::matrix4x4;
 
 #[derive(Copy, Clone)]
 pub struct SquareMatrix<T: Float, const S: usize> {
@@ -41,6 +43,131 @@ impl<T: Float + Default + Debug, const S: usize> SquareMatrix<T, S> {
         scale_matrix
     }
 
+    pub fn transpose(&self) -> Self {
+        let mut result = Self::new();
+        for i in 0..S {
+            for j in 0..S {
+                result.data[i][j] = self.data[j][i];
+            }
+        }
+
+        result
+    }
+
+    pub fn determinant(&self) -> T {
+        if S == 4 {
+            return matrix4x4::determinant(&to_array(&self.data));
+        }
+
+        let (lu, _, odd) = self.lu_decompose();
+        let det = (0..S).fold(T::one(), |det, i| det * lu[i][i]);
+
+        if odd { -det } else { det }
+    }
+
+    // None если матрица вырождена
+    pub fn try_inverse(&self) -> Option<Self> {
+        if S == 4 {
+            return matrix4x4::try_inverse(&to_array(&self.data))
+                .map(|inverse| Self::new_from_array(to_array(&inverse)));
+        }
+
+        self.lu_inverse()
+    }
+
+    fn lu_inverse(&self) -> Option<Self> {
+        let (lu, permutation, _) = self.lu_decompose();
+        let threshold = self.max_abs() * T::epsilon() * cast(S).unwrap();
+        if (0..S).any(|i| lu[i][i].abs() <= threshold) {
+            return None;
+        }
+
+        //Решаем L*U*x = P*e_col для каждого столбца единичной матрицы
+        let mut inverse = Self::new();
+        for col in 0..S {
+            let mut x = [T::zero(); S];
+            for i in 0..S {
+                let mut sum = if permutation[i] == col { T::one() } else { T::zero() };
+                for k in 0..i {
+                    sum = sum - lu[i][k] * x[k];
+                }
+                x[i] = sum;
+            }
+            for i in (0..S).rev() {
+                let mut sum = x[i];
+                for k in i + 1..S {
+                    sum = sum - lu[i][k] * x[k];
+                }
+                x[i] = sum / lu[i][i];
+            }
+            for (row, value) in inverse.data.iter_mut().zip(x) {
+                row[col] = value;
+            }
+        }
+
+        Some(inverse)
+    }
+
+    // LU разложение с частичным выбором ведущего элемента: P*A = L*U.
+    // L (без единичной диагонали) и U хранятся в одной матрице,
+    // permutation[i] - исходная строка, odd - нечётное число перестановок.
+    // data рассматривается построчно: для транспонированной матрицы результат тот же,
+    // так что порядок хранения на определитель и обратную матрицу не влияет
+    fn lu_decompose(&self) -> ([[T; S]; S], [usize; S], bool) {
+        let mut lu = self.data;
+        let mut permutation = [0; S];
+        for (i, row) in permutation.iter_mut().enumerate() {
+            *row = i;
+        }
+        let mut odd = false;
+
+        for k in 0..S {
+            let pivot = (k..S)
+                .max_by(|a, b| lu[*a][k].abs().partial_cmp(&lu[*b][k].abs()).unwrap_or(Ordering::Equal))
+                .unwrap();
+            if pivot != k {
+                lu.swap(pivot, k);
+                permutation.swap(pivot, k);
+                odd = !odd;
+            }
+
+            //Нулевой столбец: определитель 0, исключать нечего
+            if lu[k][k] == T::zero() {
+                continue;
+            }
+
+            //Строки ниже ведущей, ведущая только читается
+            let (upper, lower) = lu.split_at_mut(k + 1);
+            let pivot_row = &upper[k];
+            for row in lower {
+                let factor = row[k] / pivot_row[k];
+                row[k] = factor;
+                for (value, &pivot) in row[k + 1..].iter_mut().zip(&pivot_row[k + 1..]) {
+                    *value = *value - factor * pivot;
+                }
+            }
+        }
+
+        (lu, permutation, odd)
+    }
+
+    fn max_abs(&self) -> T {
+        self.data.iter()
+            .flat_map(|col| col.iter())
+            .fold(T::zero(), |max, value| max.max(value.abs()))
+    }
+}
+
+// Копирует [[T; S]; S] в [[T; N]; N] при S == N
+fn to_array<T: Float, const S: usize, const N: usize>(data: &[[T; S]; S]) -> [[T; N]; N] {
+    let mut result = [[T::zero(); N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            result[i][j] = data[i][j];
+        }
+    }
+
+    result
 }
 
 impl<T, const S: usize> Mul<SquareMatrix<T, S>> for SquareMatrix<T, S>
@@ -68,3 +195,112 @@ impl<T: Float + Debug, const S: usize> Debug for SquareMatrix<T, S> {
         self.data[..].fmt(f)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn assert_matrix_eq<const S: usize>(a: &SquareMatrix<f64, S>, b: &SquareMatrix<f64, S>) {
+        for i in 0..S {
+            for j in 0..S {
+                assert!((a.data[i][j] - b.data[i][j]).abs() < EPSILON, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let m = SquareMatrix::new_from_array([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        let expected = SquareMatrix::new_from_array([[1.0, 4.0, 7.0], [2.0, 5.0, 8.0], [3.0, 6.0, 9.0]]);
+
+        assert_matrix_eq(&m.transpose(), &expected);
+        assert_matrix_eq(&m.transpose().transpose(), &m);
+    }
+
+    #[test]
+    fn determinant_known_values() {
+        let m = SquareMatrix::new_from_array([[4.0, 7.0], [2.0, 6.0]]);
+        assert!((m.determinant() - 10.0).abs() < EPSILON);
+
+        let m = SquareMatrix::new_from_array([[1.0, 2.0, 3.0], [0.0, 1.0, 4.0], [5.0, 6.0, 0.0]]);
+        assert!((m.determinant() - 1.0).abs() < EPSILON);
+
+        let m = SquareMatrix::new_from_array([
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 3.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0, 0.0],
+            [1.0, 2.0, 3.0, 1.0],
+        ]);
+        assert!((m.determinant() - 24.0).abs() < EPSILON);
+
+        //Перестановка строк меняет знак
+        let m = SquareMatrix::new_from_array([[0.0, 1.0], [1.0, 0.0]]);
+        assert!((m.determinant() + 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn inverse_known_values() {
+        let m = SquareMatrix::new_from_array([[4.0, 7.0], [2.0, 6.0]]);
+        let expected = SquareMatrix::new_from_array([[0.6, -0.7], [-0.2, 0.4]]);
+        assert_matrix_eq(&m.try_inverse().unwrap(), &expected);
+
+        let m = SquareMatrix::new_from_array([[1.0, 2.0, 3.0], [0.0, 1.0, 4.0], [5.0, 6.0, 0.0]]);
+        let expected = SquareMatrix::new_from_array([[-24.0, 18.0, 5.0], [20.0, -15.0, -4.0], [-5.0, 4.0, 1.0]]);
+        assert_matrix_eq(&m.try_inverse().unwrap(), &expected);
+
+        //Без выбора ведущего элемента деление на ноль
+        let m = SquareMatrix::new_from_array([[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 2.0]]);
+        let expected = SquareMatrix::new_from_array([[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.5]]);
+        assert_matrix_eq(&m.try_inverse().unwrap(), &expected);
+    }
+
+    #[test]
+    fn inverse_4x4_matches_lu() {
+        let m = SquareMatrix::new_from_array([
+            [2.0, 1.0, 0.0, 3.0],
+            [0.0, 1.0, 4.0, 1.0],
+            [5.0, 0.0, 1.0, 2.0],
+            [1.0, 3.0, 2.0, 1.0],
+        ]);
+        let inverse = m.try_inverse().unwrap();
+
+        assert_matrix_eq(&inverse, &m.lu_inverse().unwrap());
+        assert_matrix_eq(&(m * inverse), &SquareMatrix::new_indent());
+        assert_matrix_eq(&(inverse * m), &SquareMatrix::new_indent());
+
+        let (lu, _, odd) = m.lu_decompose();
+        let lu_det = (0..4).fold(1.0, |det, i| det * lu[i][i]) * if odd { -1.0 } else { 1.0 };
+        assert!((m.determinant() - lu_det).abs() < EPSILON);
+    }
+
+    #[test]
+    fn inverse_of_transform() {
+        let translation = crate::vmath::Matrix4x4::new_translation(crate::vmath::Vector3::new(1.0, -2.0, 3.0));
+        let expected = crate::vmath::Matrix4x4::new_translation(crate::vmath::Vector3::new(-1.0, 2.0, -3.0));
+        assert_matrix_eq(&translation.try_inverse().unwrap(), &expected);
+
+        //Маленький масштаб не должен считаться вырожденным
+        let scale = SquareMatrix::new_scale(&[1e-3, 1e-3, 1e-3, 1.0]);
+        assert_matrix_eq(&scale.try_inverse().unwrap(), &SquareMatrix::new_scale(&[1e3, 1e3, 1e3, 1.0]));
+    }
+
+    #[test]
+    fn singular_has_no_inverse() {
+        let m = SquareMatrix::new_from_array([[1.0, 2.0], [2.0, 4.0]]);
+        assert!(m.try_inverse().is_none());
+        assert!(m.determinant().abs() < EPSILON);
+
+        let m = SquareMatrix::new_from_array([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        assert!(m.try_inverse().is_none());
+
+        let m: SquareMatrix<f64, 4> = SquareMatrix::new_scale(&[1.0, 1.0, 0.0, 1.0]);
+        assert!(m.try_inverse().is_none());
+        assert!(m.lu_inverse().is_none());
+        assert_eq!(m.determinant(), 0.0);
+
+        assert!(SquareMatrix::<f64, 3>::new().try_inverse().is_none());
+    }
+}
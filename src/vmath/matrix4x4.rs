@@ -1,7 +1,7 @@
 use std::{fmt::Debug, ops::Mul};
 use num::{Float, cast};
 
-use crate::vmath::{SquareMatrix, Vector3};
+use crate::vmath::{SquareMatrix, Vector3, Vector4};
 
 
 pub type Matrix4x4<T> = SquareMatrix<T, 4>;
@@ -20,6 +20,37 @@ impl<T: Float + Default + Debug + std::ops::AddAssign> Matrix4x4<T> {
         ].into()
     }
 
+    // Левосторонняя, как и new_perspective: z из [near, far] переходит в [0, 1]
+    pub fn new_orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Self {
+        let two: T = cast(2).unwrap();
+        [
+            [two / (right - left), T::zero(), T::zero(), T::zero()],
+            [T::zero(), two / (top - bottom), T::zero(), T::zero()],
+            [T::zero(), T::zero(), T::one() / (far - near), T::zero()],
+            [
+                -(right + left) / (right - left),
+                -(top + bottom) / (top - bottom),
+                -near / (far - near),
+                T::one(),
+            ],
+        ].into()
+    }
+
+    // Перспектива с обратным z и бесконечной дальней плоскостью: near переходит в 1,
+    // бесконечность в 0. Точность float лучше распределяется по глубине,
+    // сравнивать глубину нужно с DepthMode::ReversedZ
+    pub fn new_perspective_reversed_z(width: T, height: T, near: T, fovy: T) -> Self {
+        let aspect = width / height;
+        let two: T = cast(2).unwrap();
+        let focal_lenght: T = T::one() / (fovy / two).to_radians().tan();
+        [
+            [focal_lenght / aspect, T::zero(), T::zero(), T::zero()],
+            [T::zero(), focal_lenght, T::zero(), T::zero()],
+            [T::zero(), T::zero(), T::zero(), T::one()],
+            [T::zero(), T::zero(), near, T::zero()],
+        ].into()
+    }
+
     pub fn new_translation(translation: Vector3<T>) -> Self {
         let mut translation_matrix = Self::new_indent();
 
@@ -84,6 +115,19 @@ impl<T: Float> Mul<Vector3<T>> for Matrix4x4<T> {
     }
 }
 
+impl<T: Float> Mul<Vector4<T>> for Matrix4x4<T> {
+    type Output = Vector4<T>;
+    fn mul(self, rhs: Vector4<T>) -> Self::Output {
+        let d = self.data;
+        Vector4::new(
+            d[0][0] * rhs.x + d[1][0] * rhs.y + d[2][0] * rhs.z + d[3][0] * rhs.w,
+            d[0][1] * rhs.x + d[1][1] * rhs.y + d[2][1] * rhs.z + d[3][1] * rhs.w,
+            d[0][2] * rhs.x + d[1][2] * rhs.y + d[2][2] * rhs.z + d[3][2] * rhs.w,
+            d[0][3] * rhs.x + d[1][3] * rhs.y + d[2][3] * rhs.z + d[3][3] * rhs.w,
+        )
+    }
+}
+
 impl<T: Float> From<Matrix4x4<T>> for [[T; 4]; 4] {
     fn from(matrix: Matrix4x4<T>) -> Self {
         matrix.data
@@ -94,4 +138,131 @@ impl<T: Float> From<[[T; 4]; 4]> for Matrix4x4<T> {
     fn from(array: [[T; 4]; 4]) -> Self {
         Self { data: array }
     }
-}
\ No newline at end of file
+}
+
+// Замкнутые формулы для 4x4 через 2x2 миноры, их использует SquareMatrix при S == 4.
+// Как и в LU, data рассматривается построчно
+fn minors<T: Float>(m: &[[T; 4]; 4]) -> ([T; 6], [T; 6]) {
+    let s = [
+        m[0][0] * m[1][1] - m[1][0] * m[0][1],
+        m[0][0] * m[1][2] - m[1][0] * m[0][2],
+        m[0][0] * m[1][3] - m[1][0] * m[0][3],
+        m[0][1] * m[1][2] - m[1][1] * m[0][2],
+        m[0][1] * m[1][3] - m[1][1] * m[0][3],
+        m[0][2] * m[1][3] - m[1][2] * m[0][3],
+    ];
+    let c = [
+        m[2][0] * m[3][1] - m[3][0] * m[2][1],
+        m[2][0] * m[3][2] - m[3][0] * m[2][2],
+        m[2][0] * m[3][3] - m[3][0] * m[2][3],
+        m[2][1] * m[3][2] - m[3][1] * m[2][2],
+        m[2][1] * m[3][3] - m[3][1] * m[2][3],
+        m[2][2] * m[3][3] - m[3][2] * m[2][3],
+    ];
+
+    (s, c)
+}
+
+pub(super) fn determinant<T: Float>(m: &[[T; 4]; 4]) -> T {
+    let (s, c) = minors(m);
+    s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+}
+
+pub(super) fn try_inverse<T: Float>(m: &[[T; 4]; 4]) -> Option<[[T; 4]; 4]> {
+    let (s, c) = minors(m);
+    let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+
+    //Порог зависит от масштаба элементов, иначе мелкие матрицы считались бы вырожденными
+    let max_abs = m.iter().flat_map(|row| row.iter()).fold(T::zero(), |max, value| max.max(value.abs()));
+    if det.abs() <= max_abs.powi(4) * T::epsilon() * cast(4).unwrap() {
+        return None;
+    }
+
+    let inv_det = T::one() / det;
+    let r = [
+        [
+            m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3],
+            -m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3],
+            m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3],
+            -m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3],
+        ],
+        [
+            -m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1],
+            m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1],
+            -m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1],
+            m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1],
+        ],
+        [
+            m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0],
+            -m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0],
+            m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0],
+            -m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0],
+        ],
+        [
+            -m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0],
+            m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0],
+            -m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0],
+            m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0],
+        ],
+    ];
+
+    Some(r.map(|row| row.map(|value| value * inv_det)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    // Точка в NDC после перспективного деления
+    fn project(matrix: Matrix4x4<f32>, x: f32, y: f32, z: f32) -> Vector3<f32> {
+        (matrix * Vector4::new(x, y, z, 1.0)).perspective_divide()
+    }
+
+    fn assert_vector_eq(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).length() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn perspective_maps_near_far_to_unit_depth() {
+        let proj = Matrix4x4::new_perspective(1.0, 1.0, 0.1, 100.0, 90.0);
+
+        assert_vector_eq(project(proj, 0.0, 0.0, 0.1), Vector3::new(0.0, 0.0, 0.0));
+        assert_vector_eq(project(proj, 0.0, 0.0, 100.0), Vector3::new(0.0, 0.0, 1.0));
+        //При fovy 90 граница экрана проходит по x = z
+        assert_vector_eq(project(proj, 10.0, -10.0, 10.0).xy().extend(0.0), Vector3::new(1.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn orthographic_maps_box_to_clip_volume() {
+        let proj = Matrix4x4::new_orthographic(-2.0, 6.0, -1.0, 3.0, 1.0, 11.0);
+
+        assert_vector_eq(project(proj, -2.0, -1.0, 1.0), Vector3::new(-1.0, -1.0, 0.0));
+        assert_vector_eq(project(proj, 6.0, 3.0, 11.0), Vector3::new(1.0, 1.0, 1.0));
+        assert_vector_eq(project(proj, 2.0, 1.0, 6.0), Vector3::new(0.0, 0.0, 0.5));
+
+        //w не меняется - параллельная проекция
+        let clip = proj * Vector4::new(5.0, 2.0, 7.0, 1.0);
+        assert_eq!(clip.w, 1.0);
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one_and_infinity_to_zero() {
+        let proj = Matrix4x4::new_perspective_reversed_z(16.0, 9.0, 0.1, 60.0);
+
+        assert_vector_eq(project(proj, 0.0, 0.0, 0.1), Vector3::new(0.0, 0.0, 1.0));
+        assert!(project(proj, 0.0, 0.0, 1e7).z < 1e-7);
+
+        //Дальше - меньше, поэтому сравнение Greater
+        assert!(project(proj, 0.0, 0.0, 10.0).z > project(proj, 0.0, 0.0, 20.0).z);
+
+        //xy совпадают с обычной перспективой
+        let standard = Matrix4x4::new_perspective(16.0, 9.0, 0.1, 1000.0, 60.0);
+        assert_vector_eq(
+            project(proj, 3.0, -2.0, 5.0).xy().extend(0.0),
+            project(standard, 3.0, -2.0, 5.0).xy().extend(0.0),
+        );
+    }
+}
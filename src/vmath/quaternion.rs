@@ -0,0 +1,404 @@
+use std::ops::{Mul, Neg};
+use num::{Float, cast};
+
+use crate::vmath::{Matrix4x4, Vector3};
+
+/*
+    Единичный кватернион описывает поворот: (x, y, z) - векторная часть, w - скалярная.
+    Произведение a * b - сначала поворот b, потом a.
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quaternion<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T: Float> Quaternion<T> {
+    pub const fn new(x: T, y: T, z: T, w: T) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(T::zero(), T::zero(), T::zero(), T::one())
+    }
+
+    pub fn from_axis_angle(axis: Vector3<T>, angle: T) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / cast(2).unwrap()).sin_cos();
+
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    // Ось и угол в [0, 2pi]; для тождественного поворота ось - unit_x
+    pub fn to_axis_angle(self) -> (Vector3<T>, T) {
+        let q = self.normalize();
+        let two: T = cast(2).unwrap();
+        //sin(angle / 2) по длине векторной части точнее, чем через acos(w) на малых углах
+        let sin = (q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        let angle = two * sin.atan2(q.w);
+
+        if sin <= T::epsilon() {
+            return (Vector3::unit_x(), angle);
+        }
+
+        (Vector3::new(q.x / sin, q.y / sin, q.z / sin), angle)
+    }
+
+    // Углы Эйлера в порядке YXZ: yaw вокруг Y, pitch вокруг X, roll вокруг Z.
+    // Поворот unit_z даёт то же направление, что и Camera для тех же yaw и pitch
+    pub fn from_euler(yaw: T, pitch: T, roll: T) -> Self {
+        let y = Self::from_axis_angle(Vector3::unit_y(), yaw);
+        let x = Self::from_axis_angle(Vector3::unit_x(), pitch);
+        let z = Self::from_axis_angle(Vector3::unit_z(), roll);
+
+        y * x * z
+    }
+
+    // (yaw, pitch, roll), pitch в [-pi/2, pi/2]. При pitch = +-pi/2 roll считается нулевым
+    pub fn to_euler(self) -> (T, T, T) {
+        let m = self.rotation_elements();
+        let sin_pitch = -m[1][2];
+
+        if sin_pitch.abs() >= T::one() - cast::<f64, T>(1e-6).unwrap() {
+            let pitch = T::from(std::f64::consts::FRAC_PI_2).unwrap() * sin_pitch.signum();
+            return ((-m[2][0]).atan2(m[0][0]), pitch, T::zero());
+        }
+
+        (m[0][2].atan2(m[2][2]), sin_pitch.asin(), m[1][0].atan2(m[1][1]))
+    }
+
+    pub fn dot(self, other: Quaternion<T>) -> T {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(self) -> T {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Self {
+        self * (T::one() / self.length())
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    // None для нулевого кватерниона
+    pub fn try_inverse(self) -> Option<Self> {
+        let length_squared = self.dot(self);
+        if length_squared <= T::epsilon() {
+            return None;
+        }
+
+        Some(self.conjugate() * (T::one() / length_squared))
+    }
+
+    pub fn rotate(self, v: Vector3<T>) -> Vector3<T> {
+        //v' = v + 2w(q x v) + 2q x (q x v), q - векторная часть
+        let q = Vector3::new(self.x, self.y, self.z);
+        let two: T = cast(2).unwrap();
+        let t = q.cross(v) * two;
+
+        v + t * self.w + q.cross(t)
+    }
+
+    // Линейная интерполяция с нормализацией, идёт по кратчайшему пути
+    pub fn nlerp(self, other: Quaternion<T>, t: T) -> Self {
+        let other = if self.dot(other) < T::zero() { -other } else { other };
+
+        Self::new(
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+            self.z + (other.z - self.z) * t,
+            self.w + (other.w - self.w) * t,
+        ).normalize()
+    }
+
+    // Сферическая интерполяция с постоянной угловой скоростью, по кратчайшему пути
+    pub fn slerp(self, other: Quaternion<T>, t: T) -> Self {
+        let mut cos = self.dot(other);
+        let other = if cos < T::zero() {
+            cos = -cos;
+            -other
+        } else {
+            other
+        };
+
+        //Почти совпадающие повороты: sin(theta) -> 0, хватает nlerp
+        if cos > cast(0.9995).unwrap() {
+            return self.nlerp(other, t);
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let a = ((T::one() - t) * theta).sin() / sin;
+        let b = (t * theta).sin() / sin;
+
+        Self::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+    }
+
+    pub fn from_matrix(matrix: Matrix4x4<T>) -> Self {
+        //m[row][col], в Matrix4x4 data[col][row]
+        let m = |row: usize, col: usize| matrix.data[col][row];
+        let one = T::one();
+        let two: T = cast(2).unwrap();
+        let trace = m(0, 0) + m(1, 1) + m(2, 2);
+
+        let q = if trace > T::zero() {
+            let s = (trace + one).sqrt() * two;
+            Self::new((m(2, 1) - m(1, 2)) / s, (m(0, 2) - m(2, 0)) / s, (m(1, 0) - m(0, 1)) / s, s / cast(4).unwrap())
+        } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s = (one + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * two;
+            Self::new(s / cast(4).unwrap(), (m(0, 1) + m(1, 0)) / s, (m(0, 2) + m(2, 0)) / s, (m(2, 1) - m(1, 2)) / s)
+        } else if m(1, 1) > m(2, 2) {
+            let s = (one + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * two;
+            Self::new((m(0, 1) + m(1, 0)) / s, s / cast(4).unwrap(), (m(1, 2) + m(2, 1)) / s, (m(0, 2) - m(2, 0)) / s)
+        } else {
+            let s = (one + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * two;
+            Self::new((m(0, 2) + m(2, 0)) / s, (m(1, 2) + m(2, 1)) / s, s / cast(4).unwrap(), (m(1, 0) - m(0, 1)) / s)
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_matrix(self) -> Matrix4x4<T> {
+        let m = self.rotation_elements();
+        let zero = T::zero();
+
+        [
+            [m[0][0], m[1][0], m[2][0], zero],
+            [m[0][1], m[1][1], m[2][1], zero],
+            [m[0][2], m[1][2], m[2][2], zero],
+            [zero, zero, zero, T::one()],
+        ].into()
+    }
+
+    // Матрица поворота 3x3 построчно: m[row][col]
+    fn rotation_elements(self) -> [[T; 3]; 3] {
+        let Self { x, y, z, w } = self.normalize();
+        let one = T::one();
+        let two: T = cast(2).unwrap();
+
+        [
+            [one - two * (y * y + z * z), two * (x * y - z * w), two * (x * z + y * w)],
+            [two * (x * y + z * w), one - two * (x * x + z * z), two * (y * z - x * w)],
+            [two * (x * z - y * w), two * (y * z + x * w), one - two * (x * x + y * y)],
+        ]
+    }
+}
+
+impl<T: Float> Mul<Quaternion<T>> for Quaternion<T> {
+    type Output = Quaternion<T>;
+    fn mul(self, rhs: Quaternion<T>) -> Self::Output {
+        Self::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl<T: Float> Mul<T> for Quaternion<T> {
+    type Output = Quaternion<T>;
+    fn mul(self, rhs: T) -> Self::Output {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+    }
+}
+
+impl<T: Float> Mul<Vector3<T>> for Quaternion<T> {
+    type Output = Vector3<T>;
+    fn mul(self, rhs: Vector3<T>) -> Self::Output {
+        self.rotate(rhs)
+    }
+}
+
+impl<T: Float> Neg for Quaternion<T> {
+    type Output = Quaternion<T>;
+    fn neg(self) -> Self::Output {
+        Self::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl<T: Float> From<Quaternion<T>> for Matrix4x4<T> {
+    fn from(quaternion: Quaternion<T>) -> Self {
+        quaternion.to_matrix()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+    const SAMPLES: usize = 1000;
+
+    // Детерминированный генератор для property-тестов
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+
+        fn vector(&mut self) -> Vector3<f32> {
+            Vector3::new(self.range(-1.0, 1.0), self.range(-1.0, 1.0), self.range(-1.0, 1.0))
+        }
+
+        fn axis(&mut self) -> Vector3<f32> {
+            loop {
+                let v = self.vector();
+                if v.dot(v) > 0.01 {
+                    return v.normalize();
+                }
+            }
+        }
+
+        fn rotation(&mut self) -> Quaternion<f32> {
+            Quaternion::from_axis_angle(self.axis(), self.range(-PI, PI))
+        }
+    }
+
+    fn assert_vector_eq(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).dot(a - b).sqrt() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    // q и -q - один и тот же поворот
+    fn assert_rotation_eq(a: Quaternion<f32>, b: Quaternion<f32>) {
+        assert!(1.0 - a.dot(b).abs() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotates_around_axes() {
+        let q = Quaternion::from_axis_angle(Vector3::unit_y(), FRAC_PI_2);
+        assert_vector_eq(q * Vector3::unit_z(), Vector3::unit_x());
+
+        let q = Quaternion::from_axis_angle(Vector3::unit_z(), FRAC_PI_2);
+        assert_vector_eq(q * Vector3::unit_x(), Vector3::unit_y());
+    }
+
+    #[test]
+    fn euler_matches_camera_direction() {
+        let mut rng = Lcg(1);
+        for _ in 0..SAMPLES {
+            let (yaw, pitch) = (rng.range(-PI, PI), rng.range(-FRAC_PI_2, FRAC_PI_2));
+            let (yaw_sin, yaw_cos) = yaw.sin_cos();
+            let (pitch_sin, pitch_cos) = pitch.sin_cos();
+
+            let q = Quaternion::from_euler(yaw, pitch, 0.0);
+            assert_vector_eq(
+                q * Vector3::unit_z(),
+                Vector3::new(yaw_sin * pitch_cos, -pitch_sin, pitch_cos * yaw_cos),
+            );
+        }
+    }
+
+    #[test]
+    fn product_composes_rotations() {
+        let mut rng = Lcg(2);
+        for _ in 0..SAMPLES {
+            let (a, b, v) = (rng.rotation(), rng.rotation(), rng.vector());
+            assert_vector_eq((a * b) * v, a * (b * v));
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_rotation() {
+        let mut rng = Lcg(3);
+        for _ in 0..SAMPLES {
+            let q = rng.rotation() * rng.range(0.5, 2.0);
+            let v = rng.vector();
+            let inverse = q.try_inverse().unwrap();
+
+            assert_rotation_eq(q * inverse, Quaternion::identity());
+            assert_vector_eq(q.normalize().conjugate() * (q.normalize() * v), v);
+        }
+
+        assert!(Quaternion::new(0.0, 0.0, 0.0, 0.0).try_inverse().is_none());
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let mut rng = Lcg(4);
+        for _ in 0..SAMPLES {
+            let (q, v) = (rng.rotation(), rng.vector());
+            let matrix = q.to_matrix();
+
+            assert_vector_eq(matrix * v, q * v);
+            assert_rotation_eq(Quaternion::from_matrix(matrix), q);
+        }
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let mut rng = Lcg(5);
+        for _ in 0..SAMPLES {
+            let (axis, angle) = (rng.axis(), rng.range(0.01, PI));
+            let (result_axis, result_angle) = Quaternion::from_axis_angle(axis, angle).to_axis_angle();
+
+            assert_vector_eq(result_axis, axis);
+            assert!((result_angle - angle).abs() < EPSILON * 10.0);
+        }
+
+        let (_, angle) = Quaternion::<f32>::identity().to_axis_angle();
+        assert_eq!(angle, 0.0);
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let mut rng = Lcg(6);
+        for _ in 0..SAMPLES {
+            let q = Quaternion::from_euler(rng.range(-PI, PI), rng.range(-1.5, 1.5), rng.range(-PI, PI));
+            let (yaw, pitch, roll) = q.to_euler();
+
+            assert_rotation_eq(Quaternion::from_euler(yaw, pitch, roll), q);
+        }
+
+        //Gimbal lock: yaw и roll неразличимы, но поворот должен сохраниться
+        let q = Quaternion::from_euler(0.3, FRAC_PI_2, 0.2);
+        let (yaw, pitch, roll) = q.to_euler();
+        assert_vector_eq(Quaternion::from_euler(yaw, pitch, roll) * Vector3::unit_x(), q * Vector3::unit_x());
+    }
+
+    #[test]
+    fn slerp_has_constant_angular_speed() {
+        let mut rng = Lcg(7);
+        for _ in 0..SAMPLES {
+            let (a, b) = (rng.rotation(), rng.rotation());
+            let t = rng.next();
+            let (_, total) = (a.conjugate() * b).to_axis_angle();
+            let total = if total > PI { 2.0 * PI - total } else { total };
+
+            let result = a.slerp(b, t);
+            assert!((result.length() - 1.0).abs() < EPSILON);
+            assert_rotation_eq(a.slerp(b, 0.0), a);
+            assert_rotation_eq(a.slerp(b, 1.0), b);
+
+            let (_, partial) = (a.conjugate() * result).to_axis_angle();
+            let partial = if partial > PI { 2.0 * PI - partial } else { partial };
+            assert!((partial - total * t).abs() < 1e-3, "{} != {}", partial, total * t);
+        }
+    }
+
+    #[test]
+    fn nlerp_takes_shortest_path() {
+        let a = Quaternion::from_axis_angle(Vector3::unit_y(), 0.1);
+        let b = -Quaternion::from_axis_angle(Vector3::unit_y(), 0.3);
+
+        assert_rotation_eq(a.nlerp(b, 0.5), Quaternion::from_axis_angle(Vector3::unit_y(), 0.2));
+    }
+}
@@ -1,6 +1,12 @@
-use std::ops::{Sub, Add, Mul};
+use std::ops::{Sub, Add, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign, Index, IndexMut};
 use num::Float;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Vector2<T> {
+    pub x: T,
+    pub y: T,
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Vector3<T> {
     pub x: T,
@@ -8,28 +14,231 @@ pub struct Vector3<T> {
     pub z: T,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Vector4<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+// Общие для Vector2/3/4 операции, поля перечисляются покомпонентно
+macro_rules! impl_vector {
+    ($Vector:ident, $n:expr, $($field:ident),+) => {
+        impl<T: Float> $Vector<T> {
+            pub fn zero() -> Self {
+                Self { $($field: T::zero()),+ }
+            }
+
+            pub fn dot(self, other: $Vector<T>) -> T {
+                T::zero() $(+ self.$field * other.$field)+
+            }
+
+            pub fn length(self) -> T {
+                self.dot(self).sqrt()
+            }
+
+            pub fn distance(self, other: $Vector<T>) -> T {
+                (other - self).length()
+            }
+
+            pub fn normalize(self) -> Self {
+                self / self.length()
+            }
+
+            // None для нулевого (или почти нулевого) вектора, вместо деления на ноль
+            pub fn try_normalize(self) -> Option<Self> {
+                let length = self.length();
+                if length <= T::epsilon() {
+                    None
+                } else {
+                    Some(self / length)
+                }
+            }
+
+            pub fn lerp(self, other: $Vector<T>, t: T) -> Self {
+                self + (other - self) * t
+            }
+
+            pub fn min(self, other: $Vector<T>) -> Self {
+                Self { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(self, other: $Vector<T>) -> Self {
+                Self { $($field: self.$field.max(other.$field)),+ }
+            }
+
+            pub fn clamp(self, min: $Vector<T>, max: $Vector<T>) -> Self {
+                self.max(min).min(max)
+            }
+
+            // Отражение относительно плоскости с единичной нормалью normal
+            pub fn reflect(self, normal: $Vector<T>) -> Self {
+                let two = T::one() + T::one();
+                self - normal * (two * self.dot(normal))
+            }
+
+            // Преломление единичного вектора, eta - отношение коэффициентов преломления.
+            // None при полном внутреннем отражении
+            pub fn refract(self, normal: $Vector<T>, eta: T) -> Option<Self> {
+                let cos = self.dot(normal);
+                let k = T::one() - eta * eta * (T::one() - cos * cos);
+                if k < T::zero() {
+                    return None;
+                }
+
+                Some(self * eta - normal * (eta * cos + k.sqrt()))
+            }
+        }
+
+        impl<T: Float> Add<$Vector<T>> for $Vector<T> {
+            type Output = $Vector<T>;
+            fn add(self, rhs: $Vector<T>) -> Self::Output {
+                Self { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl<T: Float> Sub<$Vector<T>> for $Vector<T> {
+            type Output = $Vector<T>;
+            fn sub(self, rhs: $Vector<T>) -> Self::Output {
+                Self { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        impl<T: Float> Mul<T> for $Vector<T> {
+            type Output = $Vector<T>;
+            fn mul(self, rhs: T) -> Self::Output {
+                Self { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        // Покомпонентное умножение
+        impl<T: Float> Mul<$Vector<T>> for $Vector<T> {
+            type Output = $Vector<T>;
+            fn mul(self, rhs: $Vector<T>) -> Self::Output {
+                Self { $($field: self.$field * rhs.$field),+ }
+            }
+        }
+
+        impl<T: Float> Div<T> for $Vector<T> {
+            type Output = $Vector<T>;
+            fn div(self, rhs: T) -> Self::Output {
+                Self { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl<T: Float> Neg for $Vector<T> {
+            type Output = $Vector<T>;
+            fn neg(self) -> Self::Output {
+                Self { $($field: -self.$field),+ }
+            }
+        }
+
+        impl<T: Float> AddAssign<$Vector<T>> for $Vector<T> {
+            fn add_assign(&mut self, rhs: $Vector<T>) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl<T: Float> SubAssign<$Vector<T>> for $Vector<T> {
+            fn sub_assign(&mut self, rhs: $Vector<T>) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl<T: Float> MulAssign<T> for $Vector<T> {
+            fn mul_assign(&mut self, rhs: T) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl<T: Float> MulAssign<$Vector<T>> for $Vector<T> {
+            fn mul_assign(&mut self, rhs: $Vector<T>) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl<T: Float> DivAssign<T> for $Vector<T> {
+            fn div_assign(&mut self, rhs: T) {
+                *self = *self / rhs;
+            }
+        }
+
+        impl<T> Index<usize> for $Vector<T> {
+            type Output = T;
+            fn index(&self, index: usize) -> &T {
+                let fields = [$(&self.$field),+];
+                match fields.get(index) {
+                    Some(field) => field,
+                    None => panic!("index {} out of range for {}", index, stringify!($Vector)),
+                }
+            }
+        }
+
+        impl<T> IndexMut<usize> for $Vector<T> {
+            fn index_mut(&mut self, index: usize) -> &mut T {
+                let fields = [$(&mut self.$field),+];
+                match fields.into_iter().nth(index) {
+                    Some(field) => field,
+                    None => panic!("index {} out of range for {}", index, stringify!($Vector)),
+                }
+            }
+        }
+
+        impl<T: Copy> From<[T; $n]> for $Vector<T> {
+            fn from(array: [T; $n]) -> Self {
+                let [$($field),+] = array;
+                Self { $($field),+ }
+            }
+        }
+
+        impl<T> From<$Vector<T>> for [T; $n] {
+            fn from(vector: $Vector<T>) -> Self {
+                [$(vector.$field),+]
+            }
+        }
+    };
+}
+
+impl_vector!(Vector2, 2, x, y);
+impl_vector!(Vector3, 3, x, y, z);
+impl_vector!(Vector4, 4, x, y, z, w);
+
+impl<T: Float> Vector2<T> {
+    pub const fn new(x: T, y: T) -> Self {
+        Self { x, y }
+    }
+
+    pub fn unit_x() -> Self {
+        Self::new(T::one(), T::zero())
+    }
+
+    pub fn unit_y() -> Self {
+        Self::new(T::zero(), T::one())
+    }
+
+    pub fn yx(self) -> Self {
+        Self::new(self.y, self.x)
+    }
+
+    pub fn extend(self, z: T) -> Vector3<T> {
+        Vector3::new(self.x, self.y, z)
+    }
+}
+
 impl<T: Float> Vector3<T> {
     pub const fn new(x: T, y: T, z: T) -> Self {
         Self { x, y, z }
     }
 
-    pub fn normalize(self) -> Self {
-        let length = (self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
-        Self { x: self.x / length, y: self.y / length, z: self.z / length }
-    }
-
     pub fn cross(self, other: Vector3<T>) -> Self {
         Self::new(
             (self.y * other.z) - (self.z * other.y),
-            (self.z * other.x) - (self.x * other.z), 
+            (self.z * other.x) - (self.x * other.z),
             (self.x * other.y) - (self.y * other.x),
         )
     }
 
-    pub fn dot(self, other: Vector3<T>) -> T {
-        self.x * other.x + self.y * other.y + self.z * other.z
-    }
-
     pub fn unit_y() -> Self {
         Self::new(T::zero(), T::one(), T::zero())
     }
@@ -41,40 +250,217 @@ impl<T: Float> Vector3<T> {
     pub fn unit_z() -> Self {
         Self::new(T::zero(), T::zero(), T::one())
     }
+
+    pub fn xy(self) -> Vector2<T> {
+        Vector2::new(self.x, self.y)
+    }
+
+    pub fn xz(self) -> Vector2<T> {
+        Vector2::new(self.x, self.z)
+    }
+
+    pub fn yz(self) -> Vector2<T> {
+        Vector2::new(self.y, self.z)
+    }
+
+    pub fn zyx(self) -> Self {
+        Self::new(self.z, self.y, self.x)
+    }
+
+    pub fn extend(self, w: T) -> Vector4<T> {
+        Vector4::new(self.x, self.y, self.z, w)
+    }
 }
 
-impl<T: Float> Sub<Vector3<T>> for Vector3<T> {
-    type Output = Vector3<T>;
-    fn sub(self, rhs: Vector3<T>) -> Self::Output {
-        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+impl<T: Float> Vector4<T> {
+    pub const fn new(x: T, y: T, z: T, w: T) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn unit_x() -> Self {
+        Self::new(T::one(), T::zero(), T::zero(), T::zero())
+    }
+
+    pub fn unit_y() -> Self {
+        Self::new(T::zero(), T::one(), T::zero(), T::zero())
+    }
+
+    pub fn unit_z() -> Self {
+        Self::new(T::zero(), T::zero(), T::one(), T::zero())
+    }
+
+    pub fn unit_w() -> Self {
+        Self::new(T::zero(), T::zero(), T::zero(), T::one())
+    }
+
+    pub fn xy(self) -> Vector2<T> {
+        Vector2::new(self.x, self.y)
+    }
+
+    pub fn xyz(self) -> Vector3<T> {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    // Перспективное деление: xyz / w
+    pub fn perspective_divide(self) -> Vector3<T> {
+        self.xyz() / self.w
     }
 }
 
-impl<T: Float> Add<Vector3<T>> for Vector3<T> {
-    type Output = Vector3<T>;
-    fn add(self, rhs: Vector3<T>) -> Self::Output {
-        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+impl<T> From<(T, T)> for Vector2<T> {
+    fn from((x, y): (T, T)) -> Self {
+        Self { x, y }
     }
 }
 
-impl<T: Float> Mul<T> for Vector3<T> {
-    type Output = Vector3<T>;
-    fn mul(self, rhs: T) -> Self::Output {
-        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+impl<T> From<Vector2<T>> for (T, T) {
+    fn from(vector: Vector2<T>) -> Self {
+        (vector.x, vector.y)
     }
 }
 
+impl<T> From<(T, T, T)> for Vector3<T> {
+    fn from((x, y, z): (T, T, T)) -> Self {
+        Self { x, y, z }
+    }
+}
 
-#[derive(Clone, Copy, Debug)]
-pub struct Vector4<T> {
-    pub x: T,
-    pub y: T,
-    pub z: T,
-    pub w: T,
+impl<T> From<Vector3<T>> for (T, T, T) {
+    fn from(vector: Vector3<T>) -> Self {
+        (vector.x, vector.y, vector.z)
+    }
 }
 
-impl<T> Vector4<T> {
-    pub fn new(x: T, y: T, z: T, w: T) -> Self {
+impl<T> From<(T, T, T, T)> for Vector4<T> {
+    fn from((x, y, z, w): (T, T, T, T)) -> Self {
         Self { x, y, z, w }
     }
-}
\ No newline at end of file
+}
+
+impl<T> From<Vector4<T>> for (T, T, T, T) {
+    fn from(vector: Vector4<T>) -> Self {
+        (vector.x, vector.y, vector.z, vector.w)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    #[test]
+    fn arithmetic() {
+        let a = Vector3::new(1.0, 2.0, 3.0);
+        let b = Vector3::new(4.0, -5.0, 6.0);
+
+        assert_eq!(a + b, Vector3::new(5.0, -3.0, 9.0));
+        assert_eq!(a - b, Vector3::new(-3.0, 7.0, -3.0));
+        assert_eq!(a * b, Vector3::new(4.0, -10.0, 18.0));
+        assert_eq!(a * 2.0, Vector3::new(2.0, 4.0, 6.0));
+        assert_eq!(b / 2.0, Vector3::new(2.0, -2.5, 3.0));
+        assert_eq!(-a, Vector3::new(-1.0, -2.0, -3.0));
+        assert_eq!(a.dot(b), 12.0);
+        assert_eq!(Vector3::<f32>::unit_x().cross(Vector3::unit_y()), Vector3::unit_z());
+
+        let mut c = a;
+        c += b;
+        c -= a;
+        c *= 2.0;
+        c /= 4.0;
+        c *= Vector3::new(2.0, 2.0, 2.0);
+        assert_eq!(c, b);
+    }
+
+    #[test]
+    fn length_distance_lerp() {
+        assert_eq!(Vector2::new(3.0, 4.0).length(), 5.0);
+        assert_eq!(Vector3::new(1.0, 1.0, 1.0).distance(Vector3::new(1.0, 4.0, 5.0)), 5.0);
+        assert_eq!(
+            Vector4::new(0.0, 0.0, 0.0, 0.0).lerp(Vector4::new(2.0, 4.0, 6.0, 8.0), 0.25),
+            Vector4::new(0.5, 1.0, 1.5, 2.0)
+        );
+    }
+
+    #[test]
+    fn normalize_zero_safe() {
+        assert!(Vector3::<f32>::zero().try_normalize().is_none());
+        assert!(Vector2::new(1e-9_f32, 0.0).try_normalize().is_none());
+
+        let n = Vector3::new(0.0, 3.0, 4.0).try_normalize().unwrap();
+        assert!((n.length() - 1.0).abs() < EPSILON);
+        assert_eq!(n, Vector3::new(0.0, 3.0, 4.0).normalize());
+    }
+
+    #[test]
+    fn min_max_clamp() {
+        let a = Vector3::new(1.0, 5.0, -2.0);
+        let b = Vector3::new(3.0, 0.0, -4.0);
+
+        assert_eq!(a.min(b), Vector3::new(1.0, 0.0, -4.0));
+        assert_eq!(a.max(b), Vector3::new(3.0, 5.0, -2.0));
+        assert_eq!(
+            Vector3::new(-1.0, 0.5, 2.0).clamp(Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)),
+            Vector3::new(0.0, 0.5, 1.0)
+        );
+    }
+
+    #[test]
+    fn reflect_refract() {
+        let normal = Vector3::unit_y();
+        let incoming = Vector3::new(1.0, -1.0, 0.0_f32).normalize();
+
+        let reflected = incoming.reflect(normal);
+        assert!((reflected - Vector3::new(1.0, 1.0, 0.0).normalize()).length() < EPSILON);
+
+        //eta = 1 - луч проходит не меняя направления
+        let refracted = incoming.refract(normal, 1.0).unwrap();
+        assert!((refracted - incoming).length() < EPSILON);
+
+        //Из плотной среды под углом 45 градусов - полное внутреннее отражение
+        assert!(incoming.refract(normal, 1.5).is_none());
+
+        //Закон Снеллиуса: sin(out) = eta * sin(in)
+        let refracted = incoming.refract(normal, 0.5).unwrap();
+        let sin_in = incoming.cross(-normal).length();
+        let sin_out = refracted.cross(-normal).length();
+        assert!((sin_out - 0.5 * sin_in).abs() < EPSILON);
+    }
+
+    #[test]
+    fn indexing_and_conversions() {
+        let mut v = Vector4::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(v[2], 3.0);
+        v[3] = 8.0;
+        assert_eq!(v.w, 8.0);
+
+        let array: [f32; 4] = v.into();
+        assert_eq!(array, [1.0, 2.0, 3.0, 8.0]);
+        assert_eq!(Vector4::from(array), v);
+
+        let tuple: (f32, f32, f32) = Vector3::new(1.0, 2.0, 3.0).into();
+        assert_eq!(tuple, (1.0, 2.0, 3.0));
+        assert_eq!(Vector2::from((5.0, 6.0)), Vector2::new(5.0, 6.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_range() {
+        let v = Vector2::new(1.0, 2.0);
+        let _ = v[2];
+    }
+
+    #[test]
+    fn swizzles() {
+        let v = Vector4::new(1.0, 2.0, 3.0, 2.0);
+
+        assert_eq!(v.xy(), Vector2::new(1.0, 2.0));
+        assert_eq!(v.xyz(), Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(v.xyz().xz(), Vector2::new(1.0, 3.0));
+        assert_eq!(v.xyz().yz().yx(), Vector2::new(3.0, 2.0));
+        assert_eq!(v.xyz().zyx(), Vector3::new(3.0, 2.0, 1.0));
+        assert_eq!(v.perspective_divide(), Vector3::new(0.5, 1.0, 1.5));
+        assert_eq!(Vector2::new(1.0, 2.0).extend(3.0).extend(4.0), Vector4::new(1.0, 2.0, 3.0, 4.0));
+    }
+}